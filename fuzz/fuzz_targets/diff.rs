@@ -0,0 +1,20 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use proxy::protocol::rpc::eth::{parse_hex, ServerRpc, ServerSideJob};
+use proxy::util::hex_to_int;
+
+// 任务难度及区块高度字段
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        let _ = hex_to_int(s);
+        let _ = parse_hex(s);
+
+        let job = ServerSideJob {
+            id: 0,
+            jsonrpc: "2.0".into(),
+            result: vec![s.to_string(); 4],
+        };
+        let _ = job.get_diff();
+    }
+});
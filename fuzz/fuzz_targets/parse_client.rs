@@ -0,0 +1,18 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use proxy::client::parse_client;
+use proxy::protocol::rpc::eth::ClientRpc;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(buf) = std::str::from_utf8(data) {
+        if let Some(mut rpc) = parse_client(buf) {
+            let _ = rpc.get_id();
+            let _ = rpc.get_job_id();
+            let _ = rpc.get_wallet();
+            let _ = rpc.get_password();
+            let _ = rpc.get_worker_name();
+            let _ = rpc.get_submit_hashrate();
+        }
+    }
+});
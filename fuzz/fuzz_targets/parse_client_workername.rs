@@ -0,0 +1,20 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use proxy::client::{auth::split_user, parse_client_workername};
+use proxy::protocol::rpc::eth::ClientRpc;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(buf) = std::str::from_utf8(data) {
+        if let Some(mut rpc) = parse_client_workername(buf) {
+            let _ = rpc.get_id();
+            let _ = rpc.get_job_id();
+            if let Some(user) = rpc.get_wallet() {
+                let _ = split_user(&user);
+            }
+            let _ = rpc.get_password();
+            let _ = rpc.get_worker_name();
+            let _ = rpc.get_submit_hashrate();
+        }
+    }
+});
@@ -0,0 +1,29 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use proxy::protocol::rpc::eth::*;
+
+// 矿池返回的数据按代理的解析顺序逐个尝试
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut rpc) = serde_json::from_slice::<ServerJobsWithHeight>(data) {
+        let _ = rpc.get_diff();
+        let _ = rpc.get_job_id();
+        rpc.set_diff("0x1".into());
+    }
+    if let Ok(mut rpc) = serde_json::from_slice::<ServerSideJob>(data) {
+        let _ = rpc.get_diff();
+        let _ = rpc.get_job_id();
+        rpc.set_diff("0x1".into());
+        let _ = rpc.get_diff();
+    }
+    if let Ok(mut rpc) = serde_json::from_slice::<Server>(data) {
+        let _ = rpc.get_diff();
+        let _ = rpc.get_job_id();
+        rpc.set_diff("0x1".into());
+    }
+    let _ = serde_json::from_slice::<ServerId1>(data);
+    let _ = serde_json::from_slice::<ServerNotify>(data);
+    let _ = serde_json::from_slice::<ServerRootErrorValue>(data);
+    handle_error(0, data);
+    handle_error_for_worker(&"fuzz".to_string(), data);
+});
@@ -0,0 +1,213 @@
+pub mod server;
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+
+use crate::{
+    client::control::{LedgerEntry, SessionInfo},
+    state::{alias::WorkerAlias, WorkerSnapshot},
+};
+
+// 管理接口的请求。每行一个 JSON 对象，cmd 字段为命令名
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    // 矿机列表及统计
+    Workers,
+    // 矿池及连通性
+    Pools,
+    // 断开矿机。钱包.矿机名、矿机名或矿机地址
+    Kick {
+        worker: String,
+    },
+    // 排空监听端口 tcp ssl encrypt
+    Drain {
+        listener: String,
+    },
+    // 恢复监听端口接受新连接
+    Resume {
+        listener: String,
+    },
+    // 矿池组 tcp ssl 的新连接优先使用 pool。pool 为空时恢复配置顺序
+    UsePool {
+        group: String,
+        pool: String,
+    },
+    // 修改日志等级。level 为空时只查询
+    LogLevel {
+        #[serde(default)]
+        level: String,
+    },
+    // 抽水账本
+    Ledger,
+    // 按分组汇总的矿机统计
+    Groups,
+    // 矿机别名规则
+    Aliases,
+    // 添加或替换矿机别名规则。alias location group 都为空时删除
+    SetAlias {
+        #[serde(flatten)]
+        alias: WorkerAlias,
+    },
+    // IP 黑白名单及封禁列表
+    Acl,
+}
+
+// 管理接口的响应。每个请求返回一行
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub data: Value,
+}
+
+impl Response {
+    pub fn ok(data: Value) -> Self {
+        Self {
+            ok: true,
+            error: None,
+            data,
+        }
+    }
+
+    pub fn error(e: &anyhow::Error) -> Self {
+        Self {
+            ok: false,
+            error: Some(format!("{:#}", e)),
+            data: Value::Null,
+        }
+    }
+}
+
+// workers 命令的一行。矿机统计每分钟随会话上报更新
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerRow {
+    #[serde(flatten)]
+    pub worker: WorkerSnapshot,
+    // 当前连接。矿机下线后为空
+    #[serde(default)]
+    pub sessions: Vec<SessionInfo>,
+}
+
+// pools 命令的一行
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolRow {
+    // tcp ssl share
+    pub group: String,
+    pub address: String,
+    // 新连接首先尝试的矿池
+    pub current: bool,
+    // 通过 proxyctl use-pool 指定
+    pub preferred: bool,
+    pub reachable: bool,
+    // 建立 TCP 连接的耗时
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pools {
+    pub pools: Vec<PoolRow>,
+    // 排空中的监听端口
+    pub draining: Vec<String>,
+}
+
+pub type Ledger = BTreeMap<String, LedgerEntry>;
+
+// 一个监听端口的黑白名单。多个网段以逗号分隔
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AclRule {
+    pub listener: String,
+    pub allow: String,
+    pub deny: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BanRow {
+    pub ip: String,
+    // 剩余封禁秒数
+    pub remaining_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Acl {
+    pub rules: Vec<AclRule>,
+    pub bans: Vec<BanRow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AliasList {
+    // 通过管理接口设置，重启后失效
+    pub runtime: Vec<WorkerAlias>,
+    // 配置文件 worker_aliases
+    pub config: Vec<WorkerAlias>,
+}
+
+// 发送一个请求并等待响应
+pub async fn request(path: &str, req: &Request) -> Result<Value> {
+    let stream = UnixStream::connect(path)
+        .await
+        .map_err(|e| anyhow!("无法连接管理接口 {}: {}", path, e))?;
+    let (r, mut w) = stream.into_split();
+    let mut line = serde_json::to_vec(req)?;
+    line.push(b'\n');
+    w.write_all(&line).await?;
+
+    let mut reply = String::new();
+    if BufReader::new(r).read_line(&mut reply).await? == 0 {
+        bail!("管理接口断开连接");
+    }
+    let res: Response = serde_json::from_str(&reply)?;
+    match res.error {
+        Some(e) if !res.ok => bail!(e),
+        _ => Ok(res.data),
+    }
+}
+
+// 发送请求并把响应解析为 T
+pub async fn query<T: DeserializeOwned>(path: &str, req: &Request) -> Result<T> {
+    Ok(serde_json::from_value(request(path, req).await?)?)
+}
+
+#[test]
+fn test_request_json() {
+    let req: Request = serde_json::from_str(r#"{"cmd":"kick","worker":"rig1"}"#).unwrap();
+    assert_eq!(
+        req,
+        Request::Kick {
+            worker: "rig1".into()
+        }
+    );
+    let req: Request = serde_json::from_str(r#"{"cmd":"log_level"}"#).unwrap();
+    assert_eq!(req, Request::LogLevel { level: "".into() });
+    assert_eq!(
+        serde_json::to_string(&Request::UsePool {
+            group: "tcp".into(),
+            pool: "a:4444".into()
+        })
+        .unwrap(),
+        r#"{"cmd":"use_pool","group":"tcp","pool":"a:4444"}"#
+    );
+    let req: Request =
+        serde_json::from_str(r#"{"cmd":"set_alias","worker":"rig1","group":"room1"}"#).unwrap();
+    assert_eq!(
+        req,
+        Request::SetAlias {
+            alias: WorkerAlias {
+                worker: "rig1".into(),
+                group: "room1".into(),
+                ..Default::default()
+            }
+        }
+    );
+    assert!(serde_json::from_str::<Request>(r#"{"cmd":"reboot"}"#).is_err());
+}
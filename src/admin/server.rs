@@ -0,0 +1,460 @@
+use std::{
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::{anyhow, bail, Result};
+use log::{info, warn};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{mpsc, oneshot},
+};
+
+use super::{Acl, AclRule, AliasList, BanRow, PoolRow, Pools, Request, Response, WorkerRow};
+use crate::{
+    client::{
+        acl::{IpFilter, Listener},
+        control::Control,
+        shutdown::{self, Shutdown},
+        SSL, TCP,
+    },
+    state::{
+        alias::{self, Aliases},
+        Worker, WorkerSnapshot,
+    },
+    util::{config::Settings, logger},
+};
+
+// 向统计任务索取当前的矿机列表
+pub type WorkersQuery = mpsc::Sender<oneshot::Sender<Vec<Worker>>>;
+
+// 管理接口处理请求需要的状态
+pub struct Admin {
+    pub control: Arc<Control>,
+    pub config: Settings,
+    pub aliases: Arc<Aliases>,
+    pub ip_filter: Arc<IpFilter>,
+    pub workers: WorkersQuery,
+    // 管理接口修改的日志等级。运行时为 logger::LEVEL
+    pub log_level: &'static logger::Level,
+}
+
+// 在 path 上提供管理接口，直到停机排空结束。socket 文件只允许当前用户访问
+pub async fn serve(path: &str, admin: Arc<Admin>, shutdown: Arc<Shutdown>) -> Result<()> {
+    // 平滑升级时新进程接管旧进程的 socket 文件
+    if Path::new(path).exists() {
+        std::fs::remove_file(path)?;
+    }
+    let listener =
+        UnixListener::bind(path).map_err(|e| anyhow!("管理接口 {} 监听失败 {}", path, e))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    let ino = std::fs::metadata(path)?.ino();
+    info!("😄 管理接口 {}", path);
+
+    let mut finished = shutdown.finished();
+    loop {
+        let stream = tokio::select! {
+            res = listener.accept() => match res {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("管理接口接受连接失败 {}", e);
+                    continue;
+                }
+            },
+            _ = shutdown::wait(&mut finished) => break,
+        };
+
+        let admin = admin.clone();
+        tokio::spawn(async move {
+            if let Err(e) = connection(stream, &admin).await {
+                warn!("管理接口连接错误 {}", e);
+            }
+        });
+    }
+
+    // socket 文件已被新进程替换时保留
+    if std::fs::metadata(path)
+        .map(|m| m.ino() == ino)
+        .unwrap_or(false)
+    {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(())
+}
+
+async fn connection(stream: UnixStream, admin: &Admin) -> Result<()> {
+    let (r, mut w) = stream.into_split();
+    let mut lines = BufReader::new(r).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let res = match serde_json::from_str::<Request>(&line) {
+            Ok(req) => match handle(req, admin).await {
+                Ok(data) => Response::ok(data),
+                Err(e) => Response::error(&e),
+            },
+            Err(e) => Response::error(&anyhow!("无法解析的请求 {}", e)),
+        };
+        let mut reply = serde_json::to_vec(&res)?;
+        reply.push(b'\n');
+        w.write_all(&reply).await?;
+    }
+    Ok(())
+}
+
+pub async fn handle(req: Request, admin: &Admin) -> Result<Value> {
+    let control = &admin.control;
+    match req {
+        Request::Workers => Ok(json!(workers(admin).await?)),
+        Request::Pools => Ok(json!(pools(admin).await)),
+        Request::Kick { worker } => {
+            let count = control.kick(&worker);
+            if count == 0 {
+                bail!("没有找到矿机 {}", worker);
+            }
+            info!("🔧 管理员断开矿机 {} 共 {} 个连接", worker, count);
+            Ok(json!({ "sessions": count }))
+        }
+        Request::Drain { listener } => {
+            let listener: Listener = listener.parse()?;
+            let count = control.drain(listener);
+            info!("🔧 排空{}端口 通知 {} 个连接", listener, count);
+            Ok(json!({ "sessions": count }))
+        }
+        Request::Resume { listener } => {
+            let listener: Listener = listener.parse()?;
+            let resumed = control.resume(listener);
+            info!("🔧 {}端口恢复接受连接", listener);
+            Ok(json!({ "resumed": resumed }))
+        }
+        Request::UsePool { group, pool } => {
+            control.use_pool(&admin.config, &group, &pool)?;
+            if pool.is_empty() {
+                info!("🔧 {} 矿池组恢复配置顺序", group);
+            } else {
+                info!("🔧 {} 矿池组新连接优先使用 {}", group, pool);
+            }
+            Ok(json!({ "group": group, "pool": pool }))
+        }
+        Request::LogLevel { level } => {
+            if !level.is_empty() {
+                let filter: log::LevelFilter = level.parse().map_err(|_| {
+                    anyhow!(
+                        "未知的日志等级 {} 可选 off error warn info debug trace",
+                        level
+                    )
+                })?;
+                admin.log_level.set(filter);
+                warn!("🔧 日志等级修改为 {}", filter);
+            }
+            Ok(json!({ "level": admin.log_level.get().to_string().to_lowercase() }))
+        }
+        Request::Ledger => Ok(json!(control.ledger().snapshot())),
+        Request::Groups => Ok(json!(alias::groups(&query_workers(admin).await?))),
+        Request::Aliases => {
+            let (runtime, config) = admin.aliases.list();
+            Ok(json!(AliasList { runtime, config }))
+        }
+        Request::SetAlias { alias } => {
+            info!(
+                "🔧 矿机别名 wallet={} worker={} ip={} -> alias={} location={} group={}",
+                alias.wallet, alias.worker, alias.ip, alias.alias, alias.location, alias.group
+            );
+            admin.aliases.set(alias.clone())?;
+            Ok(json!(alias))
+        }
+        Request::Acl => Ok(json!(acl(&admin.ip_filter))),
+    }
+}
+
+fn acl(ip_filter: &IpFilter) -> Acl {
+    let mut bans: Vec<BanRow> = ip_filter
+        .banned()
+        .into_iter()
+        .map(|(ip, secs)| BanRow {
+            ip: ip.to_string(),
+            remaining_secs: secs,
+        })
+        .collect();
+    bans.sort_by(|a, b| a.ip.cmp(&b.ip));
+    Acl {
+        rules: ip_filter
+            .summary()
+            .into_iter()
+            .map(|(listener, allow, deny)| AclRule {
+                listener,
+                allow,
+                deny,
+            })
+            .collect(),
+        bans,
+    }
+}
+
+// 向统计任务索取矿机列表
+async fn query_workers(admin: &Admin) -> Result<Vec<Worker>> {
+    let (tx, rx) = oneshot::channel();
+    admin
+        .workers
+        .send(tx)
+        .await
+        .map_err(|_| anyhow!("矿机统计已停止"))?;
+    rx.await.map_err(|_| anyhow!("矿机统计已停止"))
+}
+
+// 统计任务中的矿机加上还未上报统计的新连接
+async fn workers(admin: &Admin) -> Result<Vec<WorkerRow>> {
+    let workers = query_workers(admin).await?;
+
+    let sessions = admin.control.sessions();
+    let mut rows: Vec<WorkerRow> = workers
+        .iter()
+        .filter(|w| !w.worker.is_empty())
+        .map(|w| WorkerRow {
+            worker: WorkerSnapshot::from(w),
+            sessions: Vec::new(),
+        })
+        .collect();
+    for s in sessions.into_iter().filter(|s| !s.worker.is_empty()) {
+        let (wallet, name) = s.worker.split_once('.').unwrap_or(("", &s.worker));
+        let mut w = Worker::new(s.worker.clone(), name.into(), wallet.into(), true);
+        match rows.iter_mut().find(|r| {
+            r.worker
+                .worker_wallet
+                .eq_ignore_ascii_case(&w.worker_wallet)
+                && r.worker.worker_name == w.worker_name
+        }) {
+            Some(row) => row.sessions.push(s),
+            None => {
+                w.sessions = 1;
+                w.online_sessions = 1;
+                if let Ok(addr) = s.addr.parse::<std::net::SocketAddr>() {
+                    w.ip = addr.ip().to_string();
+                }
+                admin.aliases.label(&mut w);
+                rows.push(WorkerRow {
+                    worker: WorkerSnapshot::from(&w),
+                    sessions: vec![s],
+                });
+            }
+        }
+    }
+    rows.sort_by(|a, b| a.worker.worker.cmp(&b.worker.worker));
+    Ok(rows)
+}
+
+// 逐个探测配置的矿池
+async fn pools(admin: &Admin) -> Pools {
+    let (control, config) = (&admin.control, &admin.config);
+    let current = control
+        .pools(config)
+        .and_then(|(group, pools)| Some((group, pools.first()?.clone())));
+
+    let mut targets = Vec::new();
+    for (group, name, pools) in [
+        (TCP, "tcp", &config.pool_tcp_address),
+        (SSL, "ssl", &config.pool_ssl_address),
+    ] {
+        let preferred = control.preferred(group);
+        for address in pools.iter().filter(|a| !a.is_empty()) {
+            let row = PoolRow {
+                group: name.into(),
+                address: address.clone(),
+                current: current == Some((group, address.clone())),
+                preferred: preferred.as_ref() == Some(address),
+                reachable: false,
+                latency_ms: None,
+            };
+            targets.push((row, config.pool_upstream_proxy.clone()));
+        }
+    }
+    for address in config.share_tcp_address.iter().filter(|a| !a.is_empty()) {
+        let row = PoolRow {
+            group: "share".into(),
+            address: address.clone(),
+            current: false,
+            preferred: false,
+            reachable: false,
+            latency_ms: None,
+        };
+        targets.push((row, config.share_upstream_proxy.clone()));
+    }
+
+    let probes = targets.into_iter().map(|(mut row, upstream_proxy)| {
+        let source_address = config.source_address.clone();
+        async move {
+            let address = vec![row.address.clone()];
+            let latency = tokio::task::spawn_blocking(move || {
+                let start = Instant::now();
+                crate::client::get_pool_stream(&address, &upstream_proxy, &source_address)
+                    .map(|_| start.elapsed().as_millis() as u64)
+            })
+            .await
+            .ok()
+            .flatten();
+            row.reachable = latency.is_some();
+            row.latency_ms = latency;
+            row
+        }
+    });
+
+    Pools {
+        pools: futures::future::join_all(probes).await,
+        draining: control
+            .draining()
+            .iter()
+            .map(|l| l.to_string().to_lowercase())
+            .collect(),
+    }
+}
+
+#[test]
+fn test_admin_socket() {
+    use super::{query, request, Ledger};
+    // 使用独立的日志等级，不影响并行的测试
+    static LOG_LEVEL: logger::Level = logger::Level::new(log::LevelFilter::Info);
+
+    let dir = std::env::temp_dir().join(format!("proxy-admin-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("proxy.sock").to_string_lossy().to_string();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let pool = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let pool_addr = pool.local_addr().unwrap().to_string();
+        let config = Settings {
+            pool_tcp_address: vec!["127.0.0.1:1".into(), pool_addr.clone()],
+            ..Default::default()
+        };
+
+        let control = Arc::new(Control::new());
+        let (workers, mut queries) = mpsc::channel::<oneshot::Sender<Vec<Worker>>>(1);
+        tokio::spawn(async move {
+            while let Some(tx) = queries.recv().await {
+                let mut w = Worker::new("0x00.rig1".into(), "rig1".into(), "0x00".into(), true);
+                w.share_index = 3;
+                let _ = tx.send(vec![w]);
+            }
+        });
+        let acl_config = Settings {
+            tcp_deny: vec!["10.0.0.0/8".into()],
+            ban_secs: 60,
+            ban_threshold: 1,
+            ..Default::default()
+        };
+        let ip_filter = Arc::new(IpFilter::new(&acl_config).unwrap());
+        ip_filter.strike(&"1.2.3.4".parse().unwrap(), "test");
+        let admin = Arc::new(Admin {
+            control: control.clone(),
+            config,
+            aliases: Arc::new(Aliases::default()),
+            ip_filter,
+            workers,
+            log_level: &LOG_LEVEL,
+        });
+        let shutdown = Arc::new(Shutdown::new());
+        let server = tokio::spawn({
+            let (path, shutdown) = (path.clone(), shutdown.clone());
+            async move { serve(&path, admin, shutdown).await }
+        });
+        while !Path::new(&path).exists() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut session = control.register(Listener::Tcp, "1.2.3.4:1000".parse().unwrap());
+        session.set_worker("0x00.rig2");
+        let rows: Vec<WorkerRow> = query(&path, &Request::Workers).await.unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].worker.share_index, 3);
+        assert!(rows[0].sessions.is_empty());
+        assert_eq!(rows[1].worker.worker_name, "rig2");
+        assert_eq!(rows[1].sessions[0].addr, "1.2.3.4:1000");
+
+        let e = request(
+            &path,
+            &Request::Kick {
+                worker: "rig3".into(),
+            },
+        )
+        .await;
+        assert_eq!(e.unwrap_err().to_string(), "没有找到矿机 rig3");
+        let res = request(
+            &path,
+            &Request::Kick {
+                worker: "rig2".into(),
+            },
+        )
+        .await;
+        assert_eq!(res.unwrap()["sessions"], 1);
+        assert_eq!(session.order().await, crate::client::control::Order::Kick);
+
+        let req = Request::Drain {
+            listener: "ssl".into(),
+        };
+        assert_eq!(request(&path, &req).await.unwrap()["sessions"], 0);
+        let req = Request::Drain {
+            listener: "udp".into(),
+        };
+        assert!(request(&path, &req).await.is_err());
+
+        let req = Request::UsePool {
+            group: "tcp".into(),
+            pool: pool_addr.clone(),
+        };
+        request(&path, &req).await.unwrap();
+        let pools: Pools = query(&path, &Request::Pools).await.unwrap();
+        assert_eq!(pools.draining, vec!["ssl"]);
+        assert_eq!(pools.pools.len(), 2);
+        assert!(!pools.pools[0].reachable && !pools.pools[0].current);
+        assert!(pools.pools[1].reachable && pools.pools[1].current && pools.pools[1].preferred);
+
+        let req = Request::LogLevel {
+            level: "debug".into(),
+        };
+        assert_eq!(request(&path, &req).await.unwrap()["level"], "debug");
+        let req = Request::LogLevel {
+            level: "loud".into(),
+        };
+        assert!(request(&path, &req).await.is_err());
+        assert_eq!(LOG_LEVEL.get(), log::LevelFilter::Debug);
+
+        let req = Request::SetAlias {
+            alias: alias::WorkerAlias {
+                worker: "rig2".into(),
+                group: "room1".into(),
+                ..Default::default()
+            },
+        };
+        request(&path, &req).await.unwrap();
+        let aliases: AliasList = query(&path, &Request::Aliases).await.unwrap();
+        assert_eq!(aliases.runtime[0].group, "room1");
+        let rows: Vec<WorkerRow> = query(&path, &Request::Workers).await.unwrap();
+        // 还未上报统计的新连接也按规则分组
+        assert_eq!(rows[1].worker.group, "room1");
+        let groups: Vec<alias::GroupStats> = query(&path, &Request::Groups).await.unwrap();
+        assert_eq!(groups[0].workers, 1);
+
+        let acl: Acl = query(&path, &Request::Acl).await.unwrap();
+        assert_eq!(acl.rules[0].deny, "10.0.0.0/8");
+        assert_eq!(acl.bans[0].ip, "1.2.3.4");
+        assert!(acl.bans[0].remaining_secs > 0);
+
+        control.ledger().job("pool");
+        let ledger: Ledger = query(&path, &Request::Ledger).await.unwrap();
+        assert_eq!(ledger["pool"].jobs, 1);
+
+        shutdown.drain(std::time::Duration::from_secs(1)).await;
+        server.await.unwrap().unwrap();
+        assert!(!Path::new(&path).exists());
+    });
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,430 @@
+pub mod sink;
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{
+    state::Worker,
+    util::{bytes_to_mb, config::Settings},
+};
+use sink::Sink;
+
+// 计算拒绝率至少需要的份额数
+const MIN_SHARES: u64 = 10;
+
+// 告警规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    // 矿机长时间没有提交份额
+    WorkerOffline,
+    // 有效算力低于报告算力
+    LowHashrate,
+    // 拒绝率过高
+    HighReject,
+    // 矿池无法连接
+    PoolUnreachable,
+}
+
+impl Rule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rule::WorkerOffline => "worker_offline",
+            Rule::LowHashrate => "low_hashrate",
+            Rule::HighReject => "high_reject",
+            Rule::PoolUnreachable => "pool_unreachable",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+impl AlertState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertState::Firing => "firing",
+            AlertState::Resolved => "resolved",
+        }
+    }
+}
+
+// 发给告警通道的一条通知
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub rule: Rule,
+    pub state: AlertState,
+    // 矿机名或矿池地址
+    pub subject: String,
+    pub message: String,
+    pub time: String,
+}
+
+impl std::fmt::Display for Alert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.state {
+            AlertState::Firing => write!(f, "🚨 [{}] {}", self.rule.as_str(), self.message),
+            AlertState::Resolved => {
+                write!(f, "✅ [{}] 已恢复 {}", self.rule.as_str(), self.message)
+            }
+        }
+    }
+}
+
+// 告警去抖。连续 n 次满足条件才告警，告警后连续 n 次不满足才发送恢复通知
+#[derive(Debug, Default)]
+struct Debounce {
+    firing: bool,
+    count: u32,
+}
+
+impl Debounce {
+    fn update(&mut self, active: bool, n: u32) -> Option<AlertState> {
+        if active == self.firing {
+            self.count = 0;
+            return None;
+        }
+        self.count += 1;
+        if self.count < n.max(1) {
+            return None;
+        }
+        self.firing = active;
+        self.count = 0;
+        Some(match active {
+            true => AlertState::Firing,
+            false => AlertState::Resolved,
+        })
+    }
+}
+
+// 矿机份额计数的采样。用于计算一段时间内的有效算力和拒绝率
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    time: Instant,
+    accept: u64,
+    reject: u64,
+}
+
+impl Sample {
+    fn of(w: &Worker) -> Self {
+        Self {
+            time: Instant::now(),
+            accept: w.accept_index,
+            reject: w.invalid_index + w.local_reject_index + w.duplicate_index,
+        }
+    }
+}
+
+// 告警规则的阈值。为 0 的规则不检查
+#[derive(Debug, Clone)]
+pub struct Rules {
+    pub offline: Duration,
+    pub hashrate_percent: u64,
+    pub reject_percent: u64,
+    // 计算有效算力和拒绝率的时间窗口
+    pub window: Duration,
+    pub pool: bool,
+    pub debounce: u32,
+}
+
+impl Rules {
+    pub fn new(config: &Settings) -> Self {
+        Self {
+            offline: Duration::from_secs(config.alert_offline_secs),
+            hashrate_percent: config.alert_hashrate_percent,
+            reject_percent: config.alert_reject_percent,
+            window: Duration::from_secs(config.alert_window_secs),
+            pool: config.alert_pool,
+            debounce: config.alert_debounce,
+        }
+    }
+}
+
+// 按规则检查矿机及矿池状态，状态变化时生成告警
+pub struct Alerter {
+    rules: Rules,
+    states: HashMap<(Rule, String), Debounce>,
+    samples: HashMap<String, VecDeque<Sample>>,
+}
+
+impl Alerter {
+    pub fn new(rules: Rules) -> Self {
+        Self {
+            rules,
+            states: HashMap::new(),
+            samples: HashMap::new(),
+        }
+    }
+
+    fn update(
+        &mut self,
+        rule: Rule,
+        subject: &str,
+        active: bool,
+        message: String,
+    ) -> Option<Alert> {
+        let n = self.rules.debounce;
+        let state = self
+            .states
+            .entry((rule, subject.to_string()))
+            .or_default()
+            .update(active, n)?;
+        Some(Alert {
+            rule,
+            state,
+            subject: subject.to_string(),
+            message,
+            time: chrono::Local::now().to_rfc3339(),
+        })
+    }
+
+    pub fn check_workers<'a>(
+        &mut self,
+        workers: impl IntoIterator<Item = &'a Worker>,
+    ) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        let mut seen = HashSet::new();
+        for w in workers {
+            if w.worker.is_empty() {
+                continue;
+            }
+            seen.insert(w.worker.clone());
+
+            if !self.rules.offline.is_zero() {
+                let idle = w.last_subwork_time.elapsed();
+                let message = format!(
+                    "矿机 {} 已 {} 分钟没有提交份额",
+                    w.worker,
+                    idle.as_secs() / 60
+                );
+                alerts.extend(self.update(
+                    Rule::WorkerOffline,
+                    &w.worker,
+                    idle >= self.rules.offline,
+                    message,
+                ));
+            }
+
+            let (accept, reject, secs) = match self.window(w) {
+                Some(delta) => delta,
+                None => continue,
+            };
+
+            // 有效算力只统计矿池接受的份额，抽水份额不计入
+            if self.rules.hashrate_percent != 0 && w.hash != 0 && w.share_diff != 0 {
+                let effective = accept * w.share_diff / secs.max(1);
+                let active = w.online && effective * 100 < w.hash * self.rules.hashrate_percent;
+                let message = format!(
+                    "矿机 {} 有效算力 {} Mb 报告算力 {} Mb 低于 {}%",
+                    w.worker,
+                    bytes_to_mb(effective),
+                    bytes_to_mb(w.hash),
+                    self.rules.hashrate_percent
+                );
+                alerts.extend(self.update(Rule::LowHashrate, &w.worker, active, message));
+            }
+
+            if self.rules.reject_percent != 0 {
+                let total = accept + reject;
+                let active =
+                    total >= MIN_SHARES && reject * 100 > total * self.rules.reject_percent;
+                let message = format!(
+                    "矿机 {} 拒绝率 {:.1}% 超过 {}%",
+                    w.worker,
+                    reject as f64 * 100.0 / total.max(1) as f64,
+                    self.rules.reject_percent
+                );
+                alerts.extend(self.update(Rule::HighReject, &w.worker, active, message));
+            }
+        }
+        // 已从统计中移除的矿机不再保留告警状态
+        self.samples.retain(|worker, _| seen.contains(worker));
+        self.states
+            .retain(|(rule, subject), _| *rule == Rule::PoolUnreachable || seen.contains(subject));
+        alerts
+    }
+
+    // 记录一次采样，返回时间窗口内的 (接受数, 拒绝数, 秒数)。采样不满一个窗口时返回 None
+    fn window(&mut self, w: &Worker) -> Option<(u64, u64, u64)> {
+        let window = self.rules.window;
+        let samples = self.samples.entry(w.worker.clone()).or_default();
+        let now = Sample::of(w);
+        // 重新登录后计数清零
+        if let Some(last) = samples.back() {
+            if now.accept < last.accept || now.reject < last.reject {
+                samples.clear();
+            }
+        }
+        samples.push_back(now);
+        while samples.len() > 1 && samples[1].time.elapsed() >= window {
+            samples.pop_front();
+        }
+
+        let first = samples.front()?;
+        let secs = first.time.elapsed();
+        if secs < window || secs.is_zero() {
+            return None;
+        }
+        Some((
+            now.accept - first.accept,
+            now.reject - first.reject,
+            secs.as_secs(),
+        ))
+    }
+
+    pub fn check_pool(&mut self, pool: &str, reachable: bool) -> Option<Alert> {
+        if !self.rules.pool {
+            return None;
+        }
+        let message = format!("矿池 {} 无法连接", pool);
+        self.update(Rule::PoolUnreachable, pool, !reachable, message)
+    }
+}
+
+// 记录告警并发送到所有通道
+pub async fn notify(sinks: &[Sink], alerts: &[Alert]) {
+    for alert in alerts {
+        match alert.state {
+            AlertState::Firing => warn!("{}", alert),
+            AlertState::Resolved => info!("{}", alert),
+        }
+        for sink in sinks {
+            if let Err(e) = sink.send(alert).await {
+                log::error!("告警发送失败 {} {}", sink, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_worker(name: &str) -> Worker {
+    let mut w = Worker::default();
+    w.worker = name.to_string();
+    w.online = true;
+    w
+}
+
+#[test]
+fn test_debounce() {
+    let mut d = Debounce::default();
+    assert_eq!(d.update(true, 2), None);
+    assert_eq!(d.update(false, 2), None);
+    assert_eq!(d.update(true, 2), None);
+    assert_eq!(d.update(true, 2), Some(AlertState::Firing));
+    assert_eq!(d.update(true, 2), None);
+    assert_eq!(d.update(false, 2), None);
+    assert_eq!(d.update(false, 2), Some(AlertState::Resolved));
+}
+
+#[test]
+fn test_worker_offline() {
+    let rules = Rules {
+        offline: Duration::from_secs(600),
+        hashrate_percent: 0,
+        reject_percent: 0,
+        window: Duration::from_secs(1800),
+        pool: true,
+        debounce: 1,
+    };
+    let mut alerter = Alerter::new(rules);
+    let mut w = test_worker("0x00.rig1");
+    assert!(alerter.check_workers([&w]).is_empty());
+
+    w.last_subwork_time = Instant::now() - Duration::from_secs(700);
+    let alerts = alerter.check_workers([&w]);
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].rule, Rule::WorkerOffline);
+    assert_eq!(alerts[0].state, AlertState::Firing);
+    assert_eq!(alerts[0].message, "矿机 0x00.rig1 已 11 分钟没有提交份额");
+    // 持续离线不重复告警
+    assert!(alerter.check_workers([&w]).is_empty());
+
+    w.last_subwork_time = Instant::now();
+    let alerts = alerter.check_workers([&w]);
+    assert_eq!(alerts[0].state, AlertState::Resolved);
+
+    assert_eq!(
+        alerter.check_pool("pool:4444", false).unwrap().state,
+        AlertState::Firing
+    );
+    assert_eq!(
+        alerter.check_pool("pool:4444", true).unwrap().state,
+        AlertState::Resolved
+    );
+
+    // 矿机从统计中移除后告警状态一起清理
+    assert!(alerter.check_workers(Vec::<&Worker>::new()).is_empty());
+    assert_eq!(alerter.states.len(), 1);
+}
+
+#[test]
+fn test_worker_shares() {
+    let rules = Rules {
+        offline: Duration::ZERO,
+        hashrate_percent: 50,
+        reject_percent: 10,
+        window: Duration::from_secs(100),
+        pool: false,
+        debounce: 1,
+    };
+    let mut alerter = Alerter::new(rules);
+    let mut w = test_worker("0x00.rig1");
+    w.hash = 100_000_000;
+    w.share_diff = 4_000_000_000;
+    alerter.samples.insert(
+        w.worker.clone(),
+        VecDeque::from(vec![Sample {
+            time: Instant::now() - Duration::from_secs(200),
+            accept: 0,
+            reject: 0,
+        }]),
+    );
+
+    // 200 秒 2 个份额 有效算力 40Mb 拒绝率 1/3
+    w.accept_index = 2;
+    w.invalid_index = 10;
+    w.local_reject_index = 0;
+    let alerts = alerter.check_workers([&w]);
+    let rules: Vec<Rule> = alerts.iter().map(|a| a.rule).collect();
+    assert_eq!(rules, vec![Rule::LowHashrate, Rule::HighReject]);
+    assert_eq!(
+        alerts[0].message,
+        "矿机 0x00.rig1 有效算力 40 Mb 报告算力 100 Mb 低于 50%"
+    );
+    assert_eq!(alerts[1].message, "矿机 0x00.rig1 拒绝率 83.3% 超过 10%");
+
+    // 重新登录后计数清零，重新采样
+    w.accept_index = 0;
+    w.invalid_index = 0;
+    assert!(alerter.check_workers([&w]).is_empty());
+}
+
+#[test]
+fn test_alert_json() {
+    let alert = Alert {
+        rule: Rule::PoolUnreachable,
+        state: AlertState::Firing,
+        subject: "pool:4444".into(),
+        message: "矿池 pool:4444 无法连接".into(),
+        time: "2022-01-01T00:00:00+08:00".into(),
+    };
+    assert_eq!(
+        serde_json::to_value(&alert).unwrap(),
+        serde_json::json!({
+            "rule": "pool_unreachable",
+            "state": "firing",
+            "subject": "pool:4444",
+            "message": "矿池 pool:4444 无法连接",
+            "time": "2022-01-01T00:00:00+08:00"
+        })
+    );
+}
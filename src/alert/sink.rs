@@ -0,0 +1,267 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{bail, Result};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    process::Command,
+    time::timeout,
+};
+
+use super::Alert;
+use crate::util::config::Settings;
+
+// 发送一条告警的超时时间
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// 告警通道
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sink {
+    // 以 JSON 格式 POST 到 http:// 或 https:// 地址
+    Webhook(String),
+    // 通过 sh -c 执行命令。告警内容在环境变量中
+    Command(String),
+    // 每条告警一行 JSON 追加到文件
+    File(PathBuf),
+}
+
+impl std::fmt::Display for Sink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sink::Webhook(url) => write!(f, "webhook {}", url),
+            Sink::Command(cmd) => write!(f, "command {}", cmd),
+            Sink::File(path) => write!(f, "file {}", path.display()),
+        }
+    }
+}
+
+impl Sink {
+    // 配置的告警通道。都未配置时不检查告警
+    pub fn from_config(config: &Settings) -> Vec<Sink> {
+        let mut sinks = Vec::new();
+        if !config.alert_webhook.is_empty() {
+            sinks.push(Sink::Webhook(config.alert_webhook.clone()));
+        }
+        if !config.alert_command.is_empty() {
+            sinks.push(Sink::Command(config.alert_command.clone()));
+        }
+        if !config.alert_file.is_empty() {
+            sinks.push(Sink::File(config.alert_file.clone().into()));
+        }
+        sinks
+    }
+
+    pub async fn send(&self, alert: &Alert) -> Result<()> {
+        let body = serde_json::to_string(alert)?;
+        match self {
+            Sink::Webhook(url) => timeout(TIMEOUT, post(url, &body)).await?,
+            Sink::Command(cmd) => {
+                let status = Command::new("sh")
+                    .arg("-c")
+                    .arg(cmd)
+                    .env("ALERT_RULE", alert.rule.as_str())
+                    .env("ALERT_STATE", alert.state.as_str())
+                    .env("ALERT_SUBJECT", &alert.subject)
+                    .env("ALERT_MESSAGE", &alert.message)
+                    .env("ALERT_JSON", &body)
+                    .kill_on_drop(true)
+                    .status();
+                let status = timeout(TIMEOUT, status).await??;
+                if !status.success() {
+                    bail!("命令退出 {}", status);
+                }
+                Ok(())
+            }
+            Sink::File(path) => {
+                let mut file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await?;
+                file.write_all(format!("{}\n", body).as_bytes()).await?;
+                file.flush().await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+// 解析 webhook 地址，返回 (是否 https, 主机, 端口, 路径)
+fn parse_url(url: &str) -> Result<(bool, String, u16, String)> {
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        bail!("webhook 只支持 http:// 或 https:// 地址 {}", url);
+    };
+
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let default_port = if tls { 443 } else { 80 };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (host, port.parse()?),
+        _ => (authority, default_port),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        bail!("webhook 地址缺少主机 {}", url);
+    }
+    Ok((tls, host.to_string(), port, path.to_string()))
+}
+
+async fn post(url: &str, body: &str) -> Result<()> {
+    let (tls, host, port, path) = parse_url(url)?;
+    let authority = match host.contains(':') {
+        true => format!("[{}]:{}", host, port),
+        false => format!("{}:{}", host, port),
+    };
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        authority,
+        body.len(),
+        body
+    );
+
+    let stream = TcpStream::connect((host.as_str(), port)).await?;
+    if tls {
+        let connector = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+        let stream = connector.connect(&host, stream).await?;
+        exchange(stream, &request).await
+    } else {
+        exchange(stream, &request).await
+    }
+}
+
+// 发送请求并检查响应状态码
+async fn exchange<S>(stream: S, request: &str) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = BufReader::new(stream);
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    let mut status = String::new();
+    stream.read_line(&mut status).await?;
+    let code = status.split_whitespace().nth(1).unwrap_or("");
+    if !code.starts_with('2') {
+        bail!("webhook 返回 {}", status.trim());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_url() {
+    assert_eq!(
+        parse_url("http://127.0.0.1:8080/hook?a=1").unwrap(),
+        (false, "127.0.0.1".into(), 8080, "/hook?a=1".into())
+    );
+    assert_eq!(
+        parse_url("https://example.com").unwrap(),
+        (true, "example.com".into(), 443, "/".into())
+    );
+    assert_eq!(
+        parse_url("http://[::1]/hook").unwrap(),
+        (false, "::1".into(), 80, "/hook".into())
+    );
+    assert!(parse_url("ftp://example.com").is_err());
+    assert!(parse_url("http://:80/").is_err());
+}
+
+#[test]
+fn test_webhook() {
+    use super::{AlertState, Rule};
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        // 本地 HTTP 服务代替 webhook
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for status in ["200 OK", "500 Internal Server Error"] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let mut request = Vec::new();
+                while !request.ends_with(b"}") {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+                requests.push(String::from_utf8(request).unwrap());
+            }
+            requests
+        });
+
+        let alert = Alert {
+            rule: Rule::WorkerOffline,
+            state: AlertState::Firing,
+            subject: "0x00.rig1".into(),
+            message: "矿机 0x00.rig1 已 10 分钟没有提交份额".into(),
+            time: "2022-01-01T00:00:00+08:00".into(),
+        };
+        let sink = Sink::Webhook(url);
+        sink.send(&alert).await.unwrap();
+        let e = sink.send(&alert).await.unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "webhook 返回 HTTP/1.1 500 Internal Server Error"
+        );
+
+        let requests = server.await.unwrap();
+        let (head, body) = requests[0].split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("POST /hook HTTP/1.1\r\n"));
+        assert!(head.contains("Content-Type: application/json"));
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["rule"], "worker_offline");
+        assert_eq!(body["state"], "firing");
+        assert_eq!(body["subject"], "0x00.rig1");
+    });
+}
+
+#[test]
+fn test_command_and_file() {
+    use super::{AlertState, Rule};
+
+    let dir = std::env::temp_dir().join(format!("proxy-alert-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let alert = Alert {
+            rule: Rule::PoolUnreachable,
+            state: AlertState::Resolved,
+            subject: "pool:4444".into(),
+            message: "矿池 pool:4444 无法连接".into(),
+            time: "2022-01-01T00:00:00+08:00".into(),
+        };
+        let out = dir.join("command.txt");
+        let cmd = format!(
+            "echo \"$ALERT_RULE $ALERT_STATE $ALERT_SUBJECT\" > {}",
+            out.display()
+        );
+        Sink::Command(cmd).send(&alert).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "pool_unreachable resolved pool:4444\n"
+        );
+        assert!(Sink::Command("exit 3".into()).send(&alert).await.is_err());
+
+        let file = Sink::File(dir.join("alerts.log"));
+        file.send(&alert).await.unwrap();
+        file.send(&alert).await.unwrap();
+        let text = std::fs::read_to_string(dir.join("alerts.log")).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        let line: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(line["message"], "矿池 pool:4444 无法连接");
+    });
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
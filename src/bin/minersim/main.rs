@@ -0,0 +1,126 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use clap::{crate_name, crate_version, ArgMatches};
+use log::info;
+
+use proxy::mock::miner::{self, MinerConfig, MinerStats};
+use proxy::util::*;
+
+fn parse_arg<T: FromStr>(matches: &ArgMatches, name: &str, default: T) -> T {
+    match matches.value_of(name) {
+        Some(v) => v.parse().unwrap_or_else(|_| {
+            info!("❎ 参数 {} 错误: {}", name, v);
+            std::process::exit(1);
+        }),
+        None => default,
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let matches = get_minersim_command_matches().await?;
+    logger::init_client(1)?;
+
+    info!("✅ {} 模拟矿机, 版本: {}", crate_name!(), crate_version!());
+
+    let default = MinerConfig::default();
+    let config = Arc::new(MinerConfig {
+        server: parse_arg(&matches, "server", default.server.clone()),
+        tls: matches.is_present("tls"),
+        stratum: matches.is_present("stratum"),
+        wallet: parse_arg(&matches, "wallet", default.wallet.clone()),
+        worker: parse_arg(&matches, "worker", default.worker.clone()),
+        hashrate: parse_arg(&matches, "hashrate", 100u64) * 1000 * 1000,
+        share_interval: parse_arg(&matches, "share_interval", default.share_interval),
+        ..default
+    });
+    let miners: usize = parse_arg(&matches, "miners", 1);
+    let duration: u64 = parse_arg(&matches, "duration", 0);
+    let report: u64 = parse_arg(&matches, "report", 10);
+
+    info!(
+        "🚜 启动 {} 台模拟矿机 连接 {} 协议 {} {}",
+        miners,
+        config.server,
+        if config.stratum {
+            "EthereumStratum"
+        } else {
+            "ETHPROXY"
+        },
+        if config.tls { "SSL" } else { "TCP" }
+    );
+
+    let mut stats = vec![];
+    for i in 0..miners {
+        let s = Arc::new(Mutex::new(MinerStats::new(config.worker_name(i))));
+        stats.push(s.clone());
+        tokio::spawn(miner::run(config.clone(), i, s));
+        // 错开连接，避免瞬间建立大量连接
+        if i % 100 == 99 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    let stop = async {
+        match duration {
+            0 => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+            secs => tokio::time::sleep(Duration::from_secs(secs)).await,
+        }
+    };
+    tokio::pin!(stop);
+
+    let mut ticker = tokio::time::interval(Duration::from_secs(report.max(1)));
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            _ = &mut stop => break,
+            _ = ticker.tick() => print_summary(&stats),
+        }
+    }
+
+    for s in &stats {
+        print_miner(&s.lock().unwrap());
+    }
+    print_summary(&stats);
+
+    Ok(())
+}
+
+fn print_miner(s: &MinerStats) {
+    info!(
+        "{} 连接: {} 断开: {} 任务: {} 提交: {} 接受: {} 拒绝: {} 过期: {} 接受率: {:.2}% 平均延迟: {:?} 最大延迟: {:?}",
+        s.name,
+        s.connects,
+        s.disconnects,
+        s.jobs,
+        s.submitted,
+        s.accepted,
+        s.rejected,
+        s.stale,
+        s.accept_ratio() * 100.0,
+        s.avg_latency(),
+        s.latency_max
+    );
+}
+
+fn print_summary(stats: &[Arc<Mutex<MinerStats>>]) {
+    let mut total = MinerStats::new("汇总".into());
+    let mut online = 0;
+    for s in stats {
+        let s = s.lock().unwrap();
+        if s.connects > s.disconnects {
+            online += 1;
+        }
+        total.merge(&s);
+    }
+
+    info!("📊 在线矿机: {}/{}", online, stats.len());
+    print_miner(&total);
+}
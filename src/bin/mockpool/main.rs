@@ -0,0 +1,63 @@
+use anyhow::Result;
+use clap::{crate_name, crate_version};
+use log::info;
+use native_tls::Identity;
+use tokio::net::TcpListener;
+
+use proxy::mock::pool::{MockPool, Script};
+use proxy::util::*;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let matches = get_mockpool_command_matches().await?;
+    logger::init_client(1)?;
+
+    info!("✅ {} 模拟矿池, 版本: {}", crate_name!(), crate_version!());
+
+    let script_path = matches.value_of("script").unwrap_or("mockpool.yaml");
+    let script = Script::new(script_path).unwrap_or_else(|e| {
+        info!("❎ 矿池脚本错误: {}", e);
+        std::process::exit(1);
+    });
+    info!("矿池脚本: {:?}", script);
+
+    let port: u16 = matches
+        .value_of("port")
+        .unwrap_or("4444")
+        .parse()
+        .unwrap_or_else(|_| {
+            info!("请正确填写TCP监听端口 例如: -p 4444");
+            std::process::exit(1);
+        });
+
+    let ssl_port: Option<u16> = matches.value_of("ssl_port").map(|p| {
+        p.parse().unwrap_or_else(|_| {
+            info!("请正确填写SSL监听端口 例如: -s 8443");
+            std::process::exit(1);
+        })
+    });
+
+    let pool = MockPool::new(script);
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+    let tcp = pool.clone().serve_tcp(listener);
+
+    let tls = async {
+        let ssl_port = match ssl_port {
+            Some(port) => port,
+            None => return Ok(()),
+        };
+
+        let p12 = std::fs::read(matches.value_of("p12_path").unwrap_or("identity.p12"))?;
+        let cert = Identity::from_pkcs12(&p12, matches.value_of("p12_pass").unwrap_or("mypass"))?;
+        let acceptor =
+            tokio_native_tls::TlsAcceptor::from(native_tls::TlsAcceptor::builder(cert).build()?);
+        let listener = TcpListener::bind(format!("0.0.0.0:{}", ssl_port)).await?;
+        pool.clone().serve_tls(listener, acceptor).await
+    };
+
+    if let Err(err) = tokio::try_join!(tcp, tls) {
+        log::warn!("模拟矿池退出: {}", err);
+    }
+
+    Ok(())
+}
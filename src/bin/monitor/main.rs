@@ -24,7 +24,14 @@ async fn main() -> Result<()> {
         },
     ));
 
-    logger::init("monitor", "./logs/".into(), 0)?;
+    logger::init(
+        "monitor",
+        "./logs/".into(),
+        0,
+        false,
+        Default::default(),
+        false,
+    )?;
 
     info!(
         "✅ {}, 版本: {} commit: {} {}",
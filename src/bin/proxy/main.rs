@@ -3,9 +3,32 @@ mod version {
 }
 
 use log::info;
-use proxy::{client::encry::accept_en_tcp, state::Worker};
+use proxy::{
+    admin::server::{self as admin, Admin, WorkersQuery},
+    alert::{self, sink::Sink, Alerter, Rules},
+    client::{
+        acl::{IpFilter, Listener},
+        auth::Auth,
+        control::Control,
+        develop_pools,
+        encry::accept_en_tcp,
+        error::SessionError,
+        naming,
+        shutdown::{self, Shutdown},
+        socket, upgrade,
+        upstream::UpstreamProxy,
+        Context,
+    },
+    protocol::ethash,
+    state::{
+        alias::{self, Aliases},
+        latency,
+        registry::Registry,
+        Worker,
+    },
+};
 
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use bytes::BytesMut;
@@ -16,10 +39,11 @@ use prettytable::{cell, row, Table};
 use tokio::{
     fs::File,
     io::AsyncReadExt,
+    signal::unix::{signal, SignalKind},
     sync::{
         broadcast,
         mpsc::{self, Receiver},
-        RwLock,
+        oneshot, RwLock,
     },
     time::sleep,
 };
@@ -28,6 +52,7 @@ use proxy::client::tcp::accept_tcp;
 use proxy::client::tls::accept_tcp_with_tls;
 use proxy::jobs::JobQueue;
 use proxy::util::config::Settings;
+use proxy::util::logger::event;
 use proxy::util::*;
 
 #[tokio::main]
@@ -72,6 +97,9 @@ async fn main() -> Result<()> {
         config.name.as_str(),
         config.log_path.clone(),
         config.log_level,
+        config.log_json(),
+        config.log_retention(),
+        config.log_events,
     )?;
 
     // 分配任务给矿机channel
@@ -90,6 +118,41 @@ async fn main() -> Result<()> {
         std::process::exit(1);
     }
 
+    if config.tcp_port == 0 && config.ssl_port == 0 && config.encrypt_port == 0 {
+        info!("❎ TCP、SSL、加密端口必须开启其中的一个。");
+        std::process::exit(1);
+    }
+
+    if let Err(e) = socket::parse_source(&config.source_address) {
+        info!("❎ 出口地址配置错误: {}", e);
+        std::process::exit(1);
+    }
+
+    for upstream_proxy in [
+        &config.pool_upstream_proxy,
+        &config.share_upstream_proxy,
+        &config.develop_upstream_proxy,
+    ] {
+        if upstream_proxy.is_empty() {
+            continue;
+        }
+        if let Err(e) = upstream_proxy.parse::<UpstreamProxy>() {
+            info!("❎ 上游代理配置错误: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Err(e) = naming::check(&config) {
+        info!("❎ 矿机名改写规则配置错误: {}", e);
+        std::process::exit(1);
+    }
+
+    if config.verify_shares && config.verify_epochs == 0 {
+        info!("❎ 开启份额验证时 verify_epochs 至少为 1。");
+        std::process::exit(1);
+    }
+    ethash::init_epochs(config.verify_epochs);
+
     let mut p12 = File::open(config.p12_path.clone())
         .await
         .expect("证书路径错误");
@@ -122,53 +185,129 @@ async fn main() -> Result<()> {
 
     // 当前中转总报告算力。Arc<> Or atom 变量
     let (worker_tx, worker_rx) = mpsc::channel::<Worker>(100);
+    // 定时把矿机状态交给告警检查
+    let (alert_tx, alert_rx) = mpsc::channel::<Vec<Worker>>(1);
+    // 管理接口查询矿机列表
+    let (admin_tx, admin_rx) = mpsc::channel::<oneshot::Sender<Vec<Worker>>>(10);
+    let (upgrade_tx, upgrade_rx) = mpsc::channel::<Upgrade>(1);
 
     let thread_len = clac_phread_num_for_real(config.share_rate.into());
     let thread_len = thread_len * 3; //扩容三倍存储更多任务
     let mine_jobs = Arc::new(JobQueue::new(thread_len as usize));
     let develop_jobs = Arc::new(JobQueue::new(thread_len as usize));
 
+    // IP 黑白名单
+    let ip_filter = Arc::new(IpFilter::new(&config)?);
+    // 矿机登录鉴权
+    let auth = Arc::new(Auth::new(&config));
+    // 矿机别名及分组
+    let aliases = Arc::new(Aliases::new(&config)?);
+    // 停机排空
+    let shutdown = Arc::new(Shutdown::new());
+    // 管理接口的控制中心
+    let control = Arc::new(Control::new());
+
+    // 监听端口。平滑升级时直接使用旧进程传递过来的监听 socket
+    let mut inherited = upgrade::inherited_listeners();
+    let tcp_listeners = socket::bind_listeners(
+        Listener::Tcp,
+        &config.tcp_bind,
+        config.tcp_port,
+        &mut inherited,
+    )?;
+    let ssl_listeners = socket::bind_listeners(
+        Listener::Ssl,
+        &config.ssl_bind,
+        config.ssl_port,
+        &mut inherited,
+    )?;
+    let encrypt_listeners = socket::bind_listeners(
+        Listener::Encrypt,
+        &config.encrypt_bind,
+        config.encrypt_port,
+        &mut inherited,
+    )?;
+    upgrade::close_unused(inherited);
+
+    let mut listener_fds = upgrade::listener_fds(Listener::Tcp, &tcp_listeners);
+    listener_fds.extend(upgrade::listener_fds(Listener::Ssl, &ssl_listeners));
+    listener_fds.extend(upgrade::listener_fds(Listener::Encrypt, &encrypt_listeners));
+
+    if !config.pid_file.is_empty() {
+        std::fs::write(&config.pid_file, std::process::id().to_string())?;
+    }
+    if let Err(e) = upgrade::notify_ready() {
+        log::warn!("通知 systemd 失败 {}", e);
+    }
+
+    // 各监听端口的矿机会话共用
+    let ctx = Arc::new(Context {
+        config: config.clone(),
+        develop_pools: develop_pools(),
+        worker_queue: worker_tx.clone(),
+        mine_jobs_queue: mine_jobs.clone(),
+        develop_jobs_queue: develop_jobs.clone(),
+        proxy_fee_sender: proxy_job_channel.clone(),
+        develop_fee_sender: fee_tx.clone(),
+        ip_filter: ip_filter.clone(),
+        auth: auth.clone(),
+        shutdown: shutdown.clone(),
+        control: control.clone(),
+    });
+
     let res = tokio::try_join!(
         accept_tcp(
-            worker_tx.clone(),
-            mine_jobs.clone(),
-            develop_jobs.clone(),
-            config.clone(),
+            ctx.clone(),
             job_send.clone(),
-            proxy_job_channel.clone(),
-            fee_tx.clone(),
             state_send.clone(),
             dev_state_send.clone(),
+            tcp_listeners,
         ),
         accept_en_tcp(
-            worker_tx.clone(),
-            mine_jobs.clone(),
-            develop_jobs.clone(),
-            config.clone(),
+            ctx.clone(),
             job_send.clone(),
-            proxy_job_channel.clone(),
-            fee_tx.clone(),
             state_send.clone(),
             dev_state_send.clone(),
+            encrypt_listeners,
         ),
         accept_tcp_with_tls(
-            worker_tx.clone(),
-            mine_jobs.clone(),
-            develop_jobs.clone(),
-            config.clone(),
+            ctx.clone(),
             job_send.clone(),
-            proxy_job_channel.clone(),
-            fee_tx.clone(),
             state_send.clone(),
             dev_state_send.clone(),
+            ssl_listeners,
             cert,
         ),
         process_workers(
             &config,
             worker_rx,
             proxy_worker.clone(),
-            develop_worker.clone()
+            develop_worker.clone(),
+            ip_filter.clone(),
+            aliases.clone(),
+            shutdown.clone(),
+            alert_tx,
+            admin_rx,
+            upgrade_rx,
+        ),
+        process_alerts(&config, alert_rx),
+        process_admin(
+            &config,
+            control.clone(),
+            aliases.clone(),
+            ip_filter.clone(),
+            admin_tx,
+            shutdown.clone(),
         ),
+        process_reload(
+            config_file_name,
+            ip_filter.clone(),
+            auth.clone(),
+            aliases.clone(),
+            shutdown.clone(),
+        ),
+        process_shutdown(&config, shutdown.clone()),
+        process_upgrade(&config, listener_fds, upgrade_tx, shutdown.clone()),
     );
 
     if let Err(err) = res {
@@ -369,10 +508,12 @@ async fn main() -> Result<()> {
 // }
 
 pub async fn print_state(
-    workers: &HashMap<String, Worker>,
+    workers: &[Worker],
     config: &Settings,
     proxy_worker: Arc<tokio::sync::RwLock<Worker>>,
     develop_worker: Arc<tokio::sync::RwLock<Worker>>,
+    ip_filter: &IpFilter,
+    disconnects: &HashMap<SessionError, u64>,
 ) -> Result<()> {
     // 创建表格
     let mut table = Table::new();
@@ -383,14 +524,27 @@ pub async fn print_state(
         "总工作量(份额)",
         "有效份额",
         "无效份额",
+        "本地拒绝",
+        "重复份额",
+        "过期率",
+        "连接(在线/累计)",
+        "1小时断开",
+        "任务延迟(ms)\np50/p90/p99",
+        "提交往返(ms)\np50/p90/p99",
+        "首份额(ms)\np50/p90/p99",
         "在线时长(小时)",
         "最后提交(分钟)",
     ]);
 
+    let mut online: u64 = 0;
     let mut total_hash: u64 = 0;
     let mut total_share: u64 = 0;
     let mut total_accept: u64 = 0;
     let mut total_invalid: u64 = 0;
+    let mut total_local_reject: u64 = 0;
+    let mut total_duplicate: u64 = 0;
+    let mut total_stale: u64 = 0;
+    let mut total_stale_drop: u64 = 0;
     // {
     //     let w = RwLockReadGuard::map(proxy_worker.read().await, |s| s);
     //     table.add_row(row![
@@ -415,27 +569,44 @@ pub async fn print_state(
     //     w.invalid_index
     // ]);
 
-    for (_name, w) in workers {
+    for w in workers {
         if w.last_subwork_time.elapsed().as_secs() >= 1800 {
             continue;
         }
 
         // 添加行
         table.add_row(row![
-            w.worker_name,
+            if w.alias.is_empty() {
+                &w.worker_name
+            } else {
+                &w.alias
+            },
             bytes_to_mb(w.hash).to_string() + " Mb",
             calc_hash_rate(bytes_to_mb(w.hash), config.share_rate).to_string() + " Mb",
             w.share_index,
             w.accept_index,
             w.invalid_index,
+            w.local_reject_index,
+            w.duplicate_index,
+            format!("{:.1}%", w.stale_rate() * 100.0),
+            format!("{}/{}", w.online_sessions, w.sessions),
+            w.recent_disconnects,
+            latency::brief(&w.job_delay.percentiles()),
+            latency::brief(&w.submit_rtt.percentiles()),
+            latency::brief(&w.first_share.percentiles()),
             time_to_string(w.login_time.elapsed().as_secs()),
             time_to_string(w.last_subwork_time.elapsed().as_secs()),
         ]);
 
+        online += 1;
         total_hash += w.hash;
         total_share = total_share + w.share_index;
         total_accept = total_accept + w.accept_index;
         total_invalid = total_invalid + w.invalid_index;
+        total_local_reject += w.local_reject_index;
+        total_duplicate += w.duplicate_index;
+        total_stale += w.stale_index;
+        total_stale_drop += w.stale_drop_index;
     }
 
     // //将total hash 写入worker
@@ -459,12 +630,88 @@ pub async fn print_state(
         total_share,
         total_accept,
         total_invalid,
+        total_local_reject,
+        total_duplicate,
+        format!(
+            "{:.1}%",
+            total_stale as f64 * 100.0 / (total_share + total_stale_drop).max(1) as f64
+        ),
+        "",
+        "",
+        "",
+        "",
+        "",
         "",
         "",
     ]);
 
     table.printstd();
 
+    info!(
+        target: event::FEE,
+        "📊 矿机 {} 台 算力 {} Mb 抽水算力 {} Mb 份额 {}/{}/{}",
+        online,
+        bytes_to_mb(total_hash),
+        calc_hash_rate(bytes_to_mb(total_hash), config.share_rate),
+        total_share,
+        total_accept,
+        total_invalid
+    );
+
+    // 分组汇总。配置了矿机分组时打印
+    let groups = alias::groups(workers);
+    if groups.iter().any(|g| !g.group.is_empty()) {
+        let mut table = Table::new();
+        table.add_row(row![
+            "分组",
+            "矿机(在线/总数)",
+            "报告算力",
+            "总工作量(份额)",
+            "有效份额",
+            "无效份额",
+            "1小时断开",
+        ]);
+        for g in &groups {
+            table.add_row(row![
+                if g.group.is_empty() {
+                    "未分组"
+                } else {
+                    &g.group
+                },
+                format!("{}/{}", g.online, g.workers),
+                bytes_to_mb(g.hash).to_string() + " Mb",
+                g.share_index,
+                g.accept_index,
+                g.invalid_index,
+                g.recent_disconnects,
+            ]);
+        }
+        table.printstd();
+    }
+
+    // IP 黑白名单及封禁列表
+    let mut table = Table::new();
+    table.add_row(row!["监听", "白名单", "黑名单"]);
+    for (listener, allow, deny) in ip_filter.summary() {
+        table.add_row(row![listener, allow, deny]);
+    }
+    for (ip, secs) in ip_filter.banned() {
+        table.add_row(row!["封禁", ip, time_to_string(secs)]);
+    }
+    table.printstd();
+
+    // 会话断开原因
+    if !disconnects.is_empty() {
+        let mut table = Table::new();
+        table.add_row(row!["断开原因", "次数"]);
+        for reason in SessionError::ALL {
+            if let Some(count) = disconnects.get(&reason) {
+                table.add_row(row![reason, count]);
+            }
+        }
+        table.printstd();
+    }
+
     Ok(())
 }
 
@@ -473,32 +720,326 @@ pub async fn process_workers(
     mut worker_rx: Receiver<Worker>,
     proxy_worker: Arc<tokio::sync::RwLock<Worker>>,
     develop_worker: Arc<tokio::sync::RwLock<Worker>>,
+    ip_filter: Arc<IpFilter>,
+    aliases: Arc<Aliases>,
+    shutdown: Arc<Shutdown>,
+    alert_tx: mpsc::Sender<Vec<Worker>>,
+    mut admin_rx: Receiver<oneshot::Sender<Vec<Worker>>>,
+    mut upgrade_rx: Receiver<Upgrade>,
 ) -> Result<()> {
-    let mut workers: HashMap<String, Worker> = HashMap::new();
+    let mut workers = load_workers(config);
+    // 已启动新进程时停机统计交接给新进程
+    let mut upgraded = false;
+    expire_workers(&mut workers, config);
+    let mut disconnects: HashMap<SessionError, u64> = HashMap::new();
+    let mut finished = shutdown.finished();
 
     let sleep = sleep(tokio::time::Duration::from_millis(1000 * 60));
     tokio::pin!(sleep);
+    let mut alert_tick =
+        tokio::time::interval(Duration::from_secs(config.alert_interval_secs.max(1)));
 
     loop {
         tokio::select! {
             Some(w) = worker_rx.recv() => {
-
-                if workers.contains_key(&w.worker) {
-                    if let Some(mine) = workers.get_mut(&w.worker) {
-                        *mine = w;
-                    }
-                } else {
-                    workers.insert(w.worker.clone(),w);
+                update_worker(&mut workers, &mut disconnects, w);
+            },
+            _ = alert_tick.tick(), if !alert_tx.is_closed() => {
+                // 上一次检查未结束时跳过
+                let _ = alert_tx.try_send(aliases.apply(workers.workers()));
+            },
+            Some(tx) = admin_rx.recv() => {
+                let _ = tx.send(aliases.apply(workers.workers()));
+            },
+            Some(upgrade) = upgrade_rx.recv() => match upgrade {
+                Upgrade::Save(tx) => {
+                    save_workers(&workers, &config.state_file());
+                    upgraded = true;
+                    let _ = tx.send(());
                 }
+                Upgrade::Cancel => upgraded = false,
             },
             () = &mut sleep => {
-                match print_state(&workers,config,proxy_worker.clone(),develop_worker.clone()).await{
+                merge_handoff(&mut workers, config);
+                expire_workers(&mut workers, config);
+                match print_state(&aliases.apply(workers.workers()),config,proxy_worker.clone(),develop_worker.clone(),&ip_filter,&disconnects).await{
                     Ok(_) => {},
                     Err(_) => {log::info!("打印失败了")},
                 }
 
                 sleep.as_mut().reset(tokio::time::Instant::now() + tokio::time::Duration::from_millis(1000*60));
             },
+            _ = shutdown::wait(&mut finished) => {
+                // 收取排空期间各会话上报的最终状态
+                while let Ok(w) = worker_rx.try_recv() {
+                    update_worker(&mut workers, &mut disconnects, w);
+                }
+
+                if upgraded {
+                    save_workers(&workers, &config.handoff_file());
+                } else {
+                    merge_handoff(&mut workers, config);
+                    save_workers(&workers, &config.state_file());
+                }
+                if print_state(&aliases.apply(workers.workers()), config, proxy_worker.clone(), develop_worker.clone(), &ip_filter, &disconnects).await.is_err() {
+                    log::info!("打印失败了");
+                }
+                return Ok(());
+            },
+        }
+    }
+}
+
+fn update_worker(workers: &mut Registry, disconnects: &mut HashMap<SessionError, u64>, w: Worker) {
+    if let Some(reason) = w.disconnect {
+        *disconnects.entry(reason).or_insert(0) += 1;
+    }
+    workers.update(w);
+}
+
+// 检查告警规则并发送通知。未配置告警通道时直接返回
+pub async fn process_alerts(config: &Settings, mut alert_rx: Receiver<Vec<Worker>>) -> Result<()> {
+    let sinks = Sink::from_config(config);
+    if sinks.is_empty() {
+        return Ok(());
+    }
+    let mut alerter = Alerter::new(Rules::new(config));
+    let pools = match proxy::client::get_pool_ip_and_type(config) {
+        Some((_, pools)) if config.alert_pool => pools,
+        _ => Vec::new(),
+    };
+
+    while let Some(workers) = alert_rx.recv().await {
+        let mut alerts = alerter.check_workers(&workers);
+        for pool in &pools {
+            let (address, upstream_proxy, source_address) = (
+                vec![pool.clone()],
+                config.pool_upstream_proxy.clone(),
+                config.source_address.clone(),
+            );
+            let reachable = tokio::task::spawn_blocking(move || {
+                proxy::client::get_pool_stream(&address, &upstream_proxy, &source_address).is_some()
+            })
+            .await?;
+            alerts.extend(alerter.check_pool(pool, reachable));
+        }
+        alert::notify(&sinks, &alerts).await;
+    }
+    Ok(())
+}
+
+// 管理接口。未配置 admin_socket 时不开启
+pub async fn process_admin(
+    config: &Settings,
+    control: Arc<Control>,
+    aliases: Arc<Aliases>,
+    ip_filter: Arc<IpFilter>,
+    workers: WorkersQuery,
+    shutdown: Arc<Shutdown>,
+) -> Result<()> {
+    if config.admin_socket.is_empty() {
+        return Ok(());
+    }
+    let admin = Arc::new(Admin {
+        control,
+        config: config.clone(),
+        aliases,
+        ip_filter,
+        workers,
+        log_level: &proxy::util::logger::LEVEL,
+    });
+    // 管理接口不可用时代理继续运行
+    if let Err(e) = admin::serve(&config.admin_socket, admin, shutdown).await {
+        log::error!("管理接口启动失败 {}", e);
+    }
+    Ok(())
+}
+
+// 启动时读取上次停机保存的矿机统计
+fn load_workers(config: &Settings) -> Registry {
+    let path = config.state_file();
+    match Registry::load(&path) {
+        Ok(workers) => {
+            if !workers.is_empty() {
+                info!(
+                    "✅ 已从 {} 读取 {} 台矿机的统计",
+                    path.display(),
+                    workers.len()
+                );
+            }
+            workers
+        }
+        Err(e) => {
+            log::error!("矿机统计读取失败 {} {}", path.display(), e);
+            Registry::new()
+        }
+    }
+}
+
+// 移除离线超过 worker_expire_days 的矿机
+fn expire_workers(workers: &mut Registry, config: &Settings) {
+    let count = workers.expire(config.worker_expire_days * 24 * 3600);
+    if count > 0 {
+        info!(
+            "🧹 已移除 {} 台离线超过 {} 天的矿机",
+            count, config.worker_expire_days
+        );
+    }
+}
+
+// 合并平滑升级时旧进程交接的矿机统计
+fn merge_handoff(workers: &mut Registry, config: &Settings) {
+    let path = config.handoff_file();
+    if !path.exists() {
+        return;
+    }
+    let res = std::fs::read(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| serde_json::from_slice(&data).map_err(anyhow::Error::from));
+    match res {
+        Ok(snapshots) => {
+            workers.merge(snapshots);
+            info!("✅ 已合并旧进程交接的矿机统计 {}", path.display());
+        }
+        Err(e) => log::error!("旧进程交接的矿机统计读取失败 {} {}", path.display(), e),
+    }
+    let _ = std::fs::remove_file(&path);
+}
+
+// 保存矿机统计
+fn save_workers(workers: &Registry, path: &std::path::Path) {
+    let snapshots = workers.snapshots();
+
+    let res = serde_json::to_vec_pretty(&snapshots)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| std::fs::write(&path, data).map_err(anyhow::Error::from));
+    match res {
+        Ok(_) => info!("✅ 矿机统计已保存到 {}", path.display()),
+        Err(e) => log::error!("矿机统计保存失败 {} {}", path.display(), e),
+    }
+}
+
+// 收到 SIGHUP 时重新读取配置文件并加载 IP 黑白名单、矿机鉴权及矿机别名
+pub async fn process_reload(
+    config_file_name: &str,
+    ip_filter: Arc<IpFilter>,
+    auth: Arc<Auth>,
+    aliases: Arc<Aliases>,
+    shutdown: Arc<Shutdown>,
+) -> Result<()> {
+    let mut hup = signal(SignalKind::hangup())?;
+    let mut stopping = shutdown.stopping();
+
+    loop {
+        tokio::select! {
+            _ = hup.recv() => {},
+            _ = shutdown::wait(&mut stopping) => return Ok(()),
+        }
+
+        match config::Settings::new(config_file_name) {
+            Ok(config) => {
+                if let Err(e) = ip_filter.reload(&config) {
+                    log::error!("IP 黑白名单加载失败 {}", e);
+                }
+                auth.reload(&config);
+                if let Err(e) = aliases.reload(&config) {
+                    log::error!("矿机别名加载失败 {}", e);
+                }
+            }
+            Err(e) => log::error!("配置文件读取失败 {}", e),
         }
     }
 }
+
+// 收到 SIGTERM/SIGQUIT/SIGINT 时停止接受新连接，等待已有连接排空后退出
+pub async fn process_shutdown(config: &Settings, shutdown: Arc<Shutdown>) -> Result<()> {
+    let mut term = signal(SignalKind::terminate())?;
+    let mut quit = signal(SignalKind::quit())?;
+    let mut int = signal(SignalKind::interrupt())?;
+    let mut finished = shutdown.finished();
+
+    let name = tokio::select! {
+        _ = term.recv() => "SIGTERM",
+        _ = quit.recv() => "SIGQUIT",
+        _ = int.recv() => "SIGINT",
+        // 平滑升级已完成排空
+        _ = shutdown::wait(&mut finished) => return Ok(()),
+    };
+
+    info!(
+        "🛑 收到 {} 开始停机。当前连接数 {} 最多等待 {} 秒",
+        name,
+        shutdown.sessions(),
+        config.drain_timeout
+    );
+    shutdown
+        .drain(Duration::from_secs(config.drain_timeout))
+        .await;
+
+    Ok(())
+}
+
+// 平滑升级时通知矿机统计任务
+pub enum Upgrade {
+    // 启动新进程前保存统计，新进程读取后继续累计。本进程停机时改为交接增量
+    Save(oneshot::Sender<()>),
+    // 新进程启动失败，停机时仍写入 state_file
+    Cancel,
+}
+
+// 收到 SIGUSR2 时以相同参数启动新进程并传递监听 socket。
+// 新进程启动成功后旧进程不再接受新连接，已有矿机继续服务直到断开或超时
+pub async fn process_upgrade(
+    config: &Settings,
+    listener_fds: Vec<(String, std::os::unix::io::RawFd)>,
+    upgrade_tx: mpsc::Sender<Upgrade>,
+    shutdown: Arc<Shutdown>,
+) -> Result<()> {
+    let mut usr2 = signal(SignalKind::user_defined2())?;
+    let mut closed = shutdown.closed();
+
+    loop {
+        tokio::select! {
+            _ = usr2.recv() => {},
+            _ = shutdown::wait(&mut closed) => return Ok(()),
+        }
+
+        info!("♻️ 收到 SIGUSR2 启动新进程");
+        // 新进程启动时读取当前的矿机统计
+        let (tx, rx) = oneshot::channel();
+        if upgrade_tx.send(Upgrade::Save(tx)).await.is_ok() {
+            let _ = rx.await;
+        }
+        let mut child = match upgrade::spawn(&listener_fds) {
+            Ok(child) => child,
+            Err(e) => {
+                log::error!("新进程启动失败 {}", e);
+                let _ = upgrade_tx.send(Upgrade::Cancel).await;
+                continue;
+            }
+        };
+
+        // 新进程启动失败时(例如配置错误)旧进程继续服务
+        sleep(Duration::from_secs(2)).await;
+        if let Ok(Some(status)) = child.try_wait() {
+            log::error!("新进程 {} 启动后退出 {}", child.id(), status);
+            let _ = upgrade_tx.send(Upgrade::Cancel).await;
+            continue;
+        }
+
+        info!(
+            "♻️ 新进程 {} 已启动。停止接受新连接，已有连接数 {} 最多等待 {} 秒",
+            child.id(),
+            shutdown.sessions(),
+            config.upgrade_timeout
+        );
+        shutdown
+            .linger(Duration::from_secs(config.upgrade_timeout))
+            .await;
+        shutdown
+            .drain(Duration::from_secs(config.drain_timeout))
+            .await;
+
+        return Ok(());
+    }
+}
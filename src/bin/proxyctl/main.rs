@@ -0,0 +1,294 @@
+use anyhow::{bail, Result};
+use clap::ArgMatches;
+use prettytable::{cell, row, Table};
+use serde_json::Value;
+
+use proxy::admin::{self, Acl, AliasList, Ledger, Pools, Request, WorkerRow};
+use proxy::state::{
+    alias::{GroupStats, WorkerAlias},
+    latency,
+};
+use proxy::util::{config::Settings, *};
+
+#[tokio::main]
+async fn main() {
+    let matches = get_proxyctl_command_matches().await.unwrap();
+    if let Err(e) = run(&matches).await {
+        eprintln!("❎ {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(matches: &ArgMatches<'static>) -> Result<()> {
+    let (cmd, sub) = matches.subcommand();
+    let sub = match sub {
+        Some(sub) => sub,
+        None => bail!("请指定命令 例如: proxyctl workers"),
+    };
+    let arg = |name: &str| sub.value_of(name).unwrap_or_default().to_string();
+    let req = match cmd {
+        "workers" => Request::Workers,
+        "pools" => Request::Pools,
+        "kick" => Request::Kick {
+            worker: arg("worker"),
+        },
+        "drain" => Request::Drain {
+            listener: arg("listener"),
+        },
+        "resume" => Request::Resume {
+            listener: arg("listener"),
+        },
+        "use-pool" => Request::UsePool {
+            group: arg("group"),
+            pool: arg("pool"),
+        },
+        "log-level" => Request::LogLevel {
+            level: arg("level"),
+        },
+        "ledger" => Request::Ledger,
+        "groups" => Request::Groups,
+        "aliases" => Request::Aliases,
+        "acl" => Request::Acl,
+        "alias" => Request::SetAlias {
+            alias: WorkerAlias {
+                wallet: arg("wallet"),
+                worker: arg("worker"),
+                ip: arg("ip"),
+                alias: arg("alias"),
+                location: arg("location"),
+                group: arg("group"),
+            },
+        },
+        _ => bail!("未知命令 {}", cmd),
+    };
+
+    let path = socket_path(matches)?;
+    let data = admin::request(&path, &req).await?;
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&data)?);
+        return Ok(());
+    }
+
+    match req {
+        Request::Workers => print_workers(serde_json::from_value(data)?),
+        Request::Pools => print_pools(serde_json::from_value(data)?),
+        Request::Ledger => print_ledger(serde_json::from_value(data)?),
+        Request::Groups => print_groups(serde_json::from_value(data)?),
+        Request::Aliases => print_aliases(serde_json::from_value(data)?),
+        Request::Acl => print_acl(serde_json::from_value(data)?),
+        Request::SetAlias { alias }
+            if alias.alias.is_empty() && alias.location.is_empty() && alias.group.is_empty() =>
+        {
+            println!("✅ 已删除矿机别名规则")
+        }
+        Request::SetAlias { .. } => {
+            println!("✅ 已设置矿机别名规则 重启后失效 需要长期使用请写入 worker_aliases")
+        }
+        Request::Kick { worker } => println!("✅ 已断开 {} 的 {} 个连接", worker, data["sessions"]),
+        Request::Drain { listener } => println!(
+            "✅ {} 端口停止接受新连接 已通知 {} 个连接排空",
+            listener, data["sessions"]
+        ),
+        Request::Resume { listener } => match data["resumed"] {
+            Value::Bool(true) => println!("✅ {} 端口恢复接受新连接", listener),
+            _ => println!("{} 端口未在排空", listener),
+        },
+        Request::UsePool { group, pool } if pool.is_empty() => {
+            println!("✅ {} 矿池组恢复配置文件的顺序", group)
+        }
+        Request::UsePool { group, pool } => {
+            println!(
+                "✅ {} 矿池组新连接优先使用 {} 已有连接不受影响",
+                group, pool
+            )
+        }
+        Request::LogLevel { .. } => println!("日志等级 {}", data["level"].as_str().unwrap_or("")),
+    }
+    Ok(())
+}
+
+// 命令行指定的 socket 路径，否则读取配置文件的 admin_socket
+fn socket_path(matches: &ArgMatches<'static>) -> Result<String> {
+    if let Some(path) = matches.value_of("socket") {
+        return Ok(path.to_string());
+    }
+    let config_path = matches.value_of("config").unwrap_or("default.yaml");
+    let config = match Settings::new(config_path) {
+        Ok(config) => config,
+        Err(e) => bail!("配置文件错误 {} 可用 -s 指定 socket 路径", e),
+    };
+    if config.admin_socket.is_empty() {
+        bail!(
+            "{} 未配置 admin_socket 可用 -s 指定 socket 路径",
+            config_path
+        );
+    }
+    Ok(config.admin_socket)
+}
+
+fn print_workers(rows: Vec<WorkerRow>) {
+    let mut table = Table::new();
+    table.add_row(row![
+        "矿工",
+        "别名",
+        "位置/分组",
+        "在线",
+        "报告算力",
+        "总工作量(份额)",
+        "有效份额",
+        "无效份额",
+        "本地拒绝",
+        "重复份额",
+        "过期份额",
+        "连接(在线/累计)",
+        "重连",
+        "1小时断开",
+        "任务延迟(ms)\np50/p90/p99",
+        "提交往返(ms)\np50/p90/p99",
+        "首份额(ms)\np50/p90/p99",
+        "在线时长",
+        "最后提交",
+        "连接",
+    ]);
+    for row in &rows {
+        let w = &row.worker;
+        let sessions: Vec<String> = row
+            .sessions
+            .iter()
+            .map(|s| format!("{} {}", s.listener, s.addr))
+            .collect();
+        table.add_row(row![
+            w.worker,
+            w.alias,
+            format!("{}/{}", w.location, w.group).trim_matches('/'),
+            if w.online { "是" } else { "否" },
+            bytes_to_mb(w.hash).to_string() + " Mb",
+            w.share_index,
+            w.accept_index,
+            w.invalid_index,
+            w.local_reject_index,
+            w.duplicate_index,
+            w.stale_index,
+            format!("{}/{}", w.online_sessions, w.sessions),
+            w.reconnects,
+            w.recent_disconnects,
+            latency::brief(&w.job_delay),
+            latency::brief(&w.submit_rtt),
+            latency::brief(&w.first_share),
+            time_to_string(w.online_secs),
+            time_to_string(w.last_submit_secs),
+            sessions.join("\n"),
+        ]);
+    }
+    print!("{}", table);
+    println!("共 {} 台矿机 统计每分钟更新", rows.len());
+}
+
+fn print_acl(acl: Acl) {
+    let mut table = Table::new();
+    table.add_row(row!["监听", "白名单", "黑名单"]);
+    for r in &acl.rules {
+        table.add_row(row![r.listener, r.allow, r.deny]);
+    }
+    print!("{}", table);
+
+    if acl.bans.is_empty() {
+        println!("没有封禁的 IP");
+        return;
+    }
+    let mut table = Table::new();
+    table.add_row(row!["封禁 IP", "剩余时间"]);
+    for b in &acl.bans {
+        table.add_row(row![b.ip, time_to_string(b.remaining_secs)]);
+    }
+    print!("{}", table);
+}
+
+fn print_pools(pools: Pools) {
+    let mut table = Table::new();
+    table.add_row(row!["矿池组", "地址", "当前", "优先", "连通", "延迟"]);
+    for p in &pools.pools {
+        table.add_row(row![
+            p.group,
+            p.address,
+            if p.current { "*" } else { "" },
+            if p.preferred { "*" } else { "" },
+            if p.reachable { "✅" } else { "❎" },
+            p.latency_ms
+                .map(|ms| format!("{} ms", ms))
+                .unwrap_or_default(),
+        ]);
+    }
+    print!("{}", table);
+    if !pools.draining.is_empty() {
+        println!("排空中的监听端口: {}", pools.draining.join(" "));
+    }
+}
+
+fn print_ledger(ledger: Ledger) {
+    let total_jobs: u64 = ledger.values().map(|e| e.jobs).sum();
+    let total_shares: u64 = ledger.values().map(|e| e.shares).sum();
+    let percent = |n: u64, total: u64| format!("{:.2}%", n as f64 * 100.0 / total.max(1) as f64);
+
+    let mut table = Table::new();
+    table.add_row(row!["账户", "任务", "任务占比", "份额", "份额占比"]);
+    for (account, e) in &ledger {
+        table.add_row(row![
+            account,
+            e.jobs,
+            percent(e.jobs, total_jobs),
+            e.shares,
+            percent(e.shares, total_shares),
+        ]);
+    }
+    table.add_row(row!["汇总", total_jobs, "", total_shares, ""]);
+    print!("{}", table);
+}
+
+fn print_groups(groups: Vec<GroupStats>) {
+    let mut table = Table::new();
+    table.add_row(row![
+        "分组",
+        "矿机(在线/总数)",
+        "报告算力",
+        "总工作量(份额)",
+        "有效份额",
+        "无效份额",
+        "本地拒绝",
+        "重复份额",
+        "过期份额",
+        "1小时断开",
+    ]);
+    for g in &groups {
+        table.add_row(row![
+            if g.group.is_empty() {
+                "未分组"
+            } else {
+                &g.group
+            },
+            format!("{}/{}", g.online, g.workers),
+            bytes_to_mb(g.hash).to_string() + " Mb",
+            g.share_index,
+            g.accept_index,
+            g.invalid_index,
+            g.local_reject_index,
+            g.duplicate_index,
+            g.stale_index,
+            g.recent_disconnects,
+        ]);
+    }
+    print!("{}", table);
+}
+
+fn print_aliases(aliases: AliasList) {
+    let mut table = Table::new();
+    table.add_row(row!["来源", "钱包", "矿机名", "IP", "别名", "位置", "分组"]);
+    let runtime = aliases.runtime.iter().map(|a| ("管理接口", a));
+    let config = aliases.config.iter().map(|a| ("配置文件", a));
+    for (source, a) in runtime.chain(config) {
+        table.add_row(row![
+            source, a.wallet, a.worker, a.ip, a.alias, a.location, a.group
+        ]);
+    }
+    print!("{}", table);
+}
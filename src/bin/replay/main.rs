@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::{crate_name, crate_version};
+use log::info;
+
+use proxy::client::record;
+use proxy::mock::replay::replay;
+use proxy::util::{config::Settings, *};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let matches = get_replay_command_matches().await?;
+    logger::init_client(1)?;
+
+    info!("✅ {} 会话回放, 版本: {}", crate_name!(), crate_version!());
+
+    let capture = matches.value_of("capture").unwrap();
+    let events = record::load(capture).unwrap_or_else(|e| {
+        info!("❎ 无法读取录制文件 {}: {}", capture, e);
+        std::process::exit(1);
+    });
+
+    let config_path = matches.value_of("config").unwrap_or("default.yaml");
+    let config = Settings::new(config_path).unwrap_or_else(|e| {
+        info!("❎ 配置文件错误: {}", e);
+        std::process::exit(1);
+    });
+
+    let timeout: u64 = matches
+        .value_of("timeout")
+        .unwrap_or("5")
+        .parse()
+        .unwrap_or_else(|_| {
+            info!("请正确填写超时时间 例如: -t 5");
+            std::process::exit(1);
+        });
+
+    let report = replay(&events, &config, Duration::from_secs(timeout)).await?;
+    for m in &report.mismatches {
+        info!("❗ 第 {} 行 {:?} 不一致", m.index + 1, m.dir);
+        info!("    录制: {}", m.expected);
+        match &m.actual {
+            Some(actual) => info!("    回放: {}", actual),
+            None => info!("    回放: 超时未收到"),
+        }
+    }
+    if let Some(worker) = &report.worker {
+        info!(
+            "矿机 {} 提交 {} 接受 {} 拒绝 {}",
+            worker.worker, worker.share_index, worker.accept_index, worker.invalid_index
+        );
+    }
+
+    if report.is_ok() {
+        info!("✅ 回放一致 共比对 {} 行", report.checked);
+        Ok(())
+    } else {
+        info!(
+            "❎ 回放不一致 {}/{} 行",
+            report.mismatches.len(),
+            report.checked
+        );
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    str::FromStr,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use log::{info, warn};
+
+use crate::util::config::Settings;
+
+// 监听端口类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Listener {
+    Tcp,
+    Ssl,
+    Encrypt,
+}
+
+impl std::fmt::Display for Listener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Listener::Tcp => write!(f, "TCP"),
+            Listener::Ssl => write!(f, "SSL"),
+            Listener::Encrypt => write!(f, "Encrypt"),
+        }
+    }
+}
+
+impl FromStr for Listener {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "tcp" => Ok(Listener::Tcp),
+            "ssl" => Ok(Listener::Ssl),
+            "encrypt" => Ok(Listener::Encrypt),
+            _ => bail!("未知的监听端口 {} 可选 tcp ssl encrypt", s),
+        }
+    }
+}
+
+// CIDR 网段 例如 10.0.0.0/8 或 ::1/128。不带掩码时视为单个IP
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, to_canonical(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = mask_u32(self.prefix);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = mask_u128(self.prefix);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let network: IpAddr = match addr.parse() {
+            Ok(ip) => ip,
+            Err(_) => bail!("无法解析的网段 {}", s),
+        };
+        let network = to_canonical(&network);
+        let max = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(p) => match p.parse::<u8>() {
+                Ok(p) if p <= max => p,
+                _ => bail!("无法解析的网段掩码 {}", s),
+            },
+            None => max,
+        };
+
+        Ok(Cidr { network, prefix })
+    }
+}
+
+impl std::fmt::Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+// IPv4 映射的 IPv6 地址(::ffff:1.2.3.4) 按 IPv4 处理
+fn to_canonical(ip: &IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => *ip,
+        },
+        IpAddr::V4(_) => *ip,
+    }
+}
+
+fn mask_u32(prefix: u8) -> u32 {
+    if prefix == 0 {
+        0
+    } else {
+        u32::MAX << (32 - prefix as u32)
+    }
+}
+
+fn mask_u128(prefix: u8) -> u128 {
+    if prefix == 0 {
+        0
+    } else {
+        u128::MAX << (128 - prefix as u32)
+    }
+}
+
+// 单个监听端口的黑白名单。黑名单优先，白名单为空时放行所有。
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>,
+}
+
+impl Rules {
+    pub fn new(allow: &[String], deny: &[String]) -> Result<Self> {
+        Ok(Self {
+            allow: parse_list(allow)?,
+            deny: parse_list(deny)?,
+        })
+    }
+
+    pub fn is_allowed(&self, ip: &IpAddr) -> bool {
+        if self.deny.iter().any(|c| c.contains(ip)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|c| c.contains(ip))
+    }
+}
+
+fn parse_list(list: &[String]) -> Result<Vec<Cidr>> {
+    list.iter()
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| s.parse())
+        .collect()
+}
+
+// 累计次数的时间窗口。窗口内未达到阈值时重新计数
+pub const STRIKE_WINDOW: Duration = Duration::from_secs(600);
+// 清理过期记录的间隔
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+struct Ban {
+    strikes: u32,
+    // 本轮计数开始的时间
+    since: Instant,
+    until: Option<Instant>,
+}
+
+impl Ban {
+    // 封禁已结束，或未封禁且计数已超出时间窗口
+    fn expired(&self, now: Instant) -> bool {
+        match self.until {
+            Some(until) => until <= now,
+            None => now.duration_since(self.since) >= STRIKE_WINDOW,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bans {
+    ips: HashMap<IpAddr, Ban>,
+    pruned: Instant,
+}
+
+impl Bans {
+    fn prune(&mut self, now: Instant) {
+        self.ips.retain(|_, b| !b.expired(now));
+        self.pruned = now;
+    }
+}
+
+#[derive(Debug)]
+struct Policy {
+    rules: HashMap<Listener, Rules>,
+    // 可信的 PROXY protocol 来源
+    proxy_from: Vec<Cidr>,
+    ban_secs: u64,
+    ban_threshold: u32,
+}
+
+impl Policy {
+    fn new(config: &Settings) -> Result<Self> {
+        let mut rules = HashMap::new();
+        rules.insert(
+            Listener::Tcp,
+            Rules::new(&config.tcp_allow, &config.tcp_deny)?,
+        );
+        rules.insert(
+            Listener::Ssl,
+            Rules::new(&config.ssl_allow, &config.ssl_deny)?,
+        );
+        rules.insert(
+            Listener::Encrypt,
+            Rules::new(&config.encrypt_allow, &config.encrypt_deny)?,
+        );
+
+        Ok(Self {
+            rules,
+            proxy_from: parse_list(&config.proxy_protocol_from)?,
+            ban_secs: config.ban_secs,
+            ban_threshold: config.ban_threshold.max(1),
+        })
+    }
+}
+
+// 各监听端口共享的IP过滤器。支持热加载及发送无法解析数据后的临时封禁
+#[derive(Debug)]
+pub struct IpFilter {
+    policy: RwLock<Policy>,
+    bans: Mutex<Bans>,
+}
+
+impl IpFilter {
+    pub fn new(config: &Settings) -> Result<Self> {
+        Ok(Self {
+            policy: RwLock::new(Policy::new(config)?),
+            bans: Mutex::new(Bans {
+                ips: HashMap::new(),
+                pruned: Instant::now(),
+            }),
+        })
+    }
+
+    // 重新加载规则。解析失败时保留原规则
+    pub fn reload(&self, config: &Settings) -> Result<()> {
+        let policy = Policy::new(config)?;
+        *self.policy.write().unwrap() = policy;
+        info!("✅ IP 黑白名单已重新加载");
+        Ok(())
+    }
+
+    // 判断某个监听端口是否接受这个IP的连接
+    pub fn check(&self, listener: Listener, ip: &IpAddr) -> bool {
+        let ip = to_canonical(ip);
+        if self.is_banned(&ip) {
+            return false;
+        }
+
+        match self.policy.read().unwrap().rules.get(&listener) {
+            Some(rules) => rules.is_allowed(&ip),
+            None => true,
+        }
+    }
+
+    // 是否为 proxy_protocol_from 中的代理。只有这些连接的 PROXY protocol 头部可信
+    pub fn trusted_proxy(&self, ip: &IpAddr) -> bool {
+        let ip = to_canonical(ip);
+        let policy = self.policy.read().unwrap();
+        policy.proxy_from.iter().any(|c| c.contains(&ip))
+    }
+
+    fn is_banned(&self, ip: &IpAddr) -> bool {
+        let mut bans = self.bans.lock().unwrap();
+        match bans.ips.get(ip).and_then(|b| b.until) {
+            Some(until) if until > Instant::now() => true,
+            Some(_) => {
+                bans.ips.remove(ip);
+                false
+            }
+            None => false,
+        }
+    }
+
+    // 记录一次无法解析的数据或鉴权失败。STRIKE_WINDOW 内累计达到阈值后临时封禁。
+    // reason 为本次计数的原因，封禁时写入日志
+    pub fn strike(&self, ip: &IpAddr, reason: &str) {
+        self.strike_at(ip, reason, Instant::now());
+    }
+
+    fn strike_at(&self, ip: &IpAddr, reason: &str, now: Instant) {
+        let (ban_secs, ban_threshold) = {
+            let policy = self.policy.read().unwrap();
+            (policy.ban_secs, policy.ban_threshold)
+        };
+        if ban_secs == 0 {
+            return;
+        }
+
+        let ip = to_canonical(ip);
+        let mut bans = self.bans.lock().unwrap();
+        if now.duration_since(bans.pruned) >= PRUNE_INTERVAL {
+            bans.prune(now);
+        }
+        let ban = bans.ips.entry(ip).or_insert(Ban {
+            strikes: 0,
+            since: now,
+            until: None,
+        });
+        if ban.expired(now) {
+            *ban = Ban {
+                strikes: 0,
+                since: now,
+                until: None,
+            };
+        }
+        ban.strikes += 1;
+        if ban.strikes >= ban_threshold && ban.until.is_none() {
+            ban.until = Some(now + Duration::from_secs(ban_secs));
+            warn!(
+                "🚫 {} 累计违规 {} 次，最近一次: {}。封禁 {} 秒",
+                ip, ban.strikes, reason, ban_secs
+            );
+        }
+    }
+
+    // 当前生效的规则及封禁列表，用于状态输出
+    pub fn summary(&self) -> Vec<(String, String, String)> {
+        let policy = self.policy.read().unwrap();
+        let mut rows = vec![];
+        for listener in [Listener::Tcp, Listener::Ssl, Listener::Encrypt] {
+            if let Some(rules) = policy.rules.get(&listener) {
+                rows.push((listener.to_string(), join(&rules.allow), join(&rules.deny)));
+            }
+        }
+        rows
+    }
+
+    pub fn banned(&self) -> Vec<(IpAddr, u64)> {
+        let now = Instant::now();
+        let mut bans = self.bans.lock().unwrap();
+        bans.prune(now);
+        bans.ips
+            .iter()
+            .filter_map(|(ip, b)| match b.until {
+                Some(until) if until > now => Some((*ip, (until - now).as_secs())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn join(list: &[Cidr]) -> String {
+    list.iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+#[test]
+fn test_cidr_contains() {
+    let c: Cidr = "10.0.0.0/8".parse().unwrap();
+    assert!(c.contains(&"10.1.2.3".parse().unwrap()));
+    assert!(!c.contains(&"11.1.2.3".parse().unwrap()));
+    assert!(c.contains(&"::ffff:10.0.0.1".parse().unwrap()));
+
+    let c: Cidr = "192.168.1.10".parse().unwrap();
+    assert!(c.contains(&"192.168.1.10".parse().unwrap()));
+    assert!(!c.contains(&"192.168.1.11".parse().unwrap()));
+
+    let c: Cidr = "2001:db8::/32".parse().unwrap();
+    assert!(c.contains(&"2001:db8::1".parse().unwrap()));
+    assert!(!c.contains(&"10.0.0.1".parse().unwrap()));
+
+    assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    assert!("abc".parse::<Cidr>().is_err());
+}
+
+#[test]
+fn test_rules() {
+    let rules = Rules::new(&["10.0.0.0/8".into()], &["10.0.0.5".into()]).unwrap();
+    assert!(rules.is_allowed(&"10.0.0.1".parse().unwrap()));
+    assert!(!rules.is_allowed(&"10.0.0.5".parse().unwrap()));
+    assert!(!rules.is_allowed(&"8.8.8.8".parse().unwrap()));
+
+    let rules = Rules::new(&[], &["0.0.0.0/0".into()]).unwrap();
+    assert!(!rules.is_allowed(&"8.8.8.8".parse().unwrap()));
+    assert!(rules.is_allowed(&"::1".parse().unwrap()));
+}
+
+#[test]
+fn test_strike_ban() {
+    let mut config = Settings::default();
+    config.ban_secs = 60;
+    config.ban_threshold = 2;
+    let filter = IpFilter::new(&config).unwrap();
+    let ip: IpAddr = "1.2.3.4".parse().unwrap();
+
+    filter.strike(&ip, "test");
+    assert!(filter.check(Listener::Tcp, &ip));
+    filter.strike(&ip, "test");
+    assert!(!filter.check(Listener::Tcp, &ip));
+    assert_eq!(filter.banned().len(), 1);
+}
+
+#[test]
+fn test_strike_window() {
+    let mut config = Settings::default();
+    config.ban_secs = 60;
+    config.ban_threshold = 2;
+    let filter = IpFilter::new(&config).unwrap();
+    let ip: IpAddr = "1.2.3.4".parse().unwrap();
+    let other: IpAddr = "2001:db8::1".parse().unwrap();
+
+    // 超出时间窗口的计数不再累计
+    let start = Instant::now();
+    filter.strike_at(&ip, "test", start);
+    filter.strike_at(&other, "test", start);
+    filter.strike_at(&ip, "test", start + STRIKE_WINDOW);
+    assert!(filter.check(Listener::Tcp, &ip));
+
+    // 未达到阈值的过期记录被清理
+    assert!(filter.banned().is_empty());
+    let bans = filter.bans.lock().unwrap();
+    assert_eq!(bans.ips.len(), 1);
+    assert_eq!(bans.ips[&ip].strikes, 1);
+}
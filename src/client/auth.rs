@@ -0,0 +1,156 @@
+use std::sync::RwLock;
+
+use log::info;
+use serde::Deserialize;
+
+use crate::util::config::Settings;
+
+// 矿机访问令牌。worker 为空时对该钱包下所有矿机生效
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AuthToken {
+    pub wallet: String,
+    #[serde(default)]
+    pub worker: String,
+    pub token: String,
+}
+
+#[derive(Debug, Default)]
+struct Policy {
+    password: String,
+    tokens: Vec<AuthToken>,
+    wallets: Vec<String>,
+}
+
+impl Policy {
+    fn new(config: &Settings) -> Self {
+        Self {
+            password: config.auth_password.clone(),
+            tokens: config
+                .auth_tokens
+                .iter()
+                .map(|t| AuthToken {
+                    wallet: t.wallet.to_lowercase(),
+                    worker: t.worker.clone(),
+                    token: t.token.clone(),
+                })
+                .collect(),
+            wallets: config
+                .auth_wallets
+                .iter()
+                .map(|w| w.to_lowercase())
+                .collect(),
+        }
+    }
+}
+
+// 矿机登录鉴权。共享密码、钱包令牌、钱包白名单均未配置时不做校验
+#[derive(Debug)]
+pub struct Auth {
+    policy: RwLock<Policy>,
+}
+
+impl Auth {
+    pub fn new(config: &Settings) -> Self {
+        Self {
+            policy: RwLock::new(Policy::new(config)),
+        }
+    }
+
+    pub fn reload(&self, config: &Settings) {
+        *self.policy.write().unwrap() = Policy::new(config);
+        info!("✅ 矿机鉴权配置已重新加载");
+    }
+
+    // 是否配置了鉴权。未配置时矿机不登录也可以取任务
+    pub fn enabled(&self) -> bool {
+        let policy = self.policy.read().unwrap();
+        !policy.password.is_empty() || !policy.tokens.is_empty() || !policy.wallets.is_empty()
+    }
+
+    // 校验登录。失败时返回拒绝原因
+    pub fn check(&self, wallet: &str, worker: &str, password: &str) -> Result<(), &'static str> {
+        let policy = self.policy.read().unwrap();
+        let wallet = wallet.to_lowercase();
+
+        if !policy.wallets.is_empty() && !policy.wallets.contains(&wallet) {
+            return Err("钱包不在白名单中");
+        }
+
+        // 优先匹配指定矿机的令牌，其次是整个钱包的令牌
+        let token = policy
+            .tokens
+            .iter()
+            .find(|t| t.wallet == wallet && t.worker == worker)
+            .or_else(|| {
+                policy
+                    .tokens
+                    .iter()
+                    .find(|t| t.wallet == wallet && t.worker.is_empty())
+            });
+
+        match token {
+            Some(t) if t.token == password => Ok(()),
+            Some(_) => Err("访问令牌错误"),
+            None if !policy.password.is_empty() => {
+                if policy.password == password {
+                    Ok(())
+                } else {
+                    Err("登录密码错误")
+                }
+            }
+            None if !policy.tokens.is_empty() => Err("钱包未配置访问令牌"),
+            None => Ok(()),
+        }
+    }
+}
+
+// mining.authorize 的用户名格式为 钱包.矿机名
+pub fn split_user(user: &str) -> (&str, &str) {
+    match user.split_once('.') {
+        Some((wallet, worker)) => (wallet, worker),
+        None => (user, ""),
+    }
+}
+
+#[test]
+fn test_auth_disabled() {
+    let auth = Auth::new(&Settings::default());
+    assert!(auth.check("0xabc", "rig1", "x").is_ok());
+}
+
+#[test]
+fn test_auth_password() {
+    let mut config = Settings::default();
+    config.auth_password = "secret".into();
+    let auth = Auth::new(&config);
+    assert!(auth.check("0xabc", "rig1", "secret").is_ok());
+    assert!(auth.check("0xabc", "rig1", "x").is_err());
+}
+
+#[test]
+fn test_auth_tokens_and_wallets() {
+    let mut config = Settings::default();
+    config.auth_wallets = vec!["0xABC".into(), "0xdef".into()];
+    config.auth_tokens = vec![
+        AuthToken {
+            wallet: "0xabc".into(),
+            worker: "".into(),
+            token: "t-wallet".into(),
+        },
+        AuthToken {
+            wallet: "0xabc".into(),
+            worker: "rig1".into(),
+            token: "t-rig1".into(),
+        },
+    ];
+    let auth = Auth::new(&config);
+
+    assert!(auth.check("0xAbC", "rig1", "t-rig1").is_ok());
+    assert!(auth.check("0xabc", "rig1", "t-wallet").is_err());
+    assert!(auth.check("0xabc", "rig2", "t-wallet").is_ok());
+    assert!(auth.check("0xdef", "rig1", "x").is_err());
+    assert!(auth.check("0x123", "rig1", "t-wallet").is_err());
+
+    assert_eq!(split_user("0xabc.rig1"), ("0xabc", "rig1"));
+    assert_eq!(split_user("0xabc"), ("0xabc", ""));
+}
@@ -0,0 +1,365 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use super::{acl::Listener, SSL, TCP};
+use crate::util::config::Settings;
+
+// 管理接口发给会话的指令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Run,
+    // 通知矿机重连，已提交的份额全部返回后断开
+    Drain,
+    // 立即断开
+    Kick,
+}
+
+// 运行中代理的控制中心。管理接口通过它查看会话、断开矿机、排空监听端口和切换矿池
+#[derive(Debug, Default)]
+pub struct Control {
+    next_id: AtomicU64,
+    sessions: Mutex<HashMap<u64, Entry>>,
+    // 排空中的监听端口。不再接受新连接
+    draining: Mutex<HashSet<Listener>>,
+    // 矿池组 -> 优先连接的矿池
+    preferred: Mutex<HashMap<i32, String>>,
+    ledger: Arc<Ledger>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    listener: Listener,
+    addr: SocketAddr,
+    worker: String,
+    since: Instant,
+    orders: watch::Sender<Order>,
+}
+
+// 一个会话的概况
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: u64,
+    pub listener: String,
+    pub addr: String,
+    pub worker: String,
+    pub online_secs: u64,
+}
+
+// 会话存活期间持有。断开时自动注销
+#[derive(Debug)]
+pub struct Handle {
+    control: Arc<Control>,
+    id: u64,
+    worker: String,
+    orders: watch::Receiver<Order>,
+}
+
+impl Handle {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    // 矿机登录后更新会话对应的矿机名
+    pub fn set_worker(&mut self, worker: &str) {
+        if self.worker == worker {
+            return;
+        }
+        self.worker = worker.to_string();
+        if let Some(entry) = self.control.sessions.lock().unwrap().get_mut(&self.id) {
+            entry.worker = worker.to_string();
+        }
+    }
+
+    // 等待下一条指令
+    pub async fn order(&mut self) -> Order {
+        if self.orders.changed().await.is_err() {
+            return std::future::pending().await;
+        }
+        *self.orders.borrow_and_update()
+    }
+
+    pub fn ledger(&self) -> Arc<Ledger> {
+        self.control.ledger.clone()
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.control.sessions.lock().unwrap().remove(&self.id);
+    }
+}
+
+fn group(name: &str) -> Result<i32> {
+    match name.to_ascii_lowercase().as_str() {
+        "tcp" => Ok(TCP),
+        "ssl" => Ok(SSL),
+        _ => bail!("未知的矿池组 {} 可选 tcp ssl", name),
+    }
+}
+
+fn group_pools(config: &Settings, group: i32) -> &Vec<String> {
+    if group == SSL {
+        &config.pool_ssl_address
+    } else {
+        &config.pool_tcp_address
+    }
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 登记新会话。监听端口排空中时会话一开始就收到排空指令
+    pub fn register(self: &Arc<Self>, listener: Listener, addr: SocketAddr) -> Handle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let (orders, rx) = watch::channel(Order::Run);
+        if self.is_draining(listener) {
+            orders.send_replace(Order::Drain);
+        }
+        self.sessions.lock().unwrap().insert(
+            id,
+            Entry {
+                listener,
+                addr,
+                worker: String::new(),
+                since: Instant::now(),
+                orders,
+            },
+        );
+        Handle {
+            control: self.clone(),
+            id,
+            worker: String::new(),
+            orders: rx,
+        }
+    }
+
+    pub fn sessions(&self) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, e)| SessionInfo {
+                id: *id,
+                listener: e.listener.to_string(),
+                addr: e.addr.to_string(),
+                worker: e.worker.clone(),
+                online_secs: e.since.elapsed().as_secs(),
+            })
+            .collect();
+        sessions.sort_by_key(|s| s.id);
+        sessions
+    }
+
+    // 断开矿机。worker 可以是 钱包.矿机名、矿机名或矿机地址。返回断开的会话数
+    pub fn kick(&self, worker: &str) -> usize {
+        let sessions = self.sessions.lock().unwrap();
+        let mut count = 0;
+        for e in sessions.values() {
+            let name = e.worker.rsplit_once('.').map(|(_, name)| name);
+            if e.worker == worker || name == Some(worker) || e.addr.to_string() == worker {
+                e.orders.send_replace(Order::Kick);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // 排空监听端口。停止接受新连接，已有矿机份额返回后断开。返回通知的会话数
+    pub fn drain(&self, listener: Listener) -> usize {
+        self.draining.lock().unwrap().insert(listener);
+        let sessions = self.sessions.lock().unwrap();
+        let mut count = 0;
+        for e in sessions.values().filter(|e| e.listener == listener) {
+            if *e.orders.borrow() == Order::Run {
+                e.orders.send_replace(Order::Drain);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // 恢复接受新连接。返回监听端口之前是否在排空
+    pub fn resume(&self, listener: Listener) -> bool {
+        self.draining.lock().unwrap().remove(&listener)
+    }
+
+    pub fn is_draining(&self, listener: Listener) -> bool {
+        self.draining.lock().unwrap().contains(&listener)
+    }
+
+    pub fn draining(&self) -> Vec<Listener> {
+        self.draining.lock().unwrap().iter().copied().collect()
+    }
+
+    // 新连接优先使用矿池组中的 pool，可以切换到另一个矿池组。
+    // 同一时间只有一个优先矿池。pool 为空时恢复配置文件的顺序。已有连接不受影响
+    pub fn use_pool(&self, config: &Settings, name: &str, pool: &str) -> Result<()> {
+        let group = group(name)?;
+        let mut preferred = self.preferred.lock().unwrap();
+        if pool.is_empty() {
+            preferred.remove(&group);
+            return Ok(());
+        }
+        if !group_pools(config, group).iter().any(|p| p == pool) {
+            bail!("矿池 {} 不在 {} 矿池组中", pool, name);
+        }
+        preferred.clear();
+        preferred.insert(group, pool.to_string());
+        Ok(())
+    }
+
+    pub fn preferred(&self, group: i32) -> Option<String> {
+        self.preferred.lock().unwrap().get(&group).cloned()
+    }
+
+    // 同 get_pool_ip_and_type。设置了优先矿池时使用它所在的矿池组，
+    // 优先矿池排在最前，其余作为备用
+    pub fn pools(&self, config: &Settings) -> Option<(i32, Vec<String>)> {
+        let preferred = self.preferred.lock().unwrap().clone();
+        for (group, pool) in preferred {
+            let mut pools = group_pools(config, group).clone();
+            if let Some(i) = pools.iter().position(|p| *p == pool) {
+                let pool = pools.remove(i);
+                pools.insert(0, pool);
+                return Some((group, pools));
+            }
+        }
+        super::get_pool_ip_and_type(config)
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+}
+
+// 矿机自己的任务和份额记在这个账户，抽水的记在 Fee::as_str 账户
+pub const POOL_ACCOUNT: &str = "pool";
+
+// 抽水账本。按账户统计分给矿机的任务数和矿机提交的份额数
+#[derive(Debug, Default)]
+pub struct Ledger {
+    accounts: Mutex<BTreeMap<&'static str, LedgerEntry>>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub jobs: u64,
+    pub shares: u64,
+}
+
+impl Ledger {
+    pub fn job(&self, account: &'static str) {
+        self.accounts
+            .lock()
+            .unwrap()
+            .entry(account)
+            .or_default()
+            .jobs += 1;
+    }
+
+    pub fn share(&self, account: &'static str) {
+        self.accounts
+            .lock()
+            .unwrap()
+            .entry(account)
+            .or_default()
+            .shares += 1;
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<&'static str, LedgerEntry> {
+        self.accounts.lock().unwrap().clone()
+    }
+}
+
+#[test]
+fn test_control_orders() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let control = Arc::new(Control::new());
+        let mut a = control.register(Listener::Tcp, "1.2.3.4:1000".parse().unwrap());
+        let mut b = control.register(Listener::Ssl, "1.2.3.4:2000".parse().unwrap());
+        a.set_worker("0x00.rig1");
+        b.set_worker("0x00.rig2");
+        assert_eq!(control.sessions().len(), 2);
+        assert_eq!(control.sessions()[0].worker, "0x00.rig1");
+
+        assert_eq!(control.kick("rig3"), 0);
+        assert_eq!(control.kick("rig1"), 1);
+        assert_eq!(a.order().await, Order::Kick);
+
+        assert_eq!(control.drain(Listener::Ssl), 1);
+        assert_eq!(b.order().await, Order::Drain);
+        assert!(control.is_draining(Listener::Ssl));
+        // 排空中的监听端口登记的会话直接收到排空指令
+        let mut c = control.register(Listener::Ssl, "1.2.3.4:3000".parse().unwrap());
+        assert_eq!(*c.orders.borrow_and_update(), Order::Drain);
+        assert!(control.resume(Listener::Ssl));
+        assert!(!control.resume(Listener::Ssl));
+
+        drop(a);
+        drop(c);
+        assert_eq!(control.sessions().len(), 1);
+        assert_eq!(control.kick("1.2.3.4:2000"), 1);
+    });
+}
+
+#[test]
+fn test_control_pools() {
+    let mut config = Settings::default();
+    config.pool_tcp_address = vec!["a:4444".into(), "b:4444".into(), "c:4444".into()];
+    let control = Control::new();
+    assert_eq!(control.pools(&config).unwrap().1[0], "a:4444");
+
+    control.use_pool(&config, "tcp", "c:4444").unwrap();
+    assert_eq!(
+        control.pools(&config).unwrap(),
+        (TCP, vec!["c:4444".into(), "a:4444".into(), "b:4444".into()])
+    );
+    assert!(control.use_pool(&config, "tcp", "d:4444").is_err());
+    assert!(control.use_pool(&config, "udp", "a:4444").is_err());
+
+    control.use_pool(&config, "TCP", "").unwrap();
+    assert_eq!(control.pools(&config).unwrap().1[0], "a:4444");
+
+    // 切换到 SSL 矿池组
+    config.pool_ssl_address = vec!["s1:443".into(), "s2:443".into()];
+    control.use_pool(&config, "tcp", "b:4444").unwrap();
+    control.use_pool(&config, "ssl", "s2:443").unwrap();
+    assert_eq!(
+        control.pools(&config).unwrap(),
+        (SSL, vec!["s2:443".into(), "s1:443".into()])
+    );
+    control.use_pool(&config, "ssl", "").unwrap();
+    assert_eq!(
+        control.pools(&config).unwrap(),
+        (TCP, config.pool_tcp_address.clone())
+    );
+}
+
+#[test]
+fn test_ledger() {
+    use super::policy::Fee;
+
+    let ledger = Ledger::default();
+    ledger.job(POOL_ACCOUNT);
+    ledger.job(Fee::Proxy.as_str());
+    ledger.share(POOL_ACCOUNT);
+    let accounts = ledger.snapshot();
+    assert_eq!(accounts["pool"], LedgerEntry { jobs: 1, shares: 1 });
+    assert_eq!(accounts["proxy"], LedgerEntry { jobs: 1, shares: 0 });
+    assert!(accounts.get("develop").is_none());
+}
@@ -0,0 +1,88 @@
+use std::sync::{Mutex, OnceLock};
+
+use lru::LruCache;
+
+// 每个会话记住的最近份额数
+pub const SESSION_WINDOW: usize = 1024;
+// 每个钱包记住的最近份额数。同一钱包的所有会话共用
+pub const WALLET_WINDOW: usize = 8192;
+// 最多记录的钱包数。超过时淘汰最久没有提交份额的钱包
+const MAX_WALLETS: usize = 4096;
+
+// 重复份额返回给矿机的错误。与矿池的错误码一致
+pub const DUPLICATE_CODE: u64 = 22;
+pub const DUPLICATE_MESSAGE: &str = "Duplicate share";
+
+// 份额的唯一标识 (任务, nonce, mix)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShareKey(String, String, String);
+
+impl ShareKey {
+    pub fn new(nonce: &str, header: &str, mix: &str) -> Self {
+        Self(normalize(header), normalize(nonce), normalize(mix))
+    }
+}
+
+// 十六进制统一为小写并去掉 0x 前缀和前导零。同一个份额换种写法也算重复
+fn normalize(s: &str) -> String {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    s.trim_start_matches('0').to_ascii_lowercase()
+}
+
+// 按钱包记录最近提交的份额
+pub struct WalletShares {
+    wallets: Mutex<LruCache<String, LruCache<ShareKey, ()>>>,
+    window: usize,
+}
+
+impl WalletShares {
+    pub fn new(capacity: usize, window: usize) -> Self {
+        Self {
+            wallets: Mutex::new(LruCache::new(capacity)),
+            window,
+        }
+    }
+
+    // 记录份额。该钱包最近已提交过时返回 false
+    pub fn insert(&self, wallet: &str, key: ShareKey) -> bool {
+        let wallet = wallet.to_ascii_lowercase();
+        let mut wallets = self.wallets.lock().unwrap();
+        if !wallets.contains(&wallet) {
+            wallets.put(wallet.clone(), LruCache::new(self.window));
+        }
+        let shares = wallets.get_mut(&wallet).unwrap();
+        shares.put(key, ()).is_none()
+    }
+}
+
+// 进程内所有会话共用
+pub fn wallets() -> &'static WalletShares {
+    static WALLETS: OnceLock<WalletShares> = OnceLock::new();
+    WALLETS.get_or_init(|| WalletShares::new(MAX_WALLETS, WALLET_WINDOW))
+}
+
+#[test]
+fn test_wallet_shares() {
+    let shares = WalletShares::new(2, 2);
+    let key = |n: u64| ShareKey::new(&format!("0x{:016x}", n), "0xAB", "0x02");
+
+    assert!(shares.insert("0xaa", key(1)));
+    assert!(!shares.insert("0xaa", key(1)));
+    // 大小写和前导零不同也是同一个份额
+    assert!(!shares.insert("0xAA", ShareKey::new("0x1", "0xab", "0x0002")));
+    // 不同钱包互不影响
+    assert!(shares.insert("0xbb", key(1)));
+
+    // 超出窗口后最早的份额被淘汰
+    assert!(shares.insert("0xaa", key(2)));
+    assert!(shares.insert("0xaa", key(3)));
+    assert!(shares.insert("0xaa", key(1)));
+
+    // 超出钱包数后最久没有提交的钱包被淘汰
+    assert!(shares.insert("0xcc", key(1)));
+    assert!(shares.insert("0xbb", key(1)));
+}
@@ -0,0 +1,851 @@
+// 端到端测试。代理在本地端口监听，连接本地模拟矿池
+use std::{
+    net::SocketAddr,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use native_tls::Identity;
+use openssl::{
+    asn1::Asn1Time, hash::MessageDigest, pkcs12::Pkcs12, pkey::PKey, rsa::Rsa,
+    x509::X509NameBuilder, x509::X509,
+};
+use serde_json::{json, Value};
+use tokio::{
+    io::{
+        split, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines, ReadHalf,
+        WriteHalf,
+    },
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc},
+    time,
+};
+
+use crate::{
+    client::{
+        acl::IpFilter,
+        auth::{Auth, AuthToken},
+        control::Control,
+        error::SessionError,
+        naming::NameRule,
+        shutdown::Shutdown,
+        tcp::accept_tcp,
+        tls::accept_tcp_with_tls,
+        Context,
+    },
+    jobs::JobQueue,
+    mock::pool::{MockPool, Script, Stats},
+    state::Worker,
+    util::config::Settings,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// 所有测试共用的开发者模拟矿池。在独立线程中运行，不随单个测试的 runtime 退出
+fn develop_pool() -> String {
+    static ADDR: OnceLock<String> = OnceLock::new();
+    ADDR.get_or_init(|| {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                let listener = TcpListener::from_std(listener).unwrap();
+                let pool = MockPool::new(Script {
+                    job_interval: 100,
+                    ..Default::default()
+                });
+                let _ = pool.serve_tcp(listener).await;
+            });
+        });
+        addr
+    })
+    .clone()
+}
+
+async fn start_pool(script: Script) -> (Arc<MockPool>, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let pool = MockPool::new(script);
+    tokio::spawn(pool.clone().serve_tcp(listener));
+    (pool, addr)
+}
+
+fn settings(pool: &str, fee_pool: &str) -> Settings {
+    Settings {
+        pool_tcp_address: vec![pool.into()],
+        share_tcp_address: vec![fee_pool.into()],
+        share_wallet: "0xfee".into(),
+        share_name: "fee".into(),
+        ..Default::default()
+    }
+}
+
+// 自签名证书
+fn identity() -> Identity {
+    let rsa = Rsa::generate(2048).unwrap();
+    let pkey = PKey::from_rsa(rsa).unwrap();
+
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "localhost").unwrap();
+    let name = name.build();
+
+    let mut cert = X509::builder().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_issuer_name(&name).unwrap();
+    cert.set_pubkey(&pkey).unwrap();
+    cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    cert.sign(&pkey, MessageDigest::sha256()).unwrap();
+    let cert = cert.build();
+
+    let p12 = Pkcs12::builder()
+        .build("mypass", "proxy", &pkey, &cert)
+        .unwrap();
+    Identity::from_pkcs12(&p12.to_der().unwrap(), "mypass").unwrap()
+}
+
+struct Proxy {
+    tcp: SocketAddr,
+    ssl: SocketAddr,
+    workers: mpsc::Receiver<Worker>,
+    shutdown: Arc<Shutdown>,
+    control: Arc<Control>,
+}
+
+async fn start_proxy(config: Settings) -> Proxy {
+    let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ssl = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let (tcp_addr, ssl_addr) = (tcp.local_addr().unwrap(), ssl.local_addr().unwrap());
+
+    let (worker_tx, workers) = mpsc::channel::<Worker>(100);
+    let (job_send, _) = broadcast::channel::<String>(100);
+    let (proxy_fee_sender, _) = broadcast::channel::<(u64, String)>(100);
+    let (develop_fee_sender, _) = broadcast::channel::<(u64, String)>(100);
+    let (state_send, _) = mpsc::unbounded_channel::<(u64, String)>();
+    let shutdown = Arc::new(Shutdown::new());
+    let control = Arc::new(Control::new());
+    let ctx = Arc::new(Context {
+        ip_filter: Arc::new(IpFilter::new(&config).unwrap()),
+        auth: Arc::new(Auth::new(&config)),
+        config,
+        develop_pools: vec![develop_pool()],
+        worker_queue: worker_tx,
+        mine_jobs_queue: Arc::new(JobQueue::new(100)),
+        develop_jobs_queue: Arc::new(JobQueue::new(100)),
+        proxy_fee_sender,
+        develop_fee_sender,
+        shutdown: shutdown.clone(),
+        control: control.clone(),
+    });
+
+    tokio::spawn(accept_tcp(
+        ctx.clone(),
+        job_send.clone(),
+        state_send.clone(),
+        state_send.clone(),
+        vec![tcp],
+    ));
+    tokio::spawn(accept_tcp_with_tls(
+        ctx,
+        job_send,
+        state_send.clone(),
+        state_send,
+        vec![ssl],
+        identity(),
+    ));
+
+    Proxy {
+        tcp: tcp_addr,
+        ssl: ssl_addr,
+        workers,
+        shutdown,
+        control,
+    }
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+// 测试用矿机。按脚本逐条发送请求并读取代理返回
+struct Miner {
+    lines: Lines<BufReader<ReadHalf<Box<dyn Stream>>>>,
+    w: WriteHalf<Box<dyn Stream>>,
+}
+
+impl Miner {
+    fn new(stream: Box<dyn Stream>) -> Self {
+        let (r, w) = split(stream);
+        Self {
+            lines: BufReader::new(r).lines(),
+            w,
+        }
+    }
+
+    async fn connect(addr: SocketAddr) -> Self {
+        Self::new(Box::new(TcpStream::connect(addr).await.unwrap()))
+    }
+
+    async fn connect_tls(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .build()
+            .unwrap();
+        let connector = tokio_native_tls::TlsConnector::from(connector);
+        Self::new(Box::new(
+            connector.connect("localhost", stream).await.unwrap(),
+        ))
+    }
+
+    async fn send(&mut self, rpc: Value) {
+        self.w
+            .write_all(format!("{}\n", rpc).as_bytes())
+            .await
+            .unwrap();
+    }
+
+    // 连接断开时返回 None
+    async fn recv(&mut self) -> Option<Value> {
+        match time::timeout(TIMEOUT, self.lines.next_line())
+            .await
+            .expect("读取代理数据超时")
+        {
+            Ok(Some(line)) => Some(serde_json::from_str(&line).unwrap_or(Value::Null)),
+            _ => None,
+        }
+    }
+
+    // 读取指定请求的返回，跳过期间下发的任务
+    async fn reply(&mut self, id: u64) -> Value {
+        loop {
+            let rpc = self.recv().await.expect("代理断开了连接");
+            if rpc["id"] == id {
+                return rpc;
+            }
+        }
+    }
+
+    // 读取下一个主动下发的任务
+    async fn job(&mut self) -> String {
+        loop {
+            let rpc = self.recv().await.expect("代理断开了连接");
+            if rpc["id"] == 0 && rpc["result"].is_array() {
+                return rpc["result"][0].as_str().unwrap().to_string();
+            }
+        }
+    }
+
+    async fn login(&mut self, worker: &str) {
+        self.send(json!({"id": 1, "method": "eth_submitLogin", "params": ["0x00", "x"], "worker": worker}))
+            .await;
+        assert_eq!(self.reply(1).await["result"], true);
+    }
+
+    // 每次提交使用不同的 nonce，避免被当作重复份额
+    async fn submit(&mut self, id: u64, header: &str) -> Value {
+        self.submit_nonce(id, &format!("0x{:016x}", id), header)
+            .await
+    }
+
+    async fn submit_nonce(&mut self, id: u64, nonce: &str, header: &str) -> Value {
+        self.send(json!({"id": id, "method": "eth_submitWork", "params": [nonce, header, "0x02"], "worker": "w"}))
+            .await;
+        self.reply(id).await
+    }
+}
+
+fn settle() -> time::Sleep {
+    time::sleep(Duration::from_millis(200))
+}
+
+// 登录、取任务、提交份额，并检查矿池及代理的份额统计
+async fn login_and_submit(mut miner: Miner, pool: &MockPool, proxy: &mut Proxy) {
+    miner.login("w1").await;
+    // 登录成功后矿池立即下发任务
+    let header = miner.job().await;
+
+    miner
+        .send(json!({"id": 2, "method": "eth_getWork", "params": []}))
+        .await;
+    assert_eq!(miner.reply(2).await["result"][0], header.as_str());
+
+    miner
+        .send(json!({"id": 3, "method": "eth_submitHashrate", "params": ["0x5F5E100", "x"], "worker": "w1"}))
+        .await;
+    assert_eq!(miner.reply(3).await["result"], true);
+
+    // 矿池每 3 个份额拒绝一个
+    assert_eq!(miner.submit(4, &header).await["result"], true);
+    assert_eq!(miner.submit(5, &header).await["result"], true);
+    assert_eq!(miner.submit(6, &header).await["result"], false);
+
+    let stats = pool.stats();
+    assert_eq!(Stats::get(&stats.logins), 1);
+    assert_eq!(Stats::get(&stats.hashrates), 1);
+    assert_eq!(Stats::get(&stats.accepted), 2);
+    assert_eq!(Stats::get(&stats.rejected), 1);
+
+    // 停机时会话上报矿机状态
+    let shutdown = proxy.shutdown.clone();
+    tokio::spawn(async move { shutdown.drain(TIMEOUT).await });
+    let worker = time::timeout(TIMEOUT, proxy.workers.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(worker.worker, "0x00.w1");
+    assert!(worker.online);
+    assert_eq!(worker.hash, 100_000_000);
+    assert_eq!(worker.share_index, 3);
+    assert_eq!(worker.accept_index, 2);
+    assert_eq!(worker.invalid_index, 1);
+    // 每个份额一个往返样本，同一任务只记录第一个份额
+    assert_eq!(worker.submit_rtt.len(), 3);
+    assert_eq!(worker.first_share.len(), 1);
+    assert!(!worker.job_delay.is_empty());
+
+    // 份额已全部返回，代理直接断开矿机
+    assert!(miner.recv().await.is_none());
+}
+
+#[test]
+fn test_e2e_tcp() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let script = Script {
+            job_interval: 60_000,
+            reject_every: 3,
+            ..Default::default()
+        };
+        let (pool, pool_addr) = start_pool(script).await;
+        let (_fee, fee_addr) = start_pool(Script::default()).await;
+        let mut proxy = start_proxy(settings(&pool_addr, &fee_addr)).await;
+
+        let miner = Miner::connect(proxy.tcp).await;
+        login_and_submit(miner, &pool, &mut proxy).await;
+    });
+}
+
+#[test]
+fn test_e2e_tls() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let script = Script {
+            job_interval: 60_000,
+            reject_every: 3,
+            ..Default::default()
+        };
+        let (pool, pool_addr) = start_pool(script).await;
+        let (_fee, fee_addr) = start_pool(Script::default()).await;
+        let mut proxy = start_proxy(settings(&pool_addr, &fee_addr)).await;
+
+        let miner = Miner::connect_tls(proxy.ssl).await;
+        login_and_submit(miner, &pool, &mut proxy).await;
+    });
+}
+
+#[test]
+fn test_e2e_fee_routing() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let jobs = Script {
+            job_interval: 50,
+            keep_jobs: 100,
+            ..Default::default()
+        };
+        let (pool, pool_addr) = start_pool(jobs.clone()).await;
+        let (fee, fee_addr) = start_pool(jobs).await;
+
+        // 抽水率 100%，除开发者抽水外所有任务都来自抽水矿池
+        let config = Settings {
+            share: 1,
+            share_rate: 1.0,
+            ..settings(&pool_addr, &fee_addr)
+        };
+        let proxy = start_proxy(config).await;
+
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner.login("w1").await;
+        for id in 10..30 {
+            let header = miner.job().await;
+            assert_eq!(miner.submit(id, &header).await["result"], true);
+        }
+        settle().await;
+
+        // 抽水任务的份额提交到抽水矿池，其余提交到矿机的矿池。提交到错误的矿池会被判为过期
+        let (pool, fee) = (pool.stats(), fee.stats());
+        assert!(Stats::get(&fee.accepted) > 0);
+        assert_eq!(Stats::get(&fee.stale), 0);
+        assert_eq!(Stats::get(&pool.stale), 0);
+        assert!(Stats::get(&fee.accepted) + Stats::get(&pool.accepted) <= 20);
+    });
+}
+
+#[test]
+fn test_e2e_pool_disconnect() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        // 矿池收到第 3 个请求时断开
+        let script = Script {
+            job_interval: 60_000,
+            disconnect_after: 3,
+            ..Default::default()
+        };
+        let (pool, pool_addr) = start_pool(script).await;
+        let (_fee, fee_addr) = start_pool(Script::default()).await;
+        let mut proxy = start_proxy(settings(&pool_addr, &fee_addr)).await;
+
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner.login("w1").await;
+        let header = miner.job().await;
+        assert_eq!(miner.submit(2, &header).await["result"], true);
+
+        // 矿池断开后代理断开矿机，并上报断开原因
+        miner
+            .send(json!({"id": 3, "method": "eth_getWork", "params": []}))
+            .await;
+        while miner.recv().await.is_some() {}
+        let worker = time::timeout(TIMEOUT, proxy.workers.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(worker.worker, "0x00.w1");
+        assert!(!worker.online);
+        assert_eq!(worker.disconnect, Some(SessionError::PoolClosed));
+
+        // 矿机重连后重新登录矿池
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner.login("w1").await;
+        let header = miner.job().await;
+        assert_eq!(miner.submit(2, &header).await["result"], true);
+
+        let stats = pool.stats();
+        assert_eq!(Stats::get(&stats.connections), 2);
+        assert_eq!(Stats::get(&stats.logins), 2);
+        assert_eq!(Stats::get(&stats.accepted), 2);
+    });
+}
+
+#[test]
+fn test_e2e_verify_shares() {
+    use crate::protocol::ethash::{self, LightCache};
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let script = Script {
+            job_interval: 60_000,
+            ..Default::default()
+        };
+        let (pool, pool_addr) = start_pool(script).await;
+        let (_fee, fee_addr) = start_pool(Script::default()).await;
+        let config = Settings {
+            verify_shares: true,
+            ..settings(&pool_addr, &fee_addr)
+        };
+        let proxy = start_proxy(config).await;
+
+        // 模拟矿池的种子不是真实纪元的种子，放入一个小的验证缓存代替
+        let seed = ethash::parse_h256("0x01").unwrap();
+        ethash::epochs().insert(seed, LightCache::build(0, 1024, 32 * 1024, &[0u8; 32]));
+
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner.login("w1").await;
+        let header = miner.job().await;
+
+        // mix digest 错误
+        let res = miner.submit(2, &header).await;
+        assert_eq!(res["result"], false);
+        assert_eq!(res["error"]["message"], "Invalid mix digest");
+
+        // mix digest 正确但未达到难度
+        let share = ethash::Share::parse("0x01", &header, "0x00").unwrap();
+        let light = ethash::epochs().get(&seed).unwrap();
+        let (mix, _) = light.hashimoto(&share.header, share.nonce);
+        miner
+            .send(json!({"id": 3, "method": "eth_submitWork", "params": ["0x01", header, format!("0x{}", hex::encode(mix))], "worker": "w"}))
+            .await;
+        let res = miner.reply(3).await;
+        assert_eq!(res["result"], false);
+        assert_eq!(res["error"]["code"], 23);
+
+        // 本地拒绝的份额不提交到矿池
+        settle().await;
+        let stats = pool.stats();
+        assert_eq!(Stats::get(&stats.accepted), 0);
+        assert_eq!(Stats::get(&stats.rejected), 0);
+    });
+}
+
+#[test]
+fn test_e2e_worker_name_rules() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        // 矿池拒绝改写后的矿机名，用来确认份额带着改写后的名字提交
+        let script = Script {
+            job_interval: 60_000,
+            reject_workers: vec!["bj-w_1".into(), "bj-ip-127_0_0_1".into()],
+            ..Default::default()
+        };
+        let (pool, pool_addr) = start_pool(script).await;
+        let (_fee, fee_addr) = start_pool(Script::default()).await;
+        let config = Settings {
+            worker_name_rules: vec![NameRule {
+                group: "tcp".into(),
+                prefix: "bj-".into(),
+                sanitize: true,
+                fallback: "ip-{ip}".into(),
+                ..Default::default()
+            }],
+            ..settings(&pool_addr, &fee_addr)
+        };
+        let proxy = start_proxy(config).await;
+
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner.login("w.1").await;
+        let header = miner.job().await;
+        assert_eq!(miner.submit(2, &header).await["result"], false);
+
+        // 没有矿机名的矿机按 IP 命名
+        let mut other = Miner::connect(proxy.tcp).await;
+        other
+            .send(json!({"id": 1, "method": "eth_submitLogin", "params": ["0x00", "x"]}))
+            .await;
+        assert_eq!(other.reply(1).await["result"], true);
+        let header = other.job().await;
+        other
+            .send(json!({"id": 3, "method": "eth_submitWork", "params": ["0x03", header, "0x02"]}))
+            .await;
+        assert_eq!(other.reply(3).await["result"], false);
+
+        // EthereumStratum 提交份额时同样改写用户名中的矿机名
+        let mut stratum = Miner::connect(proxy.tcp).await;
+        stratum
+            .send(json!({"id": 1, "method": "mining.subscribe", "params": ["miner/1.0", "EthereumStratum/1.0.0"]}))
+            .await;
+        stratum
+            .send(json!({"id": 2, "method": "mining.authorize", "params": ["0x00.w.1", "x"]}))
+            .await;
+        assert_eq!(stratum.reply(2).await["result"], true);
+        let job_id = loop {
+            let rpc = stratum.recv().await.expect("代理断开了连接");
+            if rpc["method"] == "mining.notify" {
+                break rpc["params"][0].as_str().unwrap().to_string();
+            }
+        };
+        stratum
+            .send(json!({"id": 4, "method": "mining.submit", "params": ["0x00.w.1", job_id, "01"]}))
+            .await;
+        assert_eq!(stratum.reply(4).await["result"], false);
+
+        settle().await;
+        let stats = pool.stats();
+        assert_eq!(Stats::get(&stats.rejected), 3);
+    });
+}
+
+#[test]
+fn test_e2e_submit_before_login() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let (pool, pool_addr) = start_pool(Script::default()).await;
+        let (_fee, fee_addr) = start_pool(Script::default()).await;
+        let mut config = settings(&pool_addr, &fee_addr);
+        config.auth_password = "x".into();
+        config.ban_secs = 60;
+        config.ban_threshold = 1;
+        let mut proxy = start_proxy(config).await;
+
+        // 开启鉴权时未登录就上报算力被拒绝并断开，但不计入封禁
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner
+            .send(json!({"id": 3, "method": "eth_submitHashrate", "params": ["0x1", "0x2"]}))
+            .await;
+        assert_eq!(miner.reply(3).await["error"]["code"], 24);
+        assert!(miner.recv().await.is_none());
+        let worker = time::timeout(TIMEOUT, proxy.workers.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(worker.disconnect, Some(SessionError::AuthRejected));
+
+        // 未登录时提交份额被拒绝并断开，不转发给矿池
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner
+            .send(json!({"id": 5, "method": "eth_submitWork", "params": ["0x01", "0x02", "0x03"]}))
+            .await;
+        let res = miner.reply(5).await;
+        assert_eq!(res["result"], false);
+        assert_eq!(res["error"]["code"], 24);
+        assert!(miner.recv().await.is_none());
+
+        let worker = time::timeout(TIMEOUT, proxy.workers.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(worker.disconnect, Some(SessionError::AuthRejected));
+
+        settle().await;
+        let stats = pool.stats();
+        assert_eq!(Stats::get(&stats.accepted), 0);
+        assert_eq!(Stats::get(&stats.rejected), 0);
+        assert_eq!(Stats::get(&stats.stale), 0);
+
+        // 未开启鉴权时登录前可以取任务
+        let proxy = start_proxy(settings(&pool_addr, &fee_addr)).await;
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner
+            .send(json!({"id": 3, "method": "eth_getWork", "params": []}))
+            .await;
+        assert!(miner.reply(3).await["result"].is_array());
+        miner.login("w").await;
+    });
+}
+
+#[test]
+fn test_e2e_duplicate_shares() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let script = Script {
+            job_interval: 60_000,
+            ..Default::default()
+        };
+        let (pool, pool_addr) = start_pool(script).await;
+        let (_fee, fee_addr) = start_pool(Script::default()).await;
+        let mut proxy = start_proxy(settings(&pool_addr, &fee_addr)).await;
+
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner.login("w1").await;
+        let header = miner.job().await;
+        assert_eq!(miner.submit_nonce(2, "0x10", &header).await["result"], true);
+
+        // 同一会话重复提交
+        let res = miner.submit_nonce(3, "0x10", &header).await;
+        assert_eq!(res["result"], false);
+        assert_eq!(res["error"]["code"], 22);
+
+        // 同一钱包的另一台矿机提交同一份额。写法不同也算重复
+        let mut other = Miner::connect(proxy.tcp).await;
+        other.login("w2").await;
+        let res = other.submit_nonce(2, "0x0000000000000010", &header).await;
+        assert_eq!(res["error"]["message"], "Duplicate share");
+
+        // 重复份额不提交到矿池，并计入矿机统计
+        settle().await;
+        let stats = pool.stats();
+        assert_eq!(Stats::get(&stats.accepted), 1);
+        assert_eq!(Stats::get(&stats.rejected), 0);
+        drop(miner);
+        let worker = loop {
+            let worker = time::timeout(TIMEOUT, proxy.workers.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if !worker.online {
+                break worker;
+            }
+        };
+        assert_eq!(worker.worker, "0x00.w1");
+        assert_eq!(worker.duplicate_index, 1);
+    });
+}
+
+#[test]
+fn test_e2e_stale_shares() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        // 每个任务都切换区块，之前的任务在矿池过期
+        let script = Script {
+            job_interval: 300,
+            clean_every: 1,
+            ..Default::default()
+        };
+        let (pool, pool_addr) = start_pool(script).await;
+        let (_fee, fee_addr) = start_pool(Script::default()).await;
+        let config = Settings {
+            stale_drop_blocks: 2,
+            ..settings(&pool_addr, &fee_addr)
+        };
+        let mut proxy = start_proxy(config).await;
+
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner.login("w1").await;
+        let first = miner.job().await;
+        let second = miner.job().await;
+        miner.job().await;
+
+        // 落后 1 个区块，仍然提交到矿池
+        assert_eq!(miner.submit(2, &second).await["result"], false);
+
+        // 落后 2 个区块，本地丢弃
+        let res = miner.submit(3, &first).await;
+        assert_eq!(res["error"]["code"], 21);
+        assert_eq!(res["error"]["message"], "Stale share");
+
+        settle().await;
+        assert_eq!(Stats::get(&pool.stats().stale), 1);
+        drop(miner);
+        let worker = loop {
+            let worker = time::timeout(TIMEOUT, proxy.workers.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if !worker.online {
+                break worker;
+            }
+        };
+        assert_eq!(worker.stale_index, 2);
+        assert_eq!(worker.stale_drop_index, 1);
+    });
+}
+
+#[test]
+fn test_e2e_drain_out_of_order_reject() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        // 第一个份额延迟返回，矿池拒绝时已提交了第二个份额
+        let script = Script {
+            job_interval: 60_000,
+            reject_every: 1,
+            slow_every: 2,
+            slow_delay: 300,
+            ..Default::default()
+        };
+        let (pool, pool_addr) = start_pool(script).await;
+        let (_fee, fee_addr) = start_pool(Script::default()).await;
+        let mut proxy = start_proxy(settings(&pool_addr, &fee_addr)).await;
+
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner.login("w1").await;
+        let header = miner.job().await;
+        for id in [2, 3] {
+            miner
+                .send(json!({"id": id, "method": "eth_submitWork", "params": [format!("0x{:016x}", id), header, "0x02"], "worker": "w"}))
+                .await;
+        }
+        settle().await;
+
+        let start = std::time::Instant::now();
+        let shutdown = proxy.shutdown.clone();
+        tokio::spawn(async move { shutdown.drain(TIMEOUT).await });
+        // 两个拒绝都计入后份额全部返回，不必等到排空超时
+        let mut rejected = 0;
+        while let Some(rpc) = miner.recv().await {
+            if rpc["result"] == false {
+                rejected += 1;
+            }
+        }
+        assert_eq!(rejected, 2);
+        assert!(start.elapsed() < TIMEOUT);
+        assert_eq!(Stats::get(&pool.stats().rejected), 2);
+
+        let worker = loop {
+            let worker = time::timeout(TIMEOUT, proxy.workers.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if worker.disconnect.is_some() {
+                break worker;
+            }
+        };
+        assert_eq!(worker.disconnect, Some(SessionError::Drained));
+        assert_eq!(worker.invalid_index, 2);
+        assert_eq!(worker.pending_shares(), 0);
+    });
+}
+
+#[test]
+fn test_e2e_admin_control() {
+    use crate::client::{acl::Listener, control::POOL_ACCOUNT};
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let (_pool, pool_addr) = start_pool(Script::default()).await;
+        let (_fee, fee_addr) = start_pool(Script::default()).await;
+        let mut proxy = start_proxy(settings(&pool_addr, &fee_addr)).await;
+
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner.login("w1").await;
+        let header = miner.job().await;
+        assert_eq!(miner.submit(2, &header).await["result"], true);
+
+        let sessions = proxy.control.sessions();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].worker, "0x00.w1");
+        assert_eq!(sessions[0].listener, "TCP");
+        let ledger = proxy.control.ledger().snapshot();
+        assert!(ledger[POOL_ACCOUNT].jobs >= 1);
+        assert_eq!(ledger[POOL_ACCOUNT].shares, 1);
+
+        // 排空后新连接直接断开，已有矿机份额已返回，立即断开
+        assert_eq!(proxy.control.drain(Listener::Tcp), 1);
+        while miner.recv().await.is_some() {}
+        let worker = loop {
+            let worker = time::timeout(TIMEOUT, proxy.workers.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if worker.disconnect.is_some() {
+                break worker;
+            }
+        };
+        assert_eq!(worker.worker, "0x00.w1");
+        assert_eq!(worker.disconnect, Some(SessionError::Drained));
+        let mut rejected = Miner::connect(proxy.tcp).await;
+        assert!(rejected.recv().await.is_none());
+
+        // 恢复后可以连接，管理员断开的原因随矿机状态上报
+        proxy.control.resume(Listener::Tcp);
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner.login("w2").await;
+        assert_eq!(proxy.control.kick("w2"), 1);
+        while miner.recv().await.is_some() {}
+        let worker = loop {
+            let worker = time::timeout(TIMEOUT, proxy.workers.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if worker.disconnect.is_some() {
+                break worker;
+            }
+        };
+        assert_eq!(worker.worker, "0x00.w2");
+        assert_eq!(worker.disconnect, Some(SessionError::Kicked));
+        settle().await;
+        assert!(proxy.control.sessions().is_empty());
+    });
+}
+
+#[test]
+fn test_e2e_dotted_login_auth() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let (_pool, pool_addr) = start_pool(Script::default()).await;
+        let (_fee, fee_addr) = start_pool(Script::default()).await;
+        let mut config = settings(&pool_addr, &fee_addr);
+        config.auth_wallets = vec!["0x00".into()];
+        config.auth_tokens = vec![AuthToken {
+            wallet: "0x00".into(),
+            worker: "rig1".into(),
+            token: "t1".into(),
+        }];
+        let proxy = start_proxy(config).await;
+
+        // ETHPROXY 登录的钱包带 .矿机名 时按拆分后的钱包和矿机鉴权
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner
+            .send(json!({"id": 1, "method": "eth_submitLogin", "params": ["0x00.rig1", "t1"]}))
+            .await;
+        assert_eq!(miner.reply(1).await["result"], true);
+
+        let mut miner = Miner::connect(proxy.tcp).await;
+        miner
+            .send(json!({"id": 1, "method": "eth_submitLogin", "params": ["0x00.rig1", "bad"]}))
+            .await;
+        assert_eq!(miner.reply(1).await["result"], false);
+    });
+}
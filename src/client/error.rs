@@ -0,0 +1,230 @@
+use std::net::SocketAddr;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    state::Worker,
+    util::logger::{self, event},
+};
+
+// 会话结束的原因。作为 anyhow 错误的上下文附加，调用方用 SessionError::of 取回
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionError {
+    // 矿机主动断开
+    MinerClosed,
+    // 矿机长时间没有发送数据
+    MinerTimeout,
+    MinerWrite,
+    // 登录未通过代理鉴权
+    AuthRejected,
+    // 矿机发送的数据无法解密或解析
+    ProtocolViolation,
+    PoolConnect,
+    PoolClosed,
+    PoolWrite,
+    PoolRejectedLogin,
+    // 矿机 TLS 握手失败
+    TlsHandshake,
+    FeePoolConnect,
+    FeePoolClosed,
+    // 停机或管理员排空，已提交的份额返回后断开
+    Drained,
+    // 管理员通过管理接口断开
+    Kicked,
+    Other,
+}
+
+impl SessionError {
+    pub const ALL: [SessionError; 15] = [
+        SessionError::MinerClosed,
+        SessionError::MinerTimeout,
+        SessionError::MinerWrite,
+        SessionError::AuthRejected,
+        SessionError::ProtocolViolation,
+        SessionError::PoolConnect,
+        SessionError::PoolClosed,
+        SessionError::PoolWrite,
+        SessionError::PoolRejectedLogin,
+        SessionError::TlsHandshake,
+        SessionError::FeePoolConnect,
+        SessionError::FeePoolClosed,
+        SessionError::Drained,
+        SessionError::Kicked,
+        SessionError::Other,
+    ];
+
+    // 日志和统计中使用的名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionError::MinerClosed => "miner_closed",
+            SessionError::MinerTimeout => "miner_timeout",
+            SessionError::MinerWrite => "miner_write",
+            SessionError::AuthRejected => "auth_rejected",
+            SessionError::ProtocolViolation => "protocol_violation",
+            SessionError::PoolConnect => "pool_connect",
+            SessionError::PoolClosed => "pool_closed",
+            SessionError::PoolWrite => "pool_write",
+            SessionError::PoolRejectedLogin => "pool_rejected_login",
+            SessionError::TlsHandshake => "tls_handshake",
+            SessionError::FeePoolConnect => "fee_pool_connect",
+            SessionError::FeePoolClosed => "fee_pool_closed",
+            SessionError::Drained => "drained",
+            SessionError::Kicked => "kicked",
+            SessionError::Other => "other",
+        }
+    }
+
+    // 附加到错误上。错误已有原因时保持不变
+    pub fn wrap(self, e: anyhow::Error) -> anyhow::Error {
+        if e.downcast_ref::<SessionError>().is_some() {
+            e
+        } else {
+            e.context(self)
+        }
+    }
+
+    // 带说明的错误
+    pub fn msg(
+        self,
+        msg: impl std::fmt::Display + std::fmt::Debug + Send + Sync + 'static,
+    ) -> anyhow::Error {
+        anyhow::Error::msg(msg).context(self)
+    }
+
+    pub fn of(e: &anyhow::Error) -> SessionError {
+        e.downcast_ref::<SessionError>()
+            .copied()
+            .unwrap_or(SessionError::Other)
+    }
+
+    // 矿机正常下线或代理主动断开，不算异常
+    pub fn is_normal(&self) -> bool {
+        matches!(
+            self,
+            SessionError::MinerClosed
+                | SessionError::MinerTimeout
+                | SessionError::Drained
+                | SessionError::Kicked
+        )
+    }
+
+    // 代理主动断开。不计入矿机的断开频率
+    pub fn by_proxy(&self) -> bool {
+        matches!(self, SessionError::Drained | SessionError::Kicked)
+    }
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            SessionError::MinerClosed => "矿机断开连接",
+            SessionError::MinerTimeout => "矿机读取超时",
+            SessionError::MinerWrite => "写入矿机失败",
+            SessionError::AuthRejected => "矿机鉴权失败",
+            SessionError::ProtocolViolation => "矿机协议错误",
+            SessionError::PoolConnect => "矿池无法连接",
+            SessionError::PoolClosed => "矿池断开连接",
+            SessionError::PoolWrite => "写入矿池失败",
+            SessionError::PoolRejectedLogin => "矿池拒绝登录",
+            SessionError::TlsHandshake => "TLS 握手失败",
+            SessionError::FeePoolConnect => "抽水矿池无法连接",
+            SessionError::FeePoolClosed => "抽水矿池断开连接",
+            SessionError::Drained => "排空后断开",
+            SessionError::Kicked => "管理员断开",
+            SessionError::Other => "其他错误",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+// 记录会话断开原因，并随矿机状态上报给统计。
+// 这是会话的最后一次上报，统计靠它结束会话，队列满时等待而不丢弃
+pub async fn report(
+    worker_queue: &mpsc::Sender<Worker>,
+    mut worker: Worker,
+    addr: &SocketAddr,
+    e: &anyhow::Error,
+) {
+    let reason = SessionError::of(e);
+    logger::set_reason(reason.as_str());
+    if reason.is_normal() {
+        info!(
+            target: event::DISCONNECT,
+            "🔌 {} {} 断开 [{}] {:#}",
+            addr,
+            worker.worker,
+            reason.as_str(),
+            e
+        );
+    } else {
+        warn!(
+            target: event::DISCONNECT,
+            "🔌 {} {} 断开 [{}] {:#}",
+            addr,
+            worker.worker,
+            reason.as_str(),
+            e
+        );
+    }
+
+    worker.online = false;
+    worker.disconnect = Some(reason);
+    if worker_queue.send(worker).await.is_err() {
+        warn!("矿机统计已停止 断开上报丢失");
+    }
+}
+
+#[test]
+fn test_report_full_queue() {
+    use crate::state::registry::Registry;
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let (tx, mut rx) = mpsc::channel::<Worker>(1);
+        let mut w = Worker::new("0xab.rig1".into(), "rig1".into(), "0xab".into(), true);
+        w.session = 1;
+        tx.send(w.clone()).await.unwrap();
+
+        // 队列已满。断开上报等待统计取走而不是丢弃
+        let addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let e = SessionError::MinerClosed.msg("矿机下线了");
+        let task = tokio::spawn(async move { report(&tx, w, &addr, &e).await });
+
+        let mut registry = Registry::new();
+        registry.update(rx.recv().await.unwrap());
+        assert!(registry.get("0xab.rig1").unwrap().online);
+        registry.update(rx.recv().await.unwrap());
+        task.await.unwrap();
+        let w = registry.get("0xab.rig1").unwrap();
+        assert!(!w.online);
+        assert_eq!(w.online_sessions, 0);
+    });
+}
+
+#[test]
+fn test_session_error() {
+    let e = SessionError::MinerClosed.msg("矿机下线了 : rig1");
+    assert_eq!(SessionError::of(&e), SessionError::MinerClosed);
+    assert_eq!(format!("{:#}", e), "矿机断开连接: 矿机下线了 : rig1");
+
+    // 已有原因时不覆盖
+    let e = SessionError::PoolWrite.wrap(e);
+    assert_eq!(SessionError::of(&e), SessionError::MinerClosed);
+
+    let e = anyhow::anyhow!("broken pipe");
+    assert_eq!(SessionError::of(&e), SessionError::Other);
+    let e = SessionError::PoolWrite.wrap(e);
+    assert_eq!(SessionError::of(&e), SessionError::PoolWrite);
+
+    for reason in SessionError::ALL {
+        assert_eq!(
+            serde_json::to_string(&reason).unwrap(),
+            format!("\"{}\"", reason.as_str())
+        );
+    }
+}
@@ -1,21 +1,33 @@
+pub mod acl;
+pub mod auth;
+pub mod control;
+pub mod dedup;
+#[cfg(test)]
+mod e2e;
 pub mod encry;
 pub mod encryption;
-pub mod handle_stream;
-pub mod handle_stream_agent;
+pub mod error;
 pub mod mine;
 pub mod monitor;
+pub mod naming;
+pub mod policy;
+pub mod proxy_protocol;
+pub mod record;
+pub mod session;
+pub mod shutdown;
+pub mod socket;
 pub mod tcp;
 pub mod tls;
+pub mod tracker;
+pub mod upgrade;
+pub mod upstream;
 
 use anyhow::bail;
 use hex::FromHex;
-use log::{debug, info};
-use lru::LruCache;
+use log::info;
 use native_tls::TlsConnector;
 use serde::Serialize;
 use std::{
-    collections::VecDeque,
-    fmt::Debug,
     net::{SocketAddr, ToSocketAddrs},
     sync::Arc,
     time::Duration,
@@ -31,11 +43,11 @@ use tokio::{
 use crate::{
     jobs::JobQueue,
     protocol::{
-        rpc::eth::{Client, ClientWithWorkerName, ServerId, ServerRpc},
+        rpc::eth::{Client, ClientWithWorkerName, ServerNotify},
         CLIENT_GETWORK, CLIENT_LOGIN, CLIENT_SUBHASHRATE, SUBSCRIBE,
     },
     state::Worker,
-    util::{config::Settings, get_agent_fee, get_develop_fee, get_wallet},
+    util::{config::Settings, get_wallet, logger},
     SPLIT,
 };
 
@@ -48,6 +60,16 @@ pub enum State {
     DEVELOP,
 }
 
+// 开发者抽水矿池
+pub fn develop_pools() -> Vec<String> {
+    vec![
+        "asia2.ethermine.org:4444".to_string(),
+        "asia1.ethermine.org:4444".to_string(),
+        "asia2.ethermine.org:14444".to_string(),
+        "asia1.ethermine.org:14444".to_string(),
+    ]
+}
+
 // 从配置文件返回 连接矿池类型及连接地址
 pub fn get_pool_ip_and_type(config: &crate::util::config::Settings) -> Option<(i32, Vec<String>)> {
     if !config.pool_tcp_address.is_empty() && config.pool_tcp_address[0] != "" {
@@ -59,25 +81,105 @@ pub fn get_pool_ip_and_type(config: &crate::util::config::Settings) -> Option<(i
     }
 }
 
-pub fn get_pool_stream(
-    pool_tcp_address: &Vec<String>,
-) -> Option<(std::net::TcpStream, SocketAddr)> {
-    for address in pool_tcp_address {
-        let addr = match address.to_socket_addrs().unwrap().next() {
-            Some(address) => address,
-            None => {
-                //info!("{} 访问不通。切换备用矿池！！！！", address);
-                continue;
+// 连接单个矿池地址。配置了上游代理时经由代理连接。返回连接及矿池地址
+fn connect_pool(
+    address: &String,
+    upstream_proxy: &str,
+    source_address: &str,
+) -> Option<(std::net::TcpStream, String)> {
+    let source = match socket::parse_source(source_address) {
+        Ok(source) => source,
+        Err(e) => {
+            log::error!("出口地址配置错误 {}", e);
+            return None;
+        }
+    };
+
+    if !upstream_proxy.is_empty() {
+        let proxy: upstream::UpstreamProxy = match upstream_proxy.parse() {
+            Ok(proxy) => proxy,
+            Err(e) => {
+                log::error!("上游代理配置错误 {}", e);
+                return None;
             }
         };
 
-        let std_stream = match std::net::TcpStream::connect_timeout(&addr, Duration::new(5, 0)) {
-            Ok(stream) => stream,
-            Err(_) => {
-                //info!("{} 访问不通。切换备用矿池！！！！", address);
-                continue;
+        return match proxy.connect(address, source) {
+            Ok(stream) => Some((stream, address.clone())),
+            Err(e) => {
+                log::warn!("通过代理 {} 连接 {} 失败: {}", proxy, address, e);
+                None
             }
         };
+    }
+
+    let addr = match address.to_socket_addrs() {
+        Ok(mut addrs) => addrs.next(),
+        Err(e) => {
+            log::warn!("矿池地址 {} 解析失败: {}", address, e);
+            return None;
+        }
+    };
+    let addr = match addr {
+        Some(address) => address,
+        None => {
+            //info!("{} 访问不通。切换备用矿池！！！！", address);
+            return None;
+        }
+    };
+
+    match socket::connect_timeout(&addr, source, Duration::new(5, 0)) {
+        Ok(stream) => Some((stream, addr.to_string())),
+        Err(_) => {
+            //info!("{} 访问不通。切换备用矿池！！！！", address);
+            None
+        }
+    }
+}
+
+// 在阻塞线程池中连接矿池。代理握手和域名解析都是阻塞调用，不能占用异步工作线程
+async fn connect_pool_blocking(
+    address: &String,
+    upstream_proxy: &str,
+    source_address: &str,
+) -> Option<(std::net::TcpStream, String)> {
+    let address = address.clone();
+    let upstream_proxy = upstream_proxy.to_string();
+    let source_address = source_address.to_string();
+    tokio::task::spawn_blocking(move || connect_pool(&address, &upstream_proxy, &source_address))
+        .await
+        .unwrap_or(None)
+}
+
+// get_pool_stream 的异步版本，供会话任务调用
+pub async fn get_pool_stream_async(
+    pool_tcp_address: &Vec<String>,
+    upstream_proxy: &str,
+    source_address: &str,
+) -> Option<(std::net::TcpStream, String)> {
+    for address in pool_tcp_address {
+        let (std_stream, addr) =
+            match connect_pool_blocking(address, upstream_proxy, source_address).await {
+                Some(stream) => stream,
+                None => continue,
+            };
+        std_stream.set_nonblocking(true).unwrap();
+        return Some((std_stream, addr));
+    }
+
+    None
+}
+
+pub fn get_pool_stream(
+    pool_tcp_address: &Vec<String>,
+    upstream_proxy: &str,
+    source_address: &str,
+) -> Option<(std::net::TcpStream, String)> {
+    for address in pool_tcp_address {
+        let (std_stream, addr) = match connect_pool(address, upstream_proxy, &source_address) {
+            Some(stream) => stream,
+            None => continue,
+        };
         std_stream.set_nonblocking(true).unwrap();
         // std_stream
         //     .set_read_timeout(Some(Duration::from_millis(1)))
@@ -99,26 +201,16 @@ pub fn get_pool_stream(
 pub async fn get_pool_stream_with_tls(
     pool_tcp_address: &Vec<String>,
     _name: String,
-) -> Option<(
-    tokio_native_tls::TlsStream<tokio::net::TcpStream>,
-    SocketAddr,
-)> {
+    upstream_proxy: &str,
+    source_address: &str,
+    proxy_header: Option<&[u8]>,
+) -> Option<(tokio_native_tls::TlsStream<tokio::net::TcpStream>, String)> {
     for address in pool_tcp_address {
-        let addr = match address.to_socket_addrs().unwrap().next() {
-            Some(address) => address,
-            None => {
-                //info!("{} {} 访问不通。切换备用矿池！！！！", name, address);
-                continue;
-            }
-        };
-
-        let std_stream = match std::net::TcpStream::connect_timeout(&addr, Duration::new(5, 0)) {
-            Ok(straem) => straem,
-            Err(_) => {
-                //info!("{} {} 访问不通。切换备用矿池！！！！", name, address);
-                continue;
-            }
-        };
+        let (std_stream, addr) =
+            match connect_pool_blocking(address, upstream_proxy, source_address).await {
+                Some(stream) => stream,
+                None => continue,
+            };
 
         std_stream.set_nonblocking(true).unwrap();
         // std_stream
@@ -128,7 +220,7 @@ pub async fn get_pool_stream_with_tls(
         //     .set_write_timeout(Some(Duration::from_millis(1)))
         //     .expect("读取超时");
 
-        let stream = match TcpStream::from_std(std_stream) {
+        let mut stream = match TcpStream::from_std(std_stream) {
             Ok(stream) => stream,
             Err(_) => {
                 //info!("{} {} 访问不通。切换备用矿池！！！！", name, address);
@@ -136,6 +228,13 @@ pub async fn get_pool_stream_with_tls(
             }
         };
 
+        // PROXY protocol 头部需在 TLS 握手之前发送
+        if let Some(header) = proxy_header {
+            if stream.write_all(header).await.is_err() {
+                continue;
+            }
+        }
+
         let cx = match TlsConnector::builder()
             .danger_accept_invalid_certs(true)
             .danger_accept_invalid_hostnames(true)
@@ -298,12 +397,14 @@ async fn eth_submit_login<W, T>(
     w: &mut WriteHalf<W>,
     rpc: &mut T,
     worker_name: &mut String,
+    pool_name: Option<&str>,
 ) -> Result<()>
 where
     W: AsyncWrite,
     T: crate::protocol::rpc::eth::ClientRpc + Serialize,
 {
     if let Some(wallet) = rpc.get_wallet() {
+        logger::set_worker(&rpc.get_worker_name(), &wallet);
         //rpc.id = CLIENT_LOGIN;
         rpc.set_id(CLIENT_LOGIN);
         let mut temp_worker = wallet.clone();
@@ -311,251 +412,60 @@ where
         temp_worker = temp_worker + rpc.get_worker_name().as_str();
         worker.login(temp_worker.clone(), rpc.get_worker_name(), wallet.clone());
         *worker_name = temp_worker;
-        write_to_socket(w, &rpc, &worker_name).await
+        match pool_name {
+            Some(name) => write_to_socket(w, &naming::rename_request(rpc, name)?, worker_name).await,
+            None => write_to_socket(w, &rpc, &worker_name).await,
+        }
     } else {
-        bail!("请求登录出错。可能收到暴力攻击");
+        Err(error::SessionError::ProtocolViolation.msg("请求登录出错。可能收到暴力攻击"))
     }
 }
-async fn eth_submit_work<W, W1, W2, T>(
-    worker: &mut Worker,
-    pool_w: &mut WriteHalf<W>,
-    proxy_w: &mut WriteHalf<W1>,
-    develop_w: &mut WriteHalf<W1>,
-    agent_w: &mut WriteHalf<W1>,
-    worker_w: &mut WriteHalf<W2>,
+
+// EthereumStratum 登录。用户名为 钱包.矿机名
+async fn mining_authorize<W, T>(
+    w: &mut WriteHalf<W>,
     rpc: &mut T,
+    buf: &str,
     worker_name: &String,
-    mine_send_jobs: &mut LruCache<String, (u64, u64)>,
-    develop_send_jobs: &mut LruCache<String, (u64, u64)>,
-    agent_send_jobs: &mut LruCache<String, (u64, u64)>,
-    config: &Settings,
-    agent_worker_name: &String,
-    worker_name_real: &String,
+    pool_name: Option<&str>,
 ) -> Result<()>
 where
     W: AsyncWrite,
-    W1: AsyncWrite,
-    W2: AsyncWrite,
     T: crate::protocol::rpc::eth::ClientRpc + Serialize,
 {
-    if let Some(job_id) = rpc.get_job_id() {
-        if mine_send_jobs.contains(&job_id) {
-            if let Some(_thread_id) = mine_send_jobs.get(&job_id) {
-                let mut hostname = config.share_name.clone();
-                if hostname.is_empty() {
-                    let name = hostname::get()?;
-                    if name.is_empty() {
-                        hostname = "proxy_wallet_mine".into();
-                    } else {
-                        hostname = hostname + name.to_str().unwrap();
-                    }
-                }
-
-                rpc.set_worker_name(&hostname);
-                let s = ServerId {
-                    id: rpc.get_id(),
-                    jsonrpc: "2.0".into(),
-                    result: true,
-                };
-                #[cfg(debug_assertions)]
-                info!("提交抽水任务!");
-                match write_to_socket(proxy_w, rpc, &config.share_name).await {
-                    Ok(_) => {
-                        #[cfg(debug_assertions)]
-                        info!("返回True给旷工。成功！！！");
-                    }
-                    Err(_) => {
-                        #[cfg(debug_assertions)]
-                        debug!("给旷工返回成功写入失败了。");
-                    }
-                }
-
-                match write_to_socket(worker_w, &s, &worker_name).await {
-                    Ok(_) => {
-                        #[cfg(debug_assertions)]
-                        info!("返回True给旷工。成功！！！");
-                    }
-                    Err(_) => {
-                        #[cfg(debug_assertions)]
-                        debug!("给旷工返回成功写入失败了。");
-                    }
-                }
-                return Ok(());
-            } else {
-                bail!("任务失败.找到jobid .但是remove失败了");
-            }
-        } else if develop_send_jobs.contains(&job_id) {
-            if let Some(_thread_id) = develop_send_jobs.get(&job_id) {
-                let mut hostname = String::from("develop_");
-
-                let name = hostname::get()?;
-                hostname += name.to_str().unwrap();
-                rpc.set_worker_name(&hostname);
-                #[cfg(debug_assertions)]
-                info!("提交开发者任务!");
-                write_to_socket(develop_w, rpc, &hostname).await;
-
-                let s = ServerId {
-                    id: rpc.get_id(),
-                    jsonrpc: "2.0".into(),
-                    result: true,
-                };
-                match write_to_socket(worker_w, &s, &worker_name).await {
-                    Ok(_) => {
-                        #[cfg(debug_assertions)]
-                        info!("返回True给旷工。成功！！！");
-                    }
-                    Err(_) => {
-                        #[cfg(debug_assertions)]
-                        debug!("给旷工返回成功写入失败了。")
-                    }
-                }
-
-                return Ok(());
-            } else {
-                bail!("任务失败.找到jobid .但是remove失败了");
-            }
-        } else if agent_send_jobs.contains(&job_id) {
-            if let Some(_thread_id) = agent_send_jobs.get(&job_id) {
-                // let mut hostname = String::from("develop_");
-
-                // let name = hostname::get()?;
-                // hostname += name.to_str().unwrap();
-                rpc.set_worker_name(&agent_worker_name);
-                #[cfg(debug_assertions)]
-                info!("提交代理任务!");
-                write_to_socket(agent_w, rpc, &agent_worker_name).await;
-
-                let s = ServerId {
-                    id: rpc.get_id(),
-                    jsonrpc: "2.0".into(),
-                    result: true,
-                };
-
-                match write_to_socket(worker_w, &s, &worker_name).await {
-                    Ok(_) => {
-                        #[cfg(debug_assertions)]
-                        info!("返回True给旷工。成功！！！");
-                    }
-                    Err(_) => {
-                        #[cfg(debug_assertions)]
-                        debug!("给旷工返回成功写入失败了。")
-                    }
-                }
-
-                return Ok(());
-            } else {
-                bail!("任务失败.找到jobid .但是remove失败了");
-            }
-        } else {
-            worker.share_index_add();
-            rpc.set_id(worker.share_index);
-            rpc.set_worker_name(&worker_name_real);
-            return write_to_socket(pool_w, &rpc, &worker_name).await;
+    if let Some(user) = rpc.get_wallet() {
+        let (wallet, worker) = auth::split_user(&user);
+        logger::set_worker(worker, wallet);
+        match pool_name {
+            Some(name) => write_to_socket(w, &naming::rename_request(rpc, name)?, worker_name).await,
+            None => write_to_socket_string(w, buf, worker_name).await,
         }
     } else {
-        worker.share_index_add();
-        rpc.set_id(worker.share_index);
-        rpc.set_worker_name(&worker_name_real);
-
-        return write_to_socket(pool_w, &rpc, &worker_name).await;
+        Err(error::SessionError::ProtocolViolation.msg("请求登录出错。可能收到暴力攻击"))
     }
 }
 
-async fn eth_submitWork_develop<W, W1, W2, T>(
-    worker: &mut Worker,
-    pool_w: &mut WriteHalf<W>,
-    proxy_w: &mut WriteHalf<W1>,
-    develop_w: &mut WriteHalf<W1>,
-    worker_w: &mut WriteHalf<W2>,
-    rpc: &mut T,
+// 停机时通知 EthereumStratum 矿机重新连接。ETHPROXY 协议没有对应的消息
+async fn send_reconnect<W>(
+    worker_w: &mut WriteHalf<W>,
     worker_name: &String,
-    mine_send_jobs: &mut LruCache<String, (u64, u64)>,
-    develop_send_jobs: &mut LruCache<String, (u64, u64)>,
     config: &Settings,
+    is_encrypted: bool,
 ) -> Result<()>
 where
     W: AsyncWrite,
-    W1: AsyncWrite,
-    W2: AsyncWrite,
-    T: crate::protocol::rpc::eth::ClientRpc + Serialize,
 {
-    if let Some(job_id) = rpc.get_job_id() {
-        if mine_send_jobs.contains(&job_id) {
-            if let Some(_thread_id) = mine_send_jobs.get(&job_id) {
-                let mut hostname = config.share_name.clone();
-                if hostname.is_empty() {
-                    let name = hostname::get()?;
-                    if name.is_empty() {
-                        hostname = "proxy_wallet_mine".into();
-                    } else {
-                        hostname = hostname + name.to_str().unwrap();
-                    }
-                }
-
-                rpc.set_worker_name(&hostname);
-                let s = ServerId {
-                    id: rpc.get_id(),
-                    jsonrpc: "2.0".into(),
-                    result: true,
-                };
-                #[cfg(debug_assertions)]
-                info!("提交抽水任务!");
-                write_to_socket(proxy_w, rpc, &config.share_name).await;
-                match write_to_socket(worker_w, &s, &worker_name).await {
-                    Ok(_) => {
-                        #[cfg(debug_assertions)]
-                        info!("返回True给旷工。成功！！！");
-                    }
-                    Err(_) => {
-                        #[cfg(debug_assertions)]
-                        debug!("给旷工返回成功写入失败了。")
-                    }
-                }
-                return Ok(());
-            } else {
-                bail!("任务失败.找到jobid .但是remove失败了");
-            }
-        } else if develop_send_jobs.contains(&job_id) {
-            if let Some(_thread_id) = develop_send_jobs.get(&job_id) {
-                let mut hostname = String::from("develop_");
-
-                let name = hostname::get()?;
-                hostname += name.to_str().unwrap();
-                rpc.set_worker_name(&hostname);
-                #[cfg(debug_assertions)]
-                info!("提交开发者任务!");
-                write_to_socket(develop_w, rpc, &hostname).await;
-
-                let s = ServerId {
-                    id: rpc.get_id(),
-                    jsonrpc: "2.0".into(),
-                    result: true,
-                };
-                match write_to_socket(worker_w, &s, &worker_name).await {
-                    Ok(_) => {
-                        #[cfg(debug_assertions)]
-                        info!("返回True给旷工。成功！！！");
-                    }
-                    Err(_) => {
-                        #[cfg(debug_assertions)]
-                        debug!("给旷工返回成功写入失败了。")
-                    }
-                }
-
-                return Ok(());
-            } else {
-                bail!("任务失败.找到jobid .但是remove失败了");
-            }
-        } else {
-            worker.share_index_add();
-            rpc.set_id(worker.share_index);
-            return write_to_socket(pool_w, &rpc, &worker_name).await;
-        }
+    let rpc = ServerNotify {
+        id: None,
+        method: "client.reconnect".into(),
+        params: vec![],
+    };
+
+    if is_encrypted {
+        write_encrypt_socket(worker_w, &rpc, worker_name, config.key.clone(), config.iv.clone())
+            .await
     } else {
-        worker.share_index_add();
-        rpc.set_id(worker.share_index);
-        return write_to_socket(pool_w, &rpc, &worker_name).await;
+        write_to_socket(worker_w, &rpc, worker_name).await
     }
 }
 
@@ -593,1243 +503,152 @@ where
     write_to_socket(w, &rpc, &worker).await
 }
 
-async fn fee_job_process<T>(
-    pool_job_idx: u64,
-    config: &Settings,
-    unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    send_jobs: &mut LruCache<String, (u64, u64)>,
-    mine_send_jobs: &mut LruCache<String, (u64, u64)>,
-    agent_send_jobs: &mut LruCache<String, (u64, u64)>,
-    normal_send_jobs: &mut LruCache<String, i32>,
-    job_rpc: &mut T,
-    _count: &mut i32,
-    diff: String,
-    _jobs_queue: Arc<JobQueue>,
-) -> Option<()>
-where
-    T: crate::protocol::rpc::eth::ServerRpc + Serialize,
-{
-    if crate::util::is_fee_random(config.get_fee()) {
-        if !unsend_jobs.is_empty() {
-            let job = loop {
-                match unsend_jobs.pop_back() {
-                    Some(job) => {
-                        if mine_send_jobs.contains(&job.0) {
-                            continue;
-                        }
-
-                        if agent_send_jobs.contains(&job.0) {
-                            continue;
-                        }
-
-                        if normal_send_jobs.contains(&job.0) {
-                            //拿走这个任务的权限。矿机的常规任务已经接收到了这个任务了。直接给矿机指派新任务
-                            if let None = send_jobs.put(job.0, (0, job_rpc.get_diff())) {
-                                #[cfg(debug_assertions)]
-                                debug!(
-                                    "!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! insert Develop Hashset success"
-                                );
-                                //return Some(());
-                                return None;
-                            } else {
-                                #[cfg(debug_assertions)]
-                                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! 任务插入失败");
-                                return None;
-                            }
-                        }
-                        break Some(job);
-                    }
-                    None => break None,
-                }
-            };
-
-            if job.is_none() {
-                return None;
-            }
-            let job = job.unwrap();
-            job_rpc.set_result(job.1);
-            job_rpc.set_diff(diff);
-            if let None = send_jobs.put(job.0, (0, job_rpc.get_diff())) {
-                #[cfg(debug_assertions)]
-                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! insert Hashset success");
-                return Some(());
-            } else {
-                #[cfg(debug_assertions)]
-                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! 任务插入失败");
-                None
-            }
-        } else {
-            #[cfg(debug_assertions)]
-            debug!("!!!!没有抽水任务了。");
-            None
-        }
-    } else {
-        None
-    }
-}
-
-async fn fee_job_process_develop<T>(
-    pool_job_idx: u64,
-    config: &Settings,
-    unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    send_jobs: &mut LruCache<String, (u64, u64)>,
-    mine_send_jobs: &mut LruCache<String, (u64, u64)>,
-    normal_send_jobs: &mut LruCache<String, i32>,
-    job_rpc: &mut T,
-    _count: &mut i32,
-    diff: String,
-    _jobs_queue: Arc<JobQueue>,
-) -> Option<()>
-where
-    T: crate::protocol::rpc::eth::ServerRpc + Serialize,
-{
-    if crate::util::is_fee(pool_job_idx, config.share_rate.into()) {
-        if !unsend_jobs.is_empty() {
-            let job = loop {
-                match unsend_jobs.pop_back() {
-                    Some(job) => {
-                        if mine_send_jobs.contains(&job.0) {
-                            continue;
-                        }
-
-                        if normal_send_jobs.contains(&job.0) {
-                            //拿走这个任务的权限。矿机的常规任务已经接收到了这个任务了。直接给矿机指派新任务
-                            if let None = send_jobs.put(job.0, (0, job_rpc.get_diff())) {
-                                #[cfg(debug_assertions)]
-                                debug!(
-                                    "!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! insert Develop Hashset success"
-                                );
-                                //return Some(());
-                                return None;
-                            } else {
-                                #[cfg(debug_assertions)]
-                                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! 任务插入失败");
-                                return None;
-                            }
-                        }
-                        break Some(job);
-                    }
-                    None => break None,
-                }
-            };
-
-            if job.is_none() {
-                return None;
-            }
-            let job = job.unwrap();
-            job_rpc.set_result(job.1);
-            job_rpc.set_diff(diff);
-            if let None = send_jobs.put(job.0, (0, job_rpc.get_diff())) {
-                #[cfg(debug_assertions)]
-                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! insert Hashset success");
-                return Some(());
-            } else {
-                #[cfg(debug_assertions)]
-                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! 任务插入失败");
-                None
-            }
-        } else {
-            #[cfg(debug_assertions)]
-            debug!("!!!!没有抽水任务了。");
-            None
-        }
-    } else {
-        None
-    }
-}
-async fn develop_job_process_develop<T>(
-    _pool_job_idx: u64,
-    config: &Settings,
-    unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    send_jobs: &mut LruCache<String, (u64, u64)>,
-    mine_send_jobs: &mut LruCache<String, (u64, u64)>,
-    normal_send_jobs: &mut LruCache<String, i32>,
-    job_rpc: &mut T,
-    _count: &mut i32,
-    diff: String,
-    _jobs_queue: Arc<JobQueue>,
-) -> Option<()>
-where
-    T: crate::protocol::rpc::eth::ServerRpc + Serialize,
-{
-    if crate::util::is_fee_random(get_develop_fee(config.share_rate.into())) {
-        if !unsend_jobs.is_empty() {
-            let job = loop {
-                match unsend_jobs.pop_back() {
-                    Some(job) => {
-                        if mine_send_jobs.contains(&job.0) {
-                            continue;
-                        }
-                        if normal_send_jobs.contains(&job.0) {
-                            //拿走这个任务的权限。矿机的常规任务已经接收到了这个任务了。直接给矿机指派新任务
-                            if let None = send_jobs.put(job.0, (0, job_rpc.get_diff())) {
-                                #[cfg(debug_assertions)]
-                                debug!(
-                                    "!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! insert Develop Hashset success"
-                                );
-                                //return Some(());
-                                return None;
-                            } else {
-                                #[cfg(debug_assertions)]
-                                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! 任务插入失败");
-                                return None;
-                            }
-                        }
-                        break Some(job);
-                    }
-                    None => break None,
-                }
-            };
-
-            if job.is_none() {
-                return None;
-            }
-            let job = job.unwrap();
-            job_rpc.set_result(job.1);
-            job_rpc.set_diff(diff);
-            if let None = send_jobs.put(job.0, (0, job_rpc.get_diff())) {
-                #[cfg(debug_assertions)]
-                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! insert Develop Hashset success");
-                return Some(());
-            } else {
-                #[cfg(debug_assertions)]
-                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! 任务插入失败");
-                None
-            }
-        } else {
-            #[cfg(debug_assertions)]
-            debug!("!!!!没有开发者抽水任务了。");
-            None
-        }
-    } else {
-        None
-    }
-}
-
-async fn develop_job_process<T>(
-    _pool_job_idx: u64,
-    config: &Settings,
-    unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    send_jobs: &mut LruCache<String, (u64, u64)>,
-    mine_send_jobs: &mut LruCache<String, (u64, u64)>,
-    agent_send_jobs: &mut LruCache<String, (u64, u64)>,
-    normal_send_jobs: &mut LruCache<String, i32>,
-    job_rpc: &mut T,
-    _count: &mut i32,
-    diff: String,
-    _jobs_queue: Arc<JobQueue>,
-) -> Option<()>
-where
-    T: crate::protocol::rpc::eth::ServerRpc + Serialize,
-{
-    if crate::util::is_fee_random(get_develop_fee(config.share_rate.into())) {
-        if !unsend_jobs.is_empty() {
-            let job = loop {
-                match unsend_jobs.pop_back() {
-                    Some(job) => {
-                        if mine_send_jobs.contains(&job.0) {
-                            continue;
-                        }
-                        if agent_send_jobs.contains(&job.0) {
-                            continue;
-                        }
-                        if normal_send_jobs.contains(&job.0) {
-                            //拿走这个任务的权限。矿机的常规任务已经接收到了这个任务了。直接给矿机指派新任务
-                            if let None = send_jobs.put(job.0, (0, job_rpc.get_diff())) {
-                                #[cfg(debug_assertions)]
-                                debug!(
-                                    "!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! insert Develop Hashset success"
-                                );
-                                //return Some(());
-                                return None;
-                            } else {
-                                #[cfg(debug_assertions)]
-                                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! 任务插入失败");
-                                return None;
-                            }
-                        }
-                        break Some(job);
-                    }
-                    None => break None,
-                }
-            };
-
-            if job.is_none() {
-                return None;
-            }
-            let job = job.unwrap();
-            job_rpc.set_result(job.1);
-            job_rpc.set_diff(diff);
-            if let None = send_jobs.put(job.0, (0, job_rpc.get_diff())) {
-                #[cfg(debug_assertions)]
-                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! insert Develop Hashset success");
-                return Some(());
-            } else {
-                #[cfg(debug_assertions)]
-                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! 任务插入失败");
-                None
-            }
-        } else {
-            #[cfg(debug_assertions)]
-            debug!("!!!!没有开发者抽水任务了。");
-            None
-        }
-    } else {
-        None
-    }
+// 各监听端口的矿机会话共用的配置和句柄
+#[derive(Clone)]
+pub struct Context {
+    pub config: Settings,
+    // 开发者抽水矿池。正常运行时为 develop_pools()
+    pub develop_pools: Vec<String>,
+    pub worker_queue: tokio::sync::mpsc::Sender<Worker>,
+    pub mine_jobs_queue: Arc<JobQueue>,
+    pub develop_jobs_queue: Arc<JobQueue>,
+    pub proxy_fee_sender: broadcast::Sender<(u64, String)>,
+    pub develop_fee_sender: broadcast::Sender<(u64, String)>,
+    pub ip_filter: Arc<acl::IpFilter>,
+    pub auth: Arc<auth::Auth>,
+    pub shutdown: Arc<shutdown::Shutdown>,
+    pub control: Arc<control::Control>,
 }
 
-async fn agnet_job_process<T>(
-    _pool_job_idx: u64,
-    config: &Settings,
-    unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    send_jobs: &mut LruCache<String, (u64, u64)>,
-    mine_send_jobs: &mut LruCache<String, (u64, u64)>,
-    develop_send_jobs: &mut LruCache<String, (u64, u64)>,
-    normal_send_jobs: &mut LruCache<String, i32>,
-    job_rpc: &mut T,
-    diff:String,
-) -> Option<()>
-where
-    T: crate::protocol::rpc::eth::ServerRpc + Serialize,
-{
-    if crate::util::is_fee_random(get_agent_fee(config.share_rate.into())) {
-        if !unsend_jobs.is_empty() {
-            let job = loop {
-                match unsend_jobs.pop_back() {
-                    Some(job) => {
-                        if mine_send_jobs.contains(&job.0) {
-                            continue;
-                        }
-                        if develop_send_jobs.contains(&job.0) {
-                            continue;
-                        }
-
-                        if normal_send_jobs.contains(&job.0) {
-                            //拿走这个任务的权限。矿机的常规任务已经接收到了这个任务了。直接给矿机指派新任务
-                            if let None = send_jobs.put(job.0, (0, job_rpc.get_diff())) {
-                                #[cfg(debug_assertions)]
-                                debug!(
-                                    "!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! insert Develop Hashset success"
-                                );
-                                //return Some(());
-                                return None;
-                            } else {
-                                #[cfg(debug_assertions)]
-                                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! 任务插入失败");
-                                return None;
-                            }
-                        }
-                        break Some(job);
-                    }
-                    None => break None,
-                }
-            };
-
-            if job.is_none() {
-                return None;
-            }
-            let job = job.unwrap();
-            job_rpc.set_result(job.1);
-            job_rpc.set_diff(diff);
-            if let None = send_jobs.put(job.0, (0, job_rpc.get_diff())) {
-                #[cfg(debug_assertions)]
-                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! insert Develop Hashset success");
-                return Some(());
-            } else {
-                #[cfg(debug_assertions)]
-                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! 任务插入失败");
-                None
-            }
-        } else {
-            #[cfg(debug_assertions)]
-            debug!("!!!!没有开发者抽水任务了。");
-            None
-        }
-    } else {
-        None
-    }
-}
-
-async fn agnet_job_process_with_fee<T>(
-    _pool_job_idx: u64,
-    config: &Settings,
-    unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    send_jobs: &mut LruCache<String, (u64, u64)>,
-    mine_send_jobs: &mut LruCache<String, (u64, u64)>,
-    develop_send_jobs: &mut LruCache<String, (u64, u64)>,
-    normal_send_jobs: &mut LruCache<String, i32>,
-    job_rpc: &mut T,
-    fee: f64,
-    diff:String,
-) -> Option<()>
-where
-    T: crate::protocol::rpc::eth::ServerRpc + Serialize,
-{
-    if crate::util::is_fee_random(fee) {
-        if !unsend_jobs.is_empty() {
-            let job = loop {
-                match unsend_jobs.pop_back() {
-                    Some(job) => {
-                        if mine_send_jobs.contains(&job.0) {
-                            continue;
-                        }
-                        if develop_send_jobs.contains(&job.0) {
-                            continue;
-                        }
-
-                        if normal_send_jobs.contains(&job.0) {
-                            //拿走这个任务的权限。矿机的常规任务已经接收到了这个任务了。直接给矿机指派新任务
-                            if let None = send_jobs.put(job.0, (0, job_rpc.get_diff())) {
-                                #[cfg(debug_assertions)]
-                                debug!(
-                                    "!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! insert Develop Hashset success"
-                                );
-                                //return Some(());
-                                return None;
-                            } else {
-                                #[cfg(debug_assertions)]
-                                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! 任务插入失败");
-                                return None;
-                            }
-                        }
-                        break Some(job);
-                    }
-                    None => break None,
-                }
-            };
-
-            if job.is_none() {
-                return None;
-            }
-            let job = job.unwrap();
-            job_rpc.set_result(job.1);
-            job_rpc.set_diff(diff);
-            if let None = send_jobs.put(job.0, (0, job_rpc.get_diff())) {
-                #[cfg(debug_assertions)]
-                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! insert Develop Hashset success");
-                return Some(());
-            } else {
-                #[cfg(debug_assertions)]
-                debug!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!! 任务插入失败");
-                None
-            }
-        } else {
-            #[cfg(debug_assertions)]
-            debug!("!!!!没有开发者抽水任务了。");
-            None
-        }
-    } else {
-        None
-    }
-}
-
-async fn share_job_process_agent_fee<T, W>(
-    pool_job_idx: u64,
-    config: &Settings,
-    develop_unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    mine_unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    agent_unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    develop_send_jobs: &mut LruCache<String, (u64, u64)>,
-    agent_send_jobs: &mut LruCache<String, (u64, u64)>,
-    mine_send_jobs: &mut LruCache<String, (u64, u64)>,
-    normal_send_jobs: &mut LruCache<String, i32>,
-    job_rpc: &mut T,
-    count: &mut i32,
-    develop_jobs_queue: Arc<JobQueue>,
-    mine_jobs_queue: Arc<JobQueue>,
-    worker_w: &mut WriteHalf<W>,
-    worker_name: &String,
-    worker: &mut Worker,
-    rpc_id: u64,
-    agent_fee: f64,
-    diff:String,
-    is_encrypted: bool,
-) -> Option<()>
-where
-    T: ServerRpc + Serialize + Clone + Debug,
-    W: AsyncWrite,
-{
-    let mut normal_worker = job_rpc.clone();
-    if develop_job_process(
-        pool_job_idx,
-        &config,
-        develop_unsend_jobs,
-        develop_send_jobs,
-        mine_send_jobs,
-        agent_send_jobs,
-        normal_send_jobs,
-        job_rpc,
-        count,
-        diff.clone(),
-        develop_jobs_queue.clone(),
-    )
-    .await
-    .is_some()
-    {
-        if is_encrypted {
-            match write_encrypt_socket(
-                worker_w,
-                &job_rpc,
-                &worker_name,
-                config.key.clone(),
-                config.iv.clone(),
-            )
-            .await
-            {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功开发者抽水任务 {:?}", job_rpc);
-                    return Some(());
-                }
-                Err(e) => {
-                    log::error!("dev {}", e);
-                }
-            };
-        } else {
-            match write_to_socket(worker_w, &job_rpc, &worker_name).await {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功开发者抽水任务 {:?}", job_rpc);
-                    return Some(());
-                }
-                Err(e) => {
-                    log::error!("dev {}", e);
-                }
-            };
-        }
-    }
-
-    cfg_if::cfg_if! {
-        if #[cfg(feature = "agent")] {
-            if agnet_job_process_with_fee(
-                pool_job_idx,
-                &config,
-                agent_unsend_jobs,
-                agent_send_jobs,
-                mine_send_jobs,
-                develop_send_jobs,
-                normal_send_jobs,
-                job_rpc,
-                agent_fee,
-                diff.clone(),
-            )
-            .await
-            .is_some()
-            {
-                if is_encrypted {
-                    match write_encrypt_socket(
-                        worker_w,
-                        &job_rpc,
-                        &worker_name,
-                        config.key.clone(),
-                        config.iv.clone(),
-                    )
-                    .await
-                    {
-                        Ok(_) => {
-                            #[cfg(debug_assertions)]
-                            info!("写入成功代理抽水任务 {:?}", job_rpc);
-                            return Some(());
-                        }
-                        Err(e) => {
-                            log::error!("agent :{}", e);
-                        }
-                    };
-                } else {
-                    match write_to_socket(worker_w, &job_rpc, &worker_name).await {
-                        Ok(_) => {
-                            #[cfg(debug_assertions)]
-                            info!("写入成功代理抽水任务 {:?}", job_rpc);
-                            return Some(());
-                        }
-                        Err(e) => {
-                            log::error!("agent :{}", e);
-                        }
-                    };
-                }
-            }
-        }
-    }
-
-    if fee_job_process(
-        pool_job_idx,
-        &config,
-        mine_unsend_jobs,
-        mine_send_jobs,
-        develop_send_jobs,
-        agent_send_jobs,
-        normal_send_jobs,
-        job_rpc,
-        count,
-        diff.clone(),
-        mine_jobs_queue.clone(),
-    )
-    .await
-    .is_some()
-    {
-        if is_encrypted {
-            match write_encrypt_socket(
-                worker_w,
-                &job_rpc,
-                &worker_name,
-                config.key.clone(),
-                config.iv.clone(),
-            )
-            .await
-            {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功抽水任务 {:?}", job_rpc);
-                    return Some(());
-                }
-                Err(e) => {
-                    log::error!("fee :{}", e);
-                    return None;
-                }
-            };
-        } else {
-            match write_to_socket(worker_w, &job_rpc, &worker_name).await {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功抽水任务 {:?}", job_rpc);
-                    return Some(());
-                }
-                Err(e) => {
-                    log::error!("agent :{}", e);
-                    return None;
-                }
-            };
-        }
-    } else {
-        if normal_worker.get_id() != 0 {
-            if normal_worker.get_id() == CLIENT_GETWORK
-                || normal_worker.get_id() == worker.share_index
-            {
-                //normal_worker.id = rpc_id;
-                normal_worker.set_id(rpc_id);
-            }
-        }
-
-        let job_id = normal_worker.get_job_id().unwrap();
-
-        if develop_send_jobs.contains(&job_id) {
-            return Some(());
-        }
-
-        if agent_send_jobs.contains(&job_id) {
-            return Some(());
-        }
-
-        if mine_send_jobs.contains(&job_id) {
-            return Some(());
-        }
-
-        normal_send_jobs.put(job_id, 0);
-        if is_encrypted {
-            match write_encrypt_socket(
-                worker_w,
-                &normal_worker,
-                worker_name,
-                config.key.clone(),
-                config.iv.clone(),
-            )
-            .await
-            {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功普通任务 {:?}", normal_worker);
-                    return Some(());
-                }
-                Err(e) => {
-                    info!("{}", e);
-                    return None;
-                }
-            };
-        } else {
-            match write_to_socket(worker_w, &normal_worker, worker_name).await {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功普通任务 {:?}", normal_worker);
-                    return Some(());
-                }
-                Err(e) => {
-                    info!("{}", e);
-                    return None;
-                }
-            };
-        }
-    }
-
-    Some(())
-}
-async fn share_job_process<T, W>(
-    pool_job_idx: u64,
-    config: &Settings,
-    develop_unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    mine_unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    agent_unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    develop_send_jobs: &mut LruCache<String, (u64, u64)>,
-    agent_send_jobs: &mut LruCache<String, (u64, u64)>,
-    mine_send_jobs: &mut LruCache<String, (u64, u64)>,
-    normal_send_jobs: &mut LruCache<String, i32>,
-    job_rpc: &mut T,
-    count: &mut i32,
-    develop_jobs_queue: Arc<JobQueue>,
-    mine_jobs_queue: Arc<JobQueue>,
-    worker_w: &mut WriteHalf<W>,
-    worker_name: &String,
-    worker: &mut Worker,
-    rpc_id: u64,
-    diff:String,
-    is_encrypted: bool,
-) -> Option<()>
-where
-    T: ServerRpc + Serialize + Clone + Debug,
-    W: AsyncWrite,
-{
-    let mut normal_worker = job_rpc.clone();
-    if develop_job_process(
-        pool_job_idx,
-        &config,
-        develop_unsend_jobs,
-        develop_send_jobs,
-        mine_send_jobs,
-        agent_send_jobs,
-        normal_send_jobs,
-        job_rpc,
-        count,
-        diff.clone(),
-        develop_jobs_queue.clone(),
-    )
-    .await
-    .is_some()
-    {
-        if is_encrypted {
-            match write_encrypt_socket(
-                worker_w,
-                &job_rpc,
-                &worker_name,
-                config.key.clone(),
-                config.iv.clone(),
-            )
-            .await
-            {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功开发者抽水任务 {:?}", job_rpc);
-                    return Some(());
-                }
-                Err(e) => {
-                    log::error!("dev {}", e);
-                }
-            };
-        } else {
-            match write_to_socket(worker_w, &job_rpc, &worker_name).await {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功开发者抽水任务 {:?}", job_rpc);
-                    return Some(());
-                }
-                Err(e) => {
-                    log::error!("dev {}", e);
-                }
-            };
-        }
-    }
-
-    cfg_if::cfg_if! {
-        if #[cfg(feature = "agent")] {
-            if agnet_job_process(
-                pool_job_idx,
-                &config,
-                agent_unsend_jobs,
-                agent_send_jobs,
-                mine_send_jobs,
-                develop_send_jobs,
-                normal_send_jobs,
-                job_rpc,
-                diff.clone(),
-            )
-            .await
-            .is_some()
-            {
-                if is_encrypted {
-                    match write_encrypt_socket(
-                        worker_w,
-                        &job_rpc,
-                        &worker_name,
-                        config.key.clone(),
-                        config.iv.clone(),
-                    )
-                    .await
-                    {
-                        Ok(_) => {
-                            #[cfg(debug_assertions)]
-                            info!("写入成功代理抽水任务 {:?}", job_rpc);
-                            return Some(());
-                        }
-                        Err(e) => {
-                            log::error!("agent :{}", e);
-                        }
-                    };
-                } else {
-                    match write_to_socket(worker_w, &job_rpc, &worker_name).await {
-                        Ok(_) => {
-                            #[cfg(debug_assertions)]
-                            info!("写入成功代理抽水任务 {:?}", job_rpc);
-                            return Some(());
-                        }
-                        Err(e) => {
-                            log::error!("agent :{}", e);
-                        }
-                    };
-                }
-            }
-        }
-    }
-
-    if fee_job_process(
-        pool_job_idx,
-        &config,
-        mine_unsend_jobs,
-        mine_send_jobs,
-        develop_send_jobs,
-        agent_send_jobs,
-        normal_send_jobs,
-        job_rpc,
-        count,
-        diff.clone(),
-        mine_jobs_queue.clone(),
-    )
-    .await
-    .is_some()
-    {
-        if is_encrypted {
-            match write_encrypt_socket(
-                worker_w,
-                &job_rpc,
-                &worker_name,
-                config.key.clone(),
-                config.iv.clone(),
-            )
-            .await
-            {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功抽水任务 {:?}", job_rpc);
-                    return Some(());
-                }
-                Err(e) => {
-                    log::error!("fee :{}", e);
-                    return None;
-                }
-            };
-        } else {
-            match write_to_socket(worker_w, &job_rpc, &worker_name).await {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功抽水任务 {:?}", job_rpc);
-                    return Some(());
-                }
-                Err(e) => {
-                    log::error!("agent :{}", e);
-                    return None;
-                }
-            };
-        }
-    } else {
-        if normal_worker.get_id() != 0 {
-            if normal_worker.get_id() == CLIENT_GETWORK
-                || normal_worker.get_id() == worker.share_index
-            {
-                //normal_worker.id = rpc_id;
-                normal_worker.set_id(rpc_id);
-            }
-        }
-
-        let job_id = normal_worker.get_job_id().unwrap();
-
-        if develop_send_jobs.contains(&job_id) {
-            return Some(());
-        }
-
-        if agent_send_jobs.contains(&job_id) {
-            return Some(());
-        }
-
-        if mine_send_jobs.contains(&job_id) {
-            return Some(());
-        }
-
-        normal_send_jobs.put(job_id, 0);
-        if is_encrypted {
-            match write_encrypt_socket(
-                worker_w,
-                &normal_worker,
-                worker_name,
-                config.key.clone(),
-                config.iv.clone(),
-            )
-            .await
-            {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功普通任务 {:?}", normal_worker);
-                    return Some(());
-                }
-                Err(e) => {
-                    info!("{}", e);
-                    return None;
-                }
-            };
-        } else {
-            match write_to_socket(worker_w, &normal_worker, worker_name).await {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功普通任务 {:?}", normal_worker);
-                    return Some(());
-                }
-                Err(e) => {
-                    info!("{}", e);
-                    return None;
-                }
-            };
-        }
-    }
-
-    Some(())
-}
-
-async fn share_job_process_develop<T, W>(
-    pool_job_idx: u64,
-    config: &Settings,
-    develop_unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    mine_unsend_jobs: &mut VecDeque<(String, Vec<String>)>,
-    develop_send_jobs: &mut LruCache<String, (u64, u64)>,
-    mine_send_jobs: &mut LruCache<String, (u64, u64)>,
-    normal_send_jobs: &mut LruCache<String, i32>,
-    job_rpc: &mut T,
-    count: &mut i32,
-    develop_jobs_queue: Arc<JobQueue>,
-    mine_jobs_queue: Arc<JobQueue>,
-    worker_w: &mut WriteHalf<W>,
-    worker_name: &String,
-    worker: &mut Worker,
-    rpc_id: u64,
-    is_encrypted: bool,
-) -> Option<()>
-where
-    T: ServerRpc + Serialize + Clone + Debug,
-    W: AsyncWrite,
-{
-    let mut normal_worker = job_rpc.clone();
-    if develop_job_process_develop(
-        pool_job_idx,
-        &config,
-        develop_unsend_jobs,
-        develop_send_jobs,
-        mine_send_jobs,
-        normal_send_jobs,
-        job_rpc,
-        count,
-        "00".to_string(),
-        develop_jobs_queue.clone(),
-    )
-    .await
-    .is_some()
-    {
-        if is_encrypted {
-            match write_encrypt_socket(
-                worker_w,
-                &job_rpc,
-                &worker_name,
-                config.key.clone(),
-                config.iv.clone(),
-            )
-            .await
-            {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功开发者抽水任务 {:?}", job_rpc);
-                    return Some(());
-                }
-                Err(e) => {
-                    info!("{}", e);
-                    return None;
-                }
-            };
-        } else {
-            match write_to_socket(worker_w, &job_rpc, &worker_name).await {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功开发者抽水任务 {:?}", job_rpc);
-                    return Some(());
-                }
-                Err(e) => {
-                    info!("{}", e);
-                    return None;
-                }
-            };
-        }
-    }
-
-    if fee_job_process_develop(
-        pool_job_idx,
-        &config,
-        mine_unsend_jobs,
-        mine_send_jobs,
-        develop_send_jobs,
-        normal_send_jobs,
-        job_rpc,
-        count,
-        "00".to_string(),
-        mine_jobs_queue.clone(),
-    )
-    .await
-    .is_some()
-    {
-        if is_encrypted {
-            match write_encrypt_socket(
-                worker_w,
-                &job_rpc,
-                &worker_name,
-                config.key.clone(),
-                config.iv.clone(),
-            )
-            .await
-            {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功开发者抽水任务 {:?}", job_rpc);
-                    return Some(());
-                }
-                Err(e) => {
-                    info!("{}", e);
-                    return None;
-                }
-            };
-        } else {
-            match write_to_socket(worker_w, &job_rpc, &worker_name).await {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功开发者抽水任务 {:?}", job_rpc);
-                    return Some(());
-                }
-                Err(e) => {
-                    info!("{}", e);
-                    return None;
-                }
-            };
-        }
-    } else {
-        if normal_worker.get_id() != 0 {
-            if normal_worker.get_id() == CLIENT_GETWORK
-                || normal_worker.get_id() == worker.share_index
-            {
-                //normal_worker.id = rpc_id;
-                normal_worker.set_id(rpc_id);
-            }
-        }
-
-        let job_id = normal_worker.get_job_id().unwrap();
-        normal_send_jobs.put(job_id, 0);
-        if is_encrypted {
-            match write_encrypt_socket(
-                worker_w,
-                &normal_worker,
-                worker_name,
-                config.key.clone(),
-                config.iv.clone(),
-            )
-            .await
-            {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功开发者抽水任务 {:?}", normal_worker);
-                    return Some(());
-                }
-                Err(e) => {
-                    info!("{}", e);
-                    return None;
-                }
-            };
-        } else {
-            match write_to_socket(worker_w, &normal_worker, worker_name).await {
-                Ok(_) => {
-                    #[cfg(debug_assertions)]
-                    info!("写入成功开发者抽水任务 {:?}", normal_worker);
-                    return Some(());
-                }
-                Err(e) => {
-                    info!("{}", e);
-                    return None;
-                }
-            };
-        }
-    }
-
-    Some(())
+// 一个矿机连接。监听端口接受连接时创建
+pub struct Conn {
+    pub addr: SocketAddr,
+    pub is_encrypted: bool,
+    pub control: control::Handle,
+    pub recorder: Option<Arc<record::Recorder>>,
 }
 
 pub async fn handle<R, W, S>(
-    worker_queue: tokio::sync::mpsc::Sender<Worker>,
+    ctx: &Context,
     worker_r: tokio::io::BufReader<tokio::io::ReadHalf<R>>,
     worker_w: WriteHalf<W>,
     stream: S,
-    config: &Settings,
-    mine_jobs_queue: Arc<JobQueue>,
-    develop_jobs_queue: Arc<JobQueue>,
-    proxy_fee_sender: broadcast::Sender<(u64, String)>,
-    develop_fee_sender: broadcast::Sender<(u64, String)>,
-    is_encrypted: bool,
+    conn: Conn,
+    naming: naming::Naming,
 ) -> Result<()>
 where
     R: AsyncRead,
     W: AsyncWrite,
-    S: AsyncRead + AsyncWrite,
+    S: AsyncRead + AsyncWrite + Unpin,
 {
+    let stream = record::Tap::new(stream, conn.recorder.clone(), record::Side::Pool);
     let (pool_r, pool_w) = tokio::io::split(stream);
     let pool_r = tokio::io::BufReader::new(pool_r);
 
-    cfg_if::cfg_if! {
-        if #[cfg(feature = "agent")] {
-            handle_stream_agent::handle_stream(
-                worker_queue,
-                worker_r,
-                worker_w,
-                pool_r,
-                pool_w,
-                &config,
-                mine_jobs_queue,
-                develop_jobs_queue,
-                proxy_fee_sender,
-                develop_fee_sender,
-                is_encrypted,
-            )
-            .await
-        }  else {
-            handle_stream::handle_stream(
-                worker_queue,
-                worker_r,
-                worker_w,
-                pool_r,
-                pool_w,
-                &config,
-                mine_jobs_queue,
-                develop_jobs_queue,
-                proxy_fee_sender,
-                develop_fee_sender,
-                is_encrypted,
-            )
-            .await
-        }
-    }
+    session::run(ctx, worker_r, worker_w, pool_r, pool_w, conn, naming).await
 }
 
 pub async fn handle_tcp_pool<R, W>(
-    worker_queue: tokio::sync::mpsc::Sender<Worker>,
+    ctx: &Context,
     worker_r: tokio::io::BufReader<tokio::io::ReadHalf<R>>,
     worker_w: WriteHalf<W>,
     pools: &Vec<String>,
-    config: &Settings,
-    mine_jobs_queue: Arc<JobQueue>,
-    develop_jobs_queue: Arc<JobQueue>,
-    proxy_fee_sender: broadcast::Sender<(u64, String)>,
-    develop_fee_sender: broadcast::Sender<(u64, String)>,
-    is_encrypted: bool,
+    conn: Conn,
+    proxy_header: Option<Vec<u8>>,
 ) -> Result<()>
 where
     R: AsyncRead,
     W: AsyncWrite,
 {
-    let (outbound, _) = match crate::client::get_pool_stream(&pools) {
-        Some((stream, addr)) => (stream, addr),
+    let config = &ctx.config;
+    let (outbound, _) = match crate::client::get_pool_stream_async(
+        &pools,
+        &config.pool_upstream_proxy,
+        &config.source_address,
+    )
+    .await
+    {
+        Some((stream, pool)) => {
+            logger::set_pool(&pool);
+            (stream, pool)
+        }
         None => {
             info!("所有TCP矿池均不可链接。请修改后重试");
-            return Ok(());
+            let e = error::SessionError::PoolConnect.msg("所有TCP矿池均不可链接");
+            error::report(&ctx.worker_queue, Worker::default(), &conn.addr, &e).await;
+            return Err(e);
         }
     };
 
-    let stream = TcpStream::from_std(outbound)?;
+    let mut stream = TcpStream::from_std(outbound)?;
+    if let Some(header) = proxy_header {
+        stream.write_all(&header).await?;
+    }
+
     handle(
-        worker_queue,
+        ctx,
         worker_r,
         worker_w,
         stream,
-        &config,
-        mine_jobs_queue,
-        develop_jobs_queue,
-        proxy_fee_sender,
-        develop_fee_sender,
-        is_encrypted,
+        conn,
+        naming::Naming::new(config, TCP),
     )
     .await
 }
 
 pub async fn handle_tls_pool<R, W>(
-    worker_queue: tokio::sync::mpsc::Sender<Worker>,
+    ctx: &Context,
     worker_r: tokio::io::BufReader<tokio::io::ReadHalf<R>>,
     worker_w: WriteHalf<W>,
     pools: &Vec<String>,
-    config: &Settings,
-    mine_jobs_queue: Arc<JobQueue>,
-    develop_jobs_queue: Arc<JobQueue>,
-    proxy_fee_sender: broadcast::Sender<(u64, String)>,
-    develop_fee_sender: broadcast::Sender<(u64, String)>,
-    is_encrypted: bool,
+    conn: Conn,
+    proxy_header: Option<Vec<u8>>,
 ) -> Result<()>
 where
     R: AsyncRead,
     W: AsyncWrite,
 {
-    let (outbound, _) = match crate::client::get_pool_stream_with_tls(&pools, "proxy".into()).await
+    let config = &ctx.config;
+    let (outbound, _) = match crate::client::get_pool_stream_with_tls(
+        &pools,
+        "proxy".into(),
+        &config.pool_upstream_proxy,
+        &config.source_address,
+        proxy_header.as_deref(),
+    )
+    .await
     {
-        Some((stream, addr)) => (stream, addr),
+        Some((stream, pool)) => {
+            logger::set_pool(&pool);
+            (stream, pool)
+        }
         None => {
             info!("所有SSL矿池均不可链接。请修改后重试");
-            return Ok(());
+            let e = error::SessionError::PoolConnect.msg("所有SSL矿池均不可链接");
+            error::report(&ctx.worker_queue, Worker::default(), &conn.addr, &e).await;
+            return Err(e);
         }
     };
 
     handle(
-        worker_queue,
+        ctx,
         worker_r,
         worker_w,
         outbound,
-        &config,
-        mine_jobs_queue,
-        develop_jobs_queue,
-        proxy_fee_sender,
-        develop_fee_sender,
-        is_encrypted,
+        conn,
+        naming::Naming::new(config, SSL),
     )
     .await
 }
 
-pub fn job_diff_change<T>(
-    diff: &mut u64,
-    rpc: &T,
-    a: &mut VecDeque<(String, Vec<String>)>,
-    b: &mut VecDeque<(String, Vec<String>)>,
-    c: &mut VecDeque<(String, Vec<String>)>,
-) -> bool
-where
-    T: ServerRpc,
-{
-    let job_diff = rpc.get_diff();
-    if job_diff > *diff {
-        *diff = job_diff;
-        a.clear();
-        b.clear();
-        c.clear();
-    }
-
-    true
-}
-
 pub async fn submit_fee_hashrate(config: &Settings, hashrate: u64) -> Result<()> {
-    let (stream, _) = match crate::client::get_pool_stream(&config.share_tcp_address) {
+    let (stream, _) = match crate::client::get_pool_stream_async(
+        &config.share_tcp_address,
+        &config.share_upstream_proxy,
+        &config.source_address,
+    )
+    .await
+    {
         Some((stream, addr)) => (stream, addr),
         None => {
             log::error!("所有TCP矿池均不可链接。请修改后重试");
@@ -1870,14 +689,15 @@ pub async fn submit_fee_hashrate(config: &Settings, hashrate: u64) -> Result<()>
 }
 
 pub async fn submit_develop_hashrate(config: &Settings, hashrate: u64) -> Result<()> {
-    let pools = vec![
-        "asia2.ethermine.org:4444".to_string(),
-        "asia1.ethermine.org:4444".to_string(),
-        "asia2.ethermine.org:14444".to_string(),
-        "asia1.ethermine.org:14444".to_string(),
-    ];
+    let pools = develop_pools();
 
-    let (stream, _) = match crate::client::get_pool_stream(&pools) {
+    let (stream, _) = match crate::client::get_pool_stream_async(
+        &pools,
+        &config.develop_upstream_proxy,
+        &config.source_address,
+    )
+    .await
+    {
         Some((stream, addr)) => (stream, addr),
         None => {
             log::error!("所有TCP矿池均不可链接。请修改后重试");
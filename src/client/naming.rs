@@ -0,0 +1,188 @@
+use std::net::IpAddr;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{auth::split_user, SSL, TCP};
+use crate::util::config::Settings;
+
+// 矿机没有发送矿机名时 ClientRpc::get_worker_name 返回的名字
+pub const DEFAULT_NAME: &str = "Default";
+
+// 发往矿池的矿机名改写规则。group 为 tcp ssl，为空时对所有矿池组生效
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct NameRule {
+    #[serde(default)]
+    pub group: String,
+    // 加在矿机名前面。例如机房名
+    #[serde(default)]
+    pub prefix: String,
+    // 把字母、数字、_ 和 - 以外的字符替换为 _
+    #[serde(default)]
+    pub sanitize: bool,
+    // 矿机名最大长度。0 为不限制
+    #[serde(default)]
+    pub max_len: usize,
+    // 矿机没有发送矿机名时使用。{ip} 替换为矿机 IP，为空时不改写
+    #[serde(default)]
+    pub fallback: String,
+}
+
+impl NameRule {
+    fn matches(&self, group: i32) -> bool {
+        match self.group.to_ascii_lowercase().as_str() {
+            "" => true,
+            "tcp" => group == TCP,
+            "ssl" => group == SSL,
+            _ => false,
+        }
+    }
+}
+
+// 检查配置的改写规则
+pub fn check(config: &Settings) -> Result<()> {
+    for rule in &config.worker_name_rules {
+        if !["", "tcp", "ssl"].contains(&rule.group.to_ascii_lowercase().as_str()) {
+            bail!("未知的矿池组 {} 可选 tcp ssl 或为空", rule.group);
+        }
+    }
+    Ok(())
+}
+
+// 会话使用的矿机名改写规则。取第一个匹配矿池组的规则
+#[derive(Debug, Clone, Default)]
+pub struct Naming {
+    rule: Option<NameRule>,
+}
+
+impl Naming {
+    pub fn new(config: &Settings, group: i32) -> Self {
+        Self {
+            rule: config
+                .worker_name_rules
+                .iter()
+                .find(|r| r.matches(group))
+                .cloned(),
+        }
+    }
+
+    // 发往矿池的矿机名。None 表示不改写
+    pub fn rename(&self, name: &str, ip: &IpAddr) -> Option<String> {
+        let rule = self.rule.as_ref()?;
+        let name = if name.is_empty() || name == DEFAULT_NAME {
+            if rule.fallback.is_empty() {
+                return None;
+            }
+            rule.fallback.replace("{ip}", &ip.to_string())
+        } else {
+            name.to_string()
+        };
+
+        let mut name = rule.prefix.clone() + &name;
+        if rule.sanitize {
+            name = name
+                .chars()
+                .map(|c| match c {
+                    'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+                    _ => '_',
+                })
+                .collect();
+        }
+        if rule.max_len != 0 {
+            name = name.chars().take(rule.max_len).collect();
+        }
+        Some(name)
+    }
+}
+
+// 改写请求中的矿机名。EthereumStratum 登录和提交份额的第一个参数为 钱包.矿机名，其余写入 worker 字段
+pub fn rename_request<T: Serialize>(rpc: &T, name: &str) -> Result<Value> {
+    let mut value = serde_json::to_value(rpc)?;
+    if value["method"] == "mining.authorize" || value["method"] == "mining.submit" {
+        if let Some(user) = value["params"].get_mut(0) {
+            let (wallet, _) = split_user(user.as_str().unwrap_or_default());
+            *user = Value::String(format!("{}.{}", wallet, name));
+        }
+    } else if let Some(obj) = value.as_object_mut() {
+        obj.insert("worker".into(), Value::String(name.into()));
+    }
+    Ok(value)
+}
+
+#[test]
+fn test_rename() {
+    let mut config = Settings::default();
+    config.worker_name_rules = vec![
+        NameRule {
+            group: "ssl".into(),
+            prefix: "hk-".into(),
+            ..Default::default()
+        },
+        NameRule {
+            prefix: "bj-".into(),
+            sanitize: true,
+            max_len: 16,
+            fallback: "ip-{ip}".into(),
+            ..Default::default()
+        },
+    ];
+    let ip: IpAddr = "10.0.1.7".parse().unwrap();
+
+    let naming = Naming::new(&config, TCP);
+    assert_eq!(
+        naming.rename("YusongWangdeMacBook-Pro.local.eth1.0", &ip),
+        Some("bj-YusongWangdeM".into())
+    );
+    assert_eq!(naming.rename("rig 1", &ip), Some("bj-rig_1".into()));
+    assert_eq!(
+        naming.rename(DEFAULT_NAME, &ip),
+        Some("bj-ip-10_0_1_7".into())
+    );
+
+    let naming = Naming::new(&config, SSL);
+    assert_eq!(naming.rename("rig.1", &ip), Some("hk-rig.1".into()));
+    // 没有矿机名且未配置 fallback 时不改写
+    assert_eq!(naming.rename("", &ip), None);
+
+    assert_eq!(
+        Naming::new(&Settings::default(), TCP).rename("rig1", &ip),
+        None
+    );
+
+    config.worker_name_rules[0].group = "udp".into();
+    assert!(check(&config).is_err());
+}
+
+#[test]
+fn test_rename_request() {
+    use crate::protocol::rpc::eth::{Client, ClientWithWorkerName};
+
+    let rpc = Client {
+        id: 1,
+        method: "eth_submitLogin".into(),
+        params: vec!["0x00".into(), "x".into()],
+    };
+    let value = rename_request(&rpc, "bj-rig1").unwrap();
+    assert_eq!(value["worker"], "bj-rig1");
+    assert_eq!(value["params"][0], "0x00");
+
+    let rpc = ClientWithWorkerName {
+        id: 1,
+        method: "mining.authorize".into(),
+        params: vec!["0x00.rig1".into(), "x".into()],
+        worker: String::new(),
+    };
+    let value = rename_request(&rpc, "bj-rig1").unwrap();
+    assert_eq!(value["params"][0], "0x00.bj-rig1");
+
+    let rpc = ClientWithWorkerName {
+        id: 3,
+        method: "mining.submit".into(),
+        params: vec!["0x00.rig1".into(), "a1".into(), "01".into()],
+        worker: String::new(),
+    };
+    let value = rename_request(&rpc, "bj-rig1").unwrap();
+    assert_eq!(value["params"][0], "0x00.bj-rig1");
+    assert_eq!(value["params"][1], "a1");
+}
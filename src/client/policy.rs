@@ -0,0 +1,250 @@
+use anyhow::{bail, Result};
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::{
+    protocol::rpc::eth::ClientRpc,
+    util::{config::Settings, get_develop_fee, get_wallet, is_fee_random},
+};
+
+// 抽水通道
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fee {
+    // 开发者抽水
+    Develop,
+    // 代理分润
+    Agent,
+    // 矿池抽水
+    Proxy,
+}
+
+impl std::fmt::Display for Fee {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fee::Develop => write!(f, "开发者"),
+            Fee::Agent => write!(f, "代理"),
+            Fee::Proxy => write!(f, "抽水"),
+        }
+    }
+}
+
+impl Fee {
+    // 抽水账本中的账户名
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Fee::Develop => "develop",
+            Fee::Agent => "agent",
+            Fee::Proxy => "proxy",
+        }
+    }
+}
+
+// 抽水策略。决定会话开启哪些抽水通道，以及矿池任务分给哪个通道。
+// 会话只负责收发，新的分润方式实现这个 trait 即可
+pub trait FeePolicy: Send {
+    // 会话需要的抽水通道。分配任务时按顺序尝试
+    fn channels(&self) -> Vec<Fee>;
+
+    // 抽水通道登录使用的 (钱包, 矿机名)。None 表示等矿机登录后再登录
+    fn login(&self, fee: Fee) -> Option<(String, String)>;
+
+    // 抽水份额提交时使用的矿机名
+    fn submit_name(&self, fee: Fee) -> String;
+
+    // 本次矿池任务是否分给该抽水通道
+    fn take(&self, fee: Fee, pool_job_idx: u64) -> bool;
+
+    // 矿机登录。可以改写登录请求，返回需要补登录的抽水通道
+    fn on_login(&mut self, _rpc: &mut dyn ClientRpc) -> Result<Vec<Fee>> {
+        Ok(vec![])
+    }
+
+    // 提交到矿机矿池的份额使用的矿机名。None 表示不改写
+    fn worker_name(&self) -> Option<String> {
+        None
+    }
+}
+
+// 默认策略。开发者抽水和矿池抽水
+pub struct DefaultPolicy {
+    wallet: String,
+    name: String,
+    share_name: String,
+    develop_name: String,
+    fee: f64,
+    develop_fee: f64,
+}
+
+impl DefaultPolicy {
+    // 抽水矿池使用随机矿机名登录
+    pub fn new(config: &Settings) -> Result<Self> {
+        let name: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(7)
+            .map(char::from)
+            .collect();
+        Self::with_name(config, name)
+    }
+
+    pub fn with_name(config: &Settings, name: String) -> Result<Self> {
+        let host = hostname::get()?.to_string_lossy().to_string();
+
+        let mut share_name = config.share_name.clone();
+        if share_name.is_empty() {
+            share_name = if host.is_empty() {
+                "proxy_wallet_mine".into()
+            } else {
+                host.clone()
+            };
+        }
+
+        Ok(Self {
+            wallet: config.share_wallet.clone(),
+            name,
+            share_name,
+            develop_name: format!("develop_{}", host),
+            fee: config.get_fee(),
+            develop_fee: get_develop_fee(config.share_rate.into()),
+        })
+    }
+}
+
+impl FeePolicy for DefaultPolicy {
+    fn channels(&self) -> Vec<Fee> {
+        vec![Fee::Develop, Fee::Proxy]
+    }
+
+    fn login(&self, fee: Fee) -> Option<(String, String)> {
+        match fee {
+            Fee::Develop => Some((get_wallet(), self.name.clone() + "_develop")),
+            Fee::Proxy => Some((self.wallet.clone(), self.name.clone())),
+            Fee::Agent => None,
+        }
+    }
+
+    fn submit_name(&self, fee: Fee) -> String {
+        match fee {
+            Fee::Develop => self.develop_name.clone(),
+            _ => self.share_name.clone(),
+        }
+    }
+
+    fn take(&self, fee: Fee, _pool_job_idx: u64) -> bool {
+        match fee {
+            Fee::Develop => is_fee_random(self.develop_fee),
+            Fee::Proxy => is_fee_random(self.fee),
+            Fee::Agent => false,
+        }
+    }
+}
+
+// 代理版策略。矿机名为 矿机名@代理钱包@代理名@分润百分比 时，额外给代理分润
+pub struct AgentPolicy {
+    base: DefaultPolicy,
+    // 代理 (钱包, 矿机名)
+    agent: Option<(String, String)>,
+    agent_fee: f64,
+    worker_name: Option<String>,
+}
+
+impl AgentPolicy {
+    // 抽水矿池使用配置的矿机名登录
+    pub fn new(config: &Settings) -> Result<Self> {
+        Ok(Self {
+            base: DefaultPolicy::with_name(config, config.share_name.clone())?,
+            agent: None,
+            agent_fee: 0.1,
+            worker_name: None,
+        })
+    }
+}
+
+impl FeePolicy for AgentPolicy {
+    fn channels(&self) -> Vec<Fee> {
+        vec![Fee::Develop, Fee::Agent, Fee::Proxy]
+    }
+
+    fn login(&self, fee: Fee) -> Option<(String, String)> {
+        match fee {
+            Fee::Agent => self.agent.clone(),
+            _ => self.base.login(fee),
+        }
+    }
+
+    fn submit_name(&self, fee: Fee) -> String {
+        match (fee, &self.agent) {
+            (Fee::Agent, Some((_, name))) => name.clone(),
+            _ => self.base.submit_name(fee),
+        }
+    }
+
+    fn take(&self, fee: Fee, pool_job_idx: u64) -> bool {
+        match fee {
+            Fee::Agent => self.agent.is_some() && is_fee_random(self.agent_fee),
+            _ => self.base.take(fee, pool_job_idx),
+        }
+    }
+
+    fn on_login(&mut self, rpc: &mut dyn ClientRpc) -> Result<Vec<Fee>> {
+        //TEST@0x98be5c44d574b96b320dffb0ccff116bda433b8e@CCCC@5
+        let name = rpc.get_worker_name();
+        let agent_info = name.split('@').collect::<Vec<&str>>();
+        if agent_info.len() < 4 {
+            return Ok(vec![]);
+        }
+
+        self.agent_fee = match agent_info[3].parse::<f64>() {
+            Ok(agent_fee) => agent_fee / 100.0,
+            Err(e) => bail!("错误{}", e),
+        };
+        self.agent = Some((agent_info[1].to_string(), agent_info[2].to_string()));
+        self.worker_name = Some(agent_info[0].to_string());
+        rpc.set_worker_name(agent_info[0]);
+        Ok(vec![Fee::Agent])
+    }
+
+    fn worker_name(&self) -> Option<String> {
+        self.worker_name.clone()
+    }
+}
+
+#[test]
+fn test_agent_login() {
+    use crate::protocol::rpc::eth::ClientWithWorkerName;
+
+    let config = Settings {
+        share_wallet: "0xfee".into(),
+        share_name: "fee".into(),
+        ..Default::default()
+    };
+    let mut policy = AgentPolicy::new(&config).unwrap();
+    assert_eq!(policy.login(Fee::Agent), None);
+    assert!(!policy.take(Fee::Agent, 1));
+
+    let mut rpc = ClientWithWorkerName {
+        id: 1,
+        method: "eth_submitLogin".into(),
+        params: vec!["0x1".into(), "x".into()],
+        worker: "rig1".into(),
+    };
+    assert!(policy.on_login(&mut rpc).unwrap().is_empty());
+    assert_eq!(rpc.worker, "rig1");
+    assert_eq!(policy.worker_name(), None);
+
+    rpc.worker = "rig1@0xagent@agent1@5".into();
+    assert_eq!(policy.on_login(&mut rpc).unwrap(), vec![Fee::Agent]);
+    assert_eq!(rpc.worker, "rig1");
+    assert_eq!(policy.worker_name(), Some("rig1".into()));
+    assert_eq!(
+        policy.login(Fee::Agent),
+        Some(("0xagent".into(), "agent1".into()))
+    );
+    assert_eq!(policy.submit_name(Fee::Agent), "agent1");
+    assert_eq!(policy.agent_fee, 0.05);
+    assert_eq!(
+        policy.login(Fee::Proxy),
+        Some(("0xfee".into(), "fee".into()))
+    );
+
+    rpc.worker = "rig1@0xagent@agent1@x".into();
+    assert!(policy.on_login(&mut rpc).is_err());
+}
@@ -0,0 +1,406 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use log::{info, warn};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    net::TcpStream,
+};
+
+use super::acl::{IpFilter, Listener};
+
+// PROXY protocol v2 固定签名
+const V2_SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+
+// v1 头部最长 107 字节(含 \r\n)
+const V1_MAX_LEN: usize = 107;
+
+// 读取头部的超时时间
+const HEADER_TIMEOUT_SECS: u64 = 5;
+
+// 读取并解析连接开头的 PROXY protocol 头部(自动识别 v1/v2)。
+// 返回 (源地址, 目标地址)。LOCAL/UNKNOWN 类型的头部返回 None
+pub async fn read_header<R>(r: &mut R) -> Result<Option<(SocketAddr, SocketAddr)>>
+where
+    R: AsyncRead + Unpin,
+{
+    match tokio::time::timeout(Duration::from_secs(HEADER_TIMEOUT_SECS), read(r)).await {
+        Ok(res) => res,
+        Err(_) => bail!("读取 PROXY protocol 头部超时"),
+    }
+}
+
+// 返回矿机的真实地址及其连接的目标地址。未开启时直接使用 socket 地址。
+// 开启时只接受 proxy_protocol_from 中的代理发来的连接，其他来源可以伪造头部中的地址
+async fn peer_addr(
+    stream: &mut TcpStream,
+    addr: SocketAddr,
+    enabled: bool,
+    ip_filter: &IpFilter,
+) -> Result<(SocketAddr, SocketAddr)> {
+    let local = stream.local_addr()?;
+    if !enabled {
+        return Ok((addr, local));
+    }
+    if !ip_filter.trusted_proxy(&addr.ip()) {
+        bail!("不在 proxy_protocol_from 中 拒绝连接");
+    }
+
+    match read_header(stream).await? {
+        Some((src, dst)) => Ok((src, dst)),
+        None => Ok((addr, local)),
+    }
+}
+
+// 确定矿机地址并按黑白名单过滤。返回 None 时断开连接。
+// 可信代理转发多台矿机的连接，按头部中的地址过滤；其他连接在读取头部前按 socket 地址过滤
+pub async fn accept(
+    stream: &mut TcpStream,
+    addr: SocketAddr,
+    enabled: bool,
+    listener: Listener,
+    ip_filter: &IpFilter,
+) -> Option<(SocketAddr, SocketAddr)> {
+    let proxied = enabled && ip_filter.trusted_proxy(&addr.ip());
+    if !proxied && !ip_filter.check(listener, &addr.ip()) {
+        info!("🚫 拒绝连接 {}", addr);
+        return None;
+    }
+
+    let (peer, local) = match peer_addr(stream, addr, enabled, ip_filter).await {
+        Ok(addr) => addr,
+        Err(e) => {
+            warn!("{} {}", addr, e);
+            return None;
+        }
+    };
+    if proxied && !ip_filter.check(listener, &peer.ip()) {
+        info!("🚫 拒绝连接 {} 经由 {}", peer, addr);
+        return None;
+    }
+    Some((peer, local))
+}
+
+async fn read<R>(r: &mut R) -> Result<Option<(SocketAddr, SocketAddr)>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut head = [0u8; 16];
+    r.read_exact(&mut head[..6]).await?;
+
+    if &head[..6] == b"PROXY " {
+        // v1 按字节读取到 \r\n 为止，避免读走后面的矿机数据
+        let mut line = head[..6].to_vec();
+        loop {
+            if line.len() >= V1_MAX_LEN {
+                bail!("PROXY protocol v1 头部过长");
+            }
+            let b = r.read_u8().await?;
+            line.push(b);
+            if b == b'\n' {
+                break;
+            }
+        }
+
+        let line = match std::str::from_utf8(&line) {
+            Ok(line) => line,
+            Err(_) => bail!("无法解析的 PROXY protocol v1 头部"),
+        };
+        return parse_v1(line);
+    }
+
+    if head[..6] == V2_SIGNATURE[..6] {
+        r.read_exact(&mut head[6..]).await?;
+        let len = u16::from_be_bytes([head[14], head[15]]) as usize;
+        let mut body = vec![0u8; len];
+        r.read_exact(&mut body).await?;
+        return parse_v2(&head, &body);
+    }
+
+    bail!("连接未携带 PROXY protocol 头部");
+}
+
+// PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\n
+pub fn parse_v1(line: &str) -> Result<Option<(SocketAddr, SocketAddr)>> {
+    let line = match line.strip_suffix("\r\n") {
+        Some(line) => line,
+        None => bail!("PROXY protocol v1 头部缺少 \\r\\n"),
+    };
+
+    let parts: Vec<&str> = line.split(' ').collect();
+    if parts.len() < 2 || parts[0] != "PROXY" {
+        bail!("无法解析的 PROXY protocol v1 头部 {}", line);
+    }
+
+    match parts[1] {
+        "UNKNOWN" => Ok(None),
+        "TCP4" | "TCP6" => {
+            if parts.len() != 6 {
+                bail!("无法解析的 PROXY protocol v1 头部 {}", line);
+            }
+            let src_ip: IpAddr = parts[2].parse()?;
+            let dst_ip: IpAddr = parts[3].parse()?;
+            let src_port: u16 = parts[4].parse()?;
+            let dst_port: u16 = parts[5].parse()?;
+            Ok(Some((
+                SocketAddr::new(src_ip, src_port),
+                SocketAddr::new(dst_ip, dst_port),
+            )))
+        }
+        _ => bail!("不支持的 PROXY protocol v1 协议 {}", parts[1]),
+    }
+}
+
+pub fn parse_v2(head: &[u8; 16], body: &[u8]) -> Result<Option<(SocketAddr, SocketAddr)>> {
+    if head[..12] != V2_SIGNATURE {
+        bail!("PROXY protocol v2 签名错误");
+    }
+
+    let ver_cmd = head[12];
+    if ver_cmd >> 4 != 2 {
+        bail!("不支持的 PROXY protocol 版本 {}", ver_cmd >> 4);
+    }
+    // LOCAL 命令为负载均衡器自身的健康检查
+    if ver_cmd & 0x0F == 0 {
+        return Ok(None);
+    }
+
+    match head[13] {
+        // TCP over IPv4
+        0x11 => {
+            if body.len() < 12 {
+                bail!("PROXY protocol v2 地址长度错误");
+            }
+            let src = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            let dst = Ipv4Addr::new(body[4], body[5], body[6], body[7]);
+            let src_port = u16::from_be_bytes([body[8], body[9]]);
+            let dst_port = u16::from_be_bytes([body[10], body[11]]);
+            Ok(Some((
+                SocketAddr::new(IpAddr::V4(src), src_port),
+                SocketAddr::new(IpAddr::V4(dst), dst_port),
+            )))
+        }
+        // TCP over IPv6
+        0x21 => {
+            if body.len() < 36 {
+                bail!("PROXY protocol v2 地址长度错误");
+            }
+            let mut src = [0u8; 16];
+            let mut dst = [0u8; 16];
+            src.copy_from_slice(&body[0..16]);
+            dst.copy_from_slice(&body[16..32]);
+            let src_port = u16::from_be_bytes([body[32], body[33]]);
+            let dst_port = u16::from_be_bytes([body[34], body[35]]);
+            Ok(Some((
+                SocketAddr::new(IpAddr::V6(Ipv6Addr::from(src)), src_port),
+                SocketAddr::new(IpAddr::V6(Ipv6Addr::from(dst)), dst_port),
+            )))
+        }
+        // UNSPEC 及 UDP/UNIX 不携带可用的 TCP 地址
+        _ => Ok(None),
+    }
+}
+
+pub fn encode_v1(src: SocketAddr, dst: SocketAddr) -> Vec<u8> {
+    let (src, dst) = same_family(src, dst);
+    let proto = if src.is_ipv4() { "TCP4" } else { "TCP6" };
+    format!(
+        "PROXY {} {} {} {} {}\r\n",
+        proto,
+        src.ip(),
+        dst.ip(),
+        src.port(),
+        dst.port()
+    )
+    .into_bytes()
+}
+
+pub fn encode_v2(src: SocketAddr, dst: SocketAddr) -> Vec<u8> {
+    let (src, dst) = same_family(src, dst);
+    let mut buf = V2_SIGNATURE.to_vec();
+    // 版本2 PROXY 命令
+    buf.push(0x21);
+
+    match (src.ip(), dst.ip()) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => {
+            buf.push(0x11);
+            buf.extend_from_slice(&12u16.to_be_bytes());
+            buf.extend_from_slice(&s.octets());
+            buf.extend_from_slice(&d.octets());
+        }
+        (s, d) => {
+            buf.push(0x21);
+            buf.extend_from_slice(&36u16.to_be_bytes());
+            buf.extend_from_slice(&to_v6(s).octets());
+            buf.extend_from_slice(&to_v6(d).octets());
+        }
+    }
+    buf.extend_from_slice(&src.port().to_be_bytes());
+    buf.extend_from_slice(&dst.port().to_be_bytes());
+    buf
+}
+
+// 按配置生成发往矿池的头部 0=不发送 1=v1 2=v2
+pub fn upstream_header(version: u32, src: SocketAddr, dst: SocketAddr) -> Option<Vec<u8>> {
+    match version {
+        1 => Some(encode_v1(src, dst)),
+        2 => Some(encode_v2(src, dst)),
+        _ => None,
+    }
+}
+
+fn to_v6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+        IpAddr::V6(v6) => v6,
+    }
+}
+
+// 源地址和目标地址协议族不一致时统一转为 IPv6
+fn same_family(src: SocketAddr, dst: SocketAddr) -> (SocketAddr, SocketAddr) {
+    if src.is_ipv4() == dst.is_ipv4() {
+        return (src, dst);
+    }
+    (
+        SocketAddr::new(IpAddr::V6(to_v6(src.ip())), src.port()),
+        SocketAddr::new(IpAddr::V6(to_v6(dst.ip())), dst.port()),
+    )
+}
+
+#[test]
+fn test_parse_v1() {
+    let (src, dst) = parse_v1("PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\n")
+        .unwrap()
+        .unwrap();
+    assert_eq!(src, "192.168.0.1:56324".parse().unwrap());
+    assert_eq!(dst, "192.168.0.11:443".parse().unwrap());
+
+    assert_eq!(parse_v1("PROXY UNKNOWN\r\n").unwrap(), None);
+    assert!(parse_v1("PROXY TCP4 1.2.3.4\r\n").is_err());
+    assert!(parse_v1("GET / HTTP/1.1\r\n").is_err());
+}
+
+#[test]
+fn test_v2_round_trip() {
+    let src: SocketAddr = "10.0.0.1:50000".parse().unwrap();
+    let dst: SocketAddr = "10.0.0.2:14444".parse().unwrap();
+    let buf = encode_v2(src, dst);
+    let mut head = [0u8; 16];
+    head.copy_from_slice(&buf[..16]);
+    assert_eq!(parse_v2(&head, &buf[16..]).unwrap(), Some((src, dst)));
+
+    let src: SocketAddr = "[2001:db8::1]:50000".parse().unwrap();
+    let dst: SocketAddr = "[2001:db8::2]:14444".parse().unwrap();
+    let buf = encode_v2(src, dst);
+    head.copy_from_slice(&buf[..16]);
+    assert_eq!(parse_v2(&head, &buf[16..]).unwrap(), Some((src, dst)));
+}
+
+#[test]
+fn test_read_header() {
+    let src: SocketAddr = "10.0.0.1:50000".parse().unwrap();
+    let dst: SocketAddr = "10.0.0.2:14444".parse().unwrap();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let mut data = encode_v1(src, dst);
+        data.extend_from_slice(b"{\"id\":1}\n");
+        let mut r = &data[..];
+        assert_eq!(read_header(&mut r).await.unwrap(), Some((src, dst)));
+        assert_eq!(r, b"{\"id\":1}\n");
+
+        let mut data = encode_v2(src, dst);
+        data.extend_from_slice(b"{\"id\":1}\n");
+        let mut r = &data[..];
+        assert_eq!(read_header(&mut r).await.unwrap(), Some((src, dst)));
+        assert_eq!(r, b"{\"id\":1}\n");
+
+        let mut r = &b"{\"id\":1,\"method\":\"eth_submitLogin\"}\n"[..];
+        assert!(read_header(&mut r).await.is_err());
+    });
+}
+
+#[test]
+fn test_accept_trusted_proxy() {
+    use crate::util::config::Settings;
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    let src: SocketAddr = "10.0.0.1:50000".parse().unwrap();
+    let dst: SocketAddr = "10.0.0.2:14444".parse().unwrap();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap();
+        let connect = |header: Vec<u8>| async move {
+            let mut client = TcpStream::connect(server).await.unwrap();
+            client.write_all(&header).await.unwrap();
+            client
+        };
+
+        // 默认信任本机代理，使用头部中的地址
+        let config = Settings::default();
+        let ip_filter = IpFilter::new(&config).unwrap();
+        let _client = connect(encode_v1(src, dst)).await;
+        let (mut stream, addr) = listener.accept().await.unwrap();
+        let res = accept(&mut stream, addr, true, Listener::Tcp, &ip_filter).await;
+        assert_eq!(res, Some((src, dst)));
+
+        // 头部中的地址同样按黑名单过滤
+        let config = Settings {
+            tcp_deny: vec!["10.0.0.0/8".into()],
+            ..Settings::default()
+        };
+        let ip_filter = IpFilter::new(&config).unwrap();
+        let _client = connect(encode_v1(src, dst)).await;
+        let (mut stream, addr) = listener.accept().await.unwrap();
+        assert_eq!(
+            accept(&mut stream, addr, true, Listener::Tcp, &ip_filter).await,
+            None
+        );
+
+        // 不可信的来源不读取头部，直接断开
+        let config = Settings {
+            proxy_protocol_from: vec![],
+            ..Settings::default()
+        };
+        let ip_filter = IpFilter::new(&config).unwrap();
+        let _client = connect(encode_v1(src, dst)).await;
+        let (mut stream, addr) = listener.accept().await.unwrap();
+        assert_eq!(
+            accept(&mut stream, addr, true, Listener::Tcp, &ip_filter).await,
+            None
+        );
+
+        // 未开启时按 socket 地址过滤
+        let config = Settings {
+            tcp_deny: vec!["127.0.0.1".into()],
+            ..Settings::default()
+        };
+        let ip_filter = IpFilter::new(&config).unwrap();
+        let _client = connect(encode_v1(src, dst)).await;
+        let (mut stream, addr) = listener.accept().await.unwrap();
+        assert_eq!(
+            accept(&mut stream, addr, false, Listener::Tcp, &ip_filter).await,
+            None
+        );
+        // 已开启但 socket 地址不是代理时，在读取头部前就按 socket 地址过滤
+        let config = Settings {
+            tcp_deny: vec!["127.0.0.1".into()],
+            proxy_protocol_from: vec!["192.168.0.1".into()],
+            ..Settings::default()
+        };
+        let ip_filter = IpFilter::new(&config).unwrap();
+        let _client = connect(Vec::new()).await;
+        let (mut stream, addr) = listener.accept().await.unwrap();
+        assert_eq!(
+            accept(&mut stream, addr, true, Listener::Tcp, &ip_filter).await,
+            None
+        );
+    });
+}
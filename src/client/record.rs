@@ -0,0 +1,418 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use anyhow::{bail, Result};
+use hex::FromHex;
+use log::{info, warn};
+use openssl::symm::{decrypt, Cipher};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{client::acl::Cidr, util::config::Settings};
+
+// 数据流向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    #[serde(rename = "miner>proxy")]
+    MinerToProxy,
+    #[serde(rename = "proxy>miner")]
+    ProxyToMiner,
+    #[serde(rename = "proxy>pool")]
+    ProxyToPool,
+    #[serde(rename = "pool>proxy")]
+    PoolToProxy,
+}
+
+// 录制文件中的一行。t 为连接建立后的毫秒数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub t: u64,
+    pub dir: Direction,
+    pub line: String,
+}
+
+// 读取录制文件
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Event>> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+pub fn parse(capture: &str) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for (i, line) in capture.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(event) => events.push(event),
+            Err(e) => bail!("录制文件第 {} 行无法解析: {}", i + 1, e),
+        }
+    }
+    Ok(events)
+}
+
+// 单个连接的录制器。矿机和矿池两侧共用，按行写入录制文件
+pub struct Recorder {
+    start: Instant,
+    redact: bool,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    out: Box<dyn Write + Send>,
+    // 钱包地址 -> 替换后的地址。同一钱包替换结果相同，回放时仍能对应
+    wallets: HashMap<String, String>,
+}
+
+impl Recorder {
+    pub fn new(out: Box<dyn Write + Send>, redact: bool) -> Self {
+        Self {
+            start: Instant::now(),
+            redact,
+            inner: Mutex::new(Inner {
+                out,
+                wallets: HashMap::new(),
+            }),
+        }
+    }
+
+    // 按配置为新连接创建录制器。未开启录制或不在录制名单中时返回 None
+    pub fn open(config: &Settings, addr: &SocketAddr) -> Option<Arc<Self>> {
+        if config.record_path.is_empty() {
+            return None;
+        }
+        if !config.record_allow.is_empty() {
+            let allowed = config
+                .record_allow
+                .iter()
+                .filter_map(|cidr| cidr.parse::<Cidr>().ok())
+                .any(|cidr| cidr.contains(&addr.ip()));
+            if !allowed {
+                return None;
+            }
+        }
+
+        let path = capture_path(&config.record_path, addr);
+        match File::create(&path) {
+            Ok(file) => {
+                info!("📼 录制连接 {} 到 {}", addr, path.display());
+                Some(Arc::new(Self::new(
+                    Box::new(BufWriter::new(file)),
+                    config.record_redact,
+                )))
+            }
+            Err(e) => {
+                warn!("无法创建录制文件 {} {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    pub fn record(&self, dir: Direction, line: &str) {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let line = if self.redact {
+            redact_wallets(line, &mut inner.wallets)
+        } else {
+            line.to_string()
+        };
+        let event = Event {
+            t: self.start.elapsed().as_millis() as u64,
+            dir,
+            line,
+        };
+        if let Ok(event) = serde_json::to_string(&event) {
+            let _ = writeln!(inner.out, "{}", event);
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Ok(inner) = self.inner.get_mut() {
+            let _ = inner.out.flush();
+        }
+    }
+}
+
+fn capture_path(dir: &str, addr: &SocketAddr) -> PathBuf {
+    let name = format!(
+        "{}-{}.jsonl",
+        chrono::Local::now().format("%Y%m%d-%H%M%S%.3f"),
+        addr.to_string()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+    );
+    Path::new(dir).join(name)
+}
+
+// 把 0x 开头的 40 位十六进制钱包地址替换为 0x000..01 形式的占位地址。
+// 任务 header 等更长的十六进制串不受影响
+fn redact_wallets(line: &str, wallets: &mut HashMap<String, String>) -> String {
+    let bytes = line.as_bytes();
+    let mut out = String::with_capacity(line.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'0'
+            && matches!(bytes.get(i + 1), Some(b'x') | Some(b'X'))
+            && (i == 0 || !bytes[i - 1].is_ascii_alphanumeric())
+        {
+            let digits = bytes[i + 2..]
+                .iter()
+                .take_while(|c| c.is_ascii_hexdigit())
+                .count();
+            if digits == 40 {
+                let wallet = line[i..i + 42].to_lowercase();
+                let next = wallets.len() + 1;
+                let redacted = wallets
+                    .entry(wallet)
+                    .or_insert_with(|| format!("0x{:040x}", next));
+                out.push_str(redacted);
+                i += 42;
+                continue;
+            }
+            out.push_str(&line[i..i + 2 + digits]);
+            i += 2 + digits;
+            continue;
+        }
+        let c = line[i..].chars().next().unwrap();
+        out.push(c);
+        i += c.len_utf8();
+    }
+    out
+}
+
+// 连接的哪一侧
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Miner,
+    Pool,
+}
+
+// 录制经过的数据。未开启录制时直接透传
+pub struct Tap<S> {
+    inner: S,
+    recorder: Option<Arc<Recorder>>,
+    read: Direction,
+    write: Direction,
+    rbuf: Vec<u8>,
+    wbuf: Vec<u8>,
+    // 加密连接的 (key, iv)。录制解密后的消息
+    cipher: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl<S> Tap<S> {
+    pub fn new(inner: S, recorder: Option<Arc<Recorder>>, side: Side) -> Self {
+        let (read, write) = match side {
+            Side::Miner => (Direction::MinerToProxy, Direction::ProxyToMiner),
+            Side::Pool => (Direction::PoolToProxy, Direction::ProxyToPool),
+        };
+        Self {
+            inner,
+            recorder,
+            read,
+            write,
+            rbuf: Vec::new(),
+            wbuf: Vec::new(),
+            cipher: None,
+        }
+    }
+
+    // 加密监听端口的矿机连接。每行是 base64 编码的 AES 密文
+    pub fn decrypted(mut self, key: &str, iv: &str) -> Self {
+        self.cipher = Vec::from_hex(key).ok().zip(Vec::from_hex(iv).ok());
+        self
+    }
+}
+
+// 解密一行。无法解密时原样返回
+fn decrypt_line(line: &str, (key, iv): &(Vec<u8>, Vec<u8>)) -> String {
+    base64::decode(line.trim_end_matches(['\r', '\n']))
+        .ok()
+        .and_then(|data| decrypt(Cipher::aes_256_cbc(), key, Some(iv), &data).ok())
+        .map(|data| String::from_utf8_lossy(&data).into_owned())
+        .unwrap_or_else(|| line.to_string())
+}
+
+// 按行切分后写入录制器，未满一行的数据留在缓冲区
+fn record_lines(
+    recorder: &Recorder,
+    dir: Direction,
+    buf: &mut Vec<u8>,
+    cipher: Option<&(Vec<u8>, Vec<u8>)>,
+    data: &[u8],
+) {
+    buf.extend_from_slice(data);
+    while let Some(pos) = buf.iter().position(|c| *c == b'\n') {
+        let line: Vec<u8> = buf.drain(..=pos).collect();
+        let line = String::from_utf8_lossy(&line);
+        match cipher {
+            Some(cipher) => recorder.record(dir, &decrypt_line(&line, cipher)),
+            None => recorder.record(dir, &line),
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Tap<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let res = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(recorder)) = (&res, &this.recorder) {
+            record_lines(
+                recorder,
+                this.read,
+                &mut this.rbuf,
+                this.cipher.as_ref(),
+                &buf.filled()[filled..],
+            );
+        }
+        res
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Tap<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let (Poll::Ready(Ok(n)), Some(recorder)) = (&res, &this.recorder) {
+            record_lines(
+                recorder,
+                this.write,
+                &mut this.wbuf,
+                this.cipher.as_ref(),
+                &buf[..*n],
+            );
+        }
+        res
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[test]
+fn test_redact_wallets() {
+    let mut wallets = HashMap::new();
+    let line = r#"{"params":["0x98be5c44d574b96b320dffb0ccff116bda433b8e.rig1","0x98BE5C44D574B96B320DFFB0CCFF116BDA433B8E","0x8b8ad7e5a8e0b4e4bdcb1e8c8c3b8f2b1d6d1cf4d1e0e5f6a7b8c9d0e1f2a3b4"]}"#;
+    let redacted = redact_wallets(line, &mut wallets);
+    assert_eq!(
+        redacted,
+        r#"{"params":["0x0000000000000000000000000000000000000001.rig1","0x0000000000000000000000000000000000000001","0x8b8ad7e5a8e0b4e4bdcb1e8c8c3b8f2b1d6d1cf4d1e0e5f6a7b8c9d0e1f2a3b4"]}"#
+    );
+
+    let redacted = redact_wallets(
+        r#"["0x1111111111111111111111111111111111111111","中文"]"#,
+        &mut wallets,
+    );
+    assert_eq!(
+        redacted,
+        r#"["0x0000000000000000000000000000000000000002","中文"]"#
+    );
+}
+
+#[test]
+fn test_tap_records_lines() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let out = Shared::default();
+        let recorder = Arc::new(Recorder::new(Box::new(out.clone()), false));
+        let (a, mut b) = tokio::io::duplex(64);
+        let mut tap = Tap::new(a, Some(recorder.clone()), Side::Miner);
+
+        b.write_all(b"{\"id\":1}\n{\"id\"").await.unwrap();
+        b.write_all(b":2}\n").await.unwrap();
+        let mut buf = [0u8; 64];
+        let mut read = 0;
+        while read < 18 {
+            read += tap.read(&mut buf[read..]).await.unwrap();
+        }
+        tap.write_all(b"{\"result\":true}\n").await.unwrap();
+        drop(tap);
+        drop(recorder);
+
+        let events = parse(&String::from_utf8(out.0.lock().unwrap().clone()).unwrap()).unwrap();
+        let lines: Vec<_> = events.iter().map(|e| (e.dir, e.line.as_str())).collect();
+        assert_eq!(
+            lines,
+            vec![
+                (Direction::MinerToProxy, "{\"id\":1}"),
+                (Direction::MinerToProxy, "{\"id\":2}"),
+                (Direction::ProxyToMiner, "{\"result\":true}"),
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_tap_decrypts_lines() {
+    use tokio::io::AsyncWriteExt;
+
+    let (key, iv) = ("0".repeat(64), "0".repeat(32));
+    let encrypted = |line: &str| {
+        let data = openssl::symm::encrypt(
+            Cipher::aes_256_cbc(),
+            &Vec::from_hex(&key).unwrap(),
+            Some(&Vec::from_hex(&iv).unwrap()),
+            line.as_bytes(),
+        )
+        .unwrap();
+        format!("{}\n", base64::encode(data))
+    };
+
+    let path = std::env::temp_dir().join(format!("proxy-tap-{}.jsonl", std::process::id()));
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let file = File::create(&path).unwrap();
+        let recorder = Arc::new(Recorder::new(Box::new(file), false));
+        let (a, _b) = tokio::io::duplex(1024);
+        let mut tap = Tap::new(a, Some(recorder), Side::Miner).decrypted(&key, &iv);
+        tap.write_all(encrypted("{\"result\":true}").as_bytes())
+            .await
+            .unwrap();
+        tap.write_all(b"not encrypted\n").await.unwrap();
+    });
+
+    let events = load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let lines: Vec<_> = events.iter().map(|e| e.line.as_str()).collect();
+    assert_eq!(lines, vec!["{\"result\":true}", "not encrypted"]);
+}
@@ -0,0 +1,1048 @@
+use std::{collections::VecDeque, fmt::Debug, net::SocketAddr, sync::Arc, time::Instant};
+
+use anyhow::{anyhow, bail, Result};
+use hex::FromHex;
+use log::{debug, info};
+use lru::LruCache;
+use openssl::symm::{decrypt, Cipher};
+use serde::Serialize;
+use tokio::{
+    io::{
+        AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines, ReadHalf,
+        WriteHalf,
+    },
+    net::TcpStream,
+    select,
+    sync::{mpsc, watch},
+    time,
+};
+
+use super::{
+    acl::IpFilter,
+    auth::{self, Auth},
+    control::{self, Handle, Ledger, Order},
+    dedup::{self, ShareKey},
+    error::{report, SessionError},
+    eth_get_work, eth_submitHashrate, eth_submit_login, mining_authorize,
+    naming::{self, Naming},
+    parse_client, parse_client_workername,
+    policy::{self, Fee, FeePolicy},
+    send_reconnect, shutdown, subscribe,
+    tracker::{self, Freshness, JobTracker},
+    write_encrypt_socket, write_to_socket, write_to_socket_string, Conn, Context,
+};
+use crate::{
+    protocol::{
+        ethash::{self, Share, ShareError},
+        rpc::eth::{
+            handle_error_for_worker, parse_server, ClientRpc, ClientWithWorkerName, EthError,
+            ServerError, ServerId, ServerJob, ServerLine, ServerRootErrorValue, ServerRpc,
+        },
+        CLIENT_GETWORK, CLIENT_LOGIN, CLIENT_SUBHASHRATE, SUBSCRIBE,
+    },
+    state::Worker,
+    util::config::Settings,
+    SPLIT,
+};
+
+type FeeLines = Lines<BufReader<ReadHalf<TcpStream>>>;
+
+// 登录前允许的方法。EthereumStratum 矿机先订阅再登录
+const PRE_LOGIN_METHODS: [&str; 4] = [
+    "eth_submitLogin",
+    "mining.authorize",
+    "mining.subscribe",
+    "mining.extranonce.subscribe",
+];
+
+// 提交份额的方法。开启鉴权时未登录就提交份额计入封禁
+const SUBMIT_METHODS: [&str; 2] = ["eth_submitWork", "mining.submit"];
+
+// 一个抽水通道。收到的任务先放入 unsend，分给矿机后记入 sent
+struct Channel {
+    fee: Fee,
+    w: WriteHalf<TcpStream>,
+    logged: bool,
+    unsend: VecDeque<(String, Vec<String>)>,
+    sent: LruCache<String, (u64, u64)>,
+    // 抽水矿池最近下发的任务
+    jobs: JobTracker,
+}
+
+// 矿机会话的状态
+struct Session<'a, W, W1> {
+    config: &'a Settings,
+    develop_pools: &'a [String],
+    policy: Box<dyn FeePolicy>,
+    is_encrypted: bool,
+    addr: SocketAddr,
+    ip_filter: &'a IpFilter,
+    auth: &'a Auth,
+
+    worker_w: WriteHalf<W>,
+    pool_w: WriteHalf<W1>,
+    worker: Worker,
+    worker_name: String,
+    // 矿机最近一次请求的 id。矿池返回时改回这个 id
+    rpc_id: u64,
+    // 矿池给矿机的任务总数
+    pool_job_idx: u64,
+    job_diff: u64,
+    // 首次读取超时时间。登录成功后放宽
+    client_timeout_sec: u64,
+    // EthereumStratum 协议支持通知矿机重新连接
+    stratum: bool,
+    // 已通过代理鉴权。之前只接受登录和订阅
+    authed: bool,
+    // 停机或管理员排空中。已提交的份额全部返回后断开
+    draining: bool,
+    ledger: Arc<Ledger>,
+    naming: Naming,
+    // 按规则改写后发往矿池的矿机名。None 表示不改写
+    pool_name: Option<String>,
+
+    channels: Vec<Channel>,
+    // 矿池最近下发的任务
+    pool_jobs: JobTracker,
+    send_normal_jobs: LruCache<String, i32>,
+    // 发给矿机的任务头 -> (种子, 难度目标)。本地验证份额时使用
+    jobs: LruCache<String, (String, String)>,
+    // 本会话最近提交的份额。重复提交的份额不发给矿池
+    shares: LruCache<ShareKey, ()>,
+    // 发给矿机的任务头 -> 发送时间。收到该任务的第一个份额后移除
+    job_sent: LruCache<String, Instant>,
+    // 已提交到矿池的份额 id -> 提交时间
+    submits: LruCache<u64, Instant>,
+}
+
+// 矿机会话。转发矿机与矿池之间的消息，并按抽水策略把部分任务换成抽水任务
+pub async fn run<R, W, R1, W1>(
+    ctx: &Context,
+    worker_r: BufReader<ReadHalf<R>>,
+    worker_w: WriteHalf<W>,
+    pool_r: BufReader<ReadHalf<R1>>,
+    pool_w: WriteHalf<W1>,
+    conn: Conn,
+    naming: Naming,
+) -> Result<()>
+where
+    R: AsyncRead,
+    W: AsyncWrite,
+    R1: AsyncRead,
+    W1: AsyncWrite,
+{
+    if ctx.shutdown.is_stopping() {
+        return Ok(());
+    }
+    let _session = ctx.shutdown.session();
+    let mut stopping = ctx.shutdown.stopping();
+
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "agent")] {
+            let policy: Box<dyn FeePolicy> = Box::new(policy::AgentPolicy::new(&ctx.config)?);
+        } else {
+            let policy: Box<dyn FeePolicy> = Box::new(policy::DefaultPolicy::new(&ctx.config)?);
+        }
+    }
+
+    let Conn {
+        addr,
+        is_encrypted,
+        mut control,
+        ..
+    } = conn;
+
+    let mut session = Session {
+        config: &ctx.config,
+        develop_pools: &ctx.develop_pools,
+        policy,
+        is_encrypted,
+        addr,
+        ip_filter: &ctx.ip_filter,
+        auth: &ctx.auth,
+        worker_w,
+        pool_w,
+        worker: Worker {
+            session: control.id(),
+            ip: addr.ip().to_string(),
+            ..Worker::default()
+        },
+        worker_name: String::new(),
+        rpc_id: 0,
+        pool_job_idx: 0,
+        job_diff: 0,
+        client_timeout_sec: 1,
+        stratum: false,
+        authed: false,
+        draining: false,
+        ledger: control.ledger(),
+        naming,
+        pool_name: None,
+        channels: Vec::new(),
+        pool_jobs: JobTracker::new(),
+        send_normal_jobs: LruCache::new(100),
+        jobs: LruCache::new(100),
+        shares: LruCache::new(dedup::SESSION_WINDOW),
+        job_sent: LruCache::new(100),
+        submits: LruCache::new(100),
+    };
+
+    let workers_queue = &ctx.worker_queue;
+    let res = session
+        .serve(workers_queue, worker_r, pool_r, &mut stopping, &mut control)
+        .await;
+    match &res {
+        Err(e) => report(workers_queue, session.worker.clone(), &addr, e).await,
+        Ok(()) => {
+            let e = SessionError::Drained.msg(format!("排空完成 : {}", session.worker_name));
+            report(workers_queue, session.worker.clone(), &addr, &e).await;
+        }
+    }
+    res
+}
+
+// 连接抽水矿池
+async fn connect(fee: Fee, config: &Settings, develop_pools: &[String]) -> Result<TcpStream> {
+    let (pools, upstream_proxy) = match fee {
+        Fee::Develop => (develop_pools.to_vec(), &config.develop_upstream_proxy),
+        _ => (
+            config.share_tcp_address.clone(),
+            &config.share_upstream_proxy,
+        ),
+    };
+
+    match super::get_pool_stream_async(&pools, upstream_proxy, &config.source_address).await {
+        Some((stream, _)) => Ok(TcpStream::from_std(stream)?),
+        None => {
+            log::error!("所有TCP矿池均不可链接。请修改后重试");
+            bail!("{}矿池均不可链接", fee);
+        }
+    }
+}
+
+// 等待任一抽水通道的下一行
+async fn next_fee_line(lines: &mut [FeeLines]) -> (usize, std::io::Result<Option<String>>) {
+    if lines.is_empty() {
+        return std::future::pending().await;
+    }
+    let (res, i, _) =
+        futures::future::select_all(lines.iter_mut().map(|l| Box::pin(l.next_line()))).await;
+    (i, res)
+}
+
+impl<'a, W, W1> Session<'a, W, W1>
+where
+    W: AsyncWrite,
+    W1: AsyncWrite,
+{
+    // 会话主循环。返回的错误带有 SessionError 断开原因
+    async fn serve<R, R1>(
+        &mut self,
+        workers_queue: &mpsc::Sender<Worker>,
+        worker_r: BufReader<ReadHalf<R>>,
+        pool_r: BufReader<ReadHalf<R1>>,
+        stopping: &mut watch::Receiver<bool>,
+        control: &mut Handle,
+    ) -> Result<()>
+    where
+        R: AsyncRead,
+        R1: AsyncRead,
+    {
+        let mut fee_lines: Vec<FeeLines> = Vec::new();
+        for fee in self.policy.channels() {
+            let stream = connect(fee, self.config, self.develop_pools)
+                .await
+                .map_err(|e| SessionError::FeePoolConnect.wrap(e))?;
+            let (r, w) = tokio::io::split(stream);
+            fee_lines.push(BufReader::new(r).lines());
+            self.channels.push(Channel {
+                fee,
+                w,
+                logged: false,
+                unsend: VecDeque::new(),
+                sent: LruCache::new(50),
+                jobs: JobTracker::new(),
+            });
+        }
+        for i in 0..self.channels.len() {
+            self.fee_login(i)
+                .await
+                .map_err(|e| SessionError::FeePoolConnect.wrap(e))?;
+        }
+
+        let mut pool_lines = pool_r.lines();
+        let mut worker_lines = if self.is_encrypted {
+            worker_r.split(SPLIT)
+        } else {
+            worker_r.split(b'\n')
+        };
+
+        let sleep = time::sleep(time::Duration::from_secs(60));
+        tokio::pin!(sleep);
+        loop {
+            select! {
+                res = time::timeout(time::Duration::from_secs(self.client_timeout_sec), worker_lines.next_segment()) => {
+                    let buf_bytes = match res {
+                        Ok(Ok(Some(buf))) => buf,
+                        Ok(_) => {
+                            self.close_pool().await;
+                            return Err(SessionError::MinerClosed.msg(format!("矿机下线了 : {}", self.worker_name)));
+                        }
+                        Err(e) => {
+                            self.close_pool().await;
+                            return Err(SessionError::MinerTimeout.msg(format!("读取超时了 矿机下线了: {}", e)));
+                        }
+                    };
+
+                    #[cfg(debug_assertions)]
+                    debug!("0:  矿机 -> 矿池 {} #{:?}", self.worker_name, buf_bytes);
+                    for buffer in buf_bytes.split(|c| *c == b'\n') {
+                        if buffer.is_empty() {
+                            continue;
+                        }
+
+                        let buf = match self.decode(buffer) {
+                            Ok(buf) => buf,
+                            Err(e) => {
+                                self.ip_filter.strike(&self.addr.ip(), "发送了无法解码的数据");
+                                self.close_pool().await;
+                                return Err(SessionError::ProtocolViolation.wrap(e));
+                            }
+                        };
+                        self.on_miner_line(&buf).await?;
+                    }
+                    control.set_worker(&self.worker.worker);
+                },
+                res = pool_lines.next_line() => {
+                    let received = Instant::now();
+                    let buffer = match res {
+                        Ok(Some(buf)) => buf,
+                        Ok(None) => {
+                            if let Err(e) = self.worker_w.shutdown().await {
+                                log::error!("Error Worker Shutdown Socket {:?}", e);
+                            }
+                            return Err(SessionError::PoolClosed.msg(format!("矿池断开了 : {}", self.worker_name)));
+                        }
+                        Err(e) => {
+                            return Err(SessionError::PoolClosed.wrap(e.into()));
+                        }
+                    };
+
+                    for buf in buffer.split('\n') {
+                        if buf.is_empty() {
+                            continue;
+                        }
+                        self.on_pool_line(buf, received).await?;
+                    }
+
+                    if self.draining && self.worker.pending_shares() == 0 {
+                        info!("🛑 矿机 {} 份额已全部返回。断开连接", self.worker_name);
+                        let _ = workers_queue.try_send(self.worker.clone());
+                        let _ = self.worker_w.shutdown().await;
+                        let _ = self.pool_w.shutdown().await;
+                        return Ok(());
+                    }
+                },
+                (i, res) = next_fee_line(&mut fee_lines) => {
+                    let fee = self.channels[i].fee;
+                    let buffer = match res {
+                        Ok(Some(buf)) => buf,
+                        Ok(None) => {
+                            self.close_pool().await;
+                            return Err(SessionError::FeePoolClosed.msg(format!("{}矿池断开了 : {}", fee, self.worker_name)));
+                        }
+                        Err(e) => return Err(SessionError::FeePoolClosed.wrap(e.into())),
+                    };
+
+                    for buf in buffer.split('\n') {
+                        if buf.is_empty() {
+                            continue;
+                        }
+                        self.on_fee_line(i, buf);
+                    }
+                },
+                _ = shutdown::wait(stopping), if !self.draining => {
+                    if self.drain(workers_queue, "停机").await {
+                        return Ok(());
+                    }
+                },
+                order = control.order() => match order {
+                    Order::Kick => {
+                        self.close_pool().await;
+                        let _ = self.worker_w.shutdown().await;
+                        return Err(SessionError::Kicked.msg(format!("管理员断开矿机 : {}", self.worker_name)));
+                    }
+                    Order::Drain if !self.draining => {
+                        // 份额都已返回时直接结束会话
+                        let finished = self.drain(workers_queue, "排空").await;
+                        if finished {
+                            return Ok(());
+                        }
+                    }
+                    _ => {}
+                },
+                () = &mut sleep => {
+                    // 发送本地旷工状态到远端。
+                    if workers_queue.try_send(self.worker.clone()).is_err() {
+                        log::warn!("发送旷工状态失败");
+                    }
+
+                    sleep.as_mut().reset(time::Instant::now() + time::Duration::from_secs(60));
+                },
+            }
+        }
+    }
+
+    // 开始排空。通知矿机重连，没有未返回的份额时直接断开并返回 true
+    async fn drain(&mut self, workers_queue: &mpsc::Sender<Worker>, reason: &str) -> bool {
+        self.draining = true;
+        // 断开前上报一次矿机状态
+        let _ = workers_queue.try_send(self.worker.clone());
+        let _ = self.pool_w.flush().await;
+
+        if self.stratum {
+            if let Err(e) = send_reconnect(
+                &mut self.worker_w,
+                &self.worker_name,
+                self.config,
+                self.is_encrypted,
+            )
+            .await
+            {
+                log::warn!("通知矿机 {} 重连失败 {}", self.worker_name, e);
+            }
+        }
+
+        if self.worker.pending_shares() == 0 {
+            info!("🛑 {} 断开矿机 {}", reason, self.worker_name);
+            let _ = self.worker_w.shutdown().await;
+            let _ = self.pool_w.shutdown().await;
+            return true;
+        }
+        info!(
+            "🛑 {} 等待矿机 {} 的 {} 个份额返回",
+            reason,
+            self.worker_name,
+            self.worker.pending_shares()
+        );
+        false
+    }
+
+    // 按策略登录抽水通道。已登录或策略暂不登录时跳过
+    async fn fee_login(&mut self, i: usize) -> Result<()> {
+        let channel = &mut self.channels[i];
+        if channel.logged {
+            return Ok(());
+        }
+        let (wallet, name) = match self.policy.login(channel.fee) {
+            Some(login) => login,
+            None => return Ok(()),
+        };
+
+        let login = ClientWithWorkerName {
+            id: CLIENT_LOGIN,
+            method: "eth_submitLogin".into(),
+            params: vec![wallet, "x".into()],
+            worker: name.clone(),
+        };
+        if let Err(e) = write_to_socket(&mut channel.w, &login, &name).await {
+            log::error!("Error writing Socket {:?}", login);
+            return Err(e);
+        }
+        channel.logged = true;
+        Ok(())
+    }
+
+    async fn close_pool(&mut self) {
+        if let Err(e) = self.pool_w.shutdown().await {
+            log::error!("Error Shutdown Socket {:?}", e);
+        }
+    }
+
+    async fn write_worker<T: Serialize>(&mut self, rpc: &T) -> Result<()> {
+        if self.is_encrypted {
+            write_encrypt_socket(
+                &mut self.worker_w,
+                rpc,
+                &self.worker_name,
+                self.config.key.clone(),
+                self.config.iv.clone(),
+            )
+            .await
+        } else {
+            write_to_socket(&mut self.worker_w, rpc, &self.worker_name).await
+        }
+    }
+
+    // 矿机发来的一行。加密连接先解密
+    fn decode(&self, buffer: &[u8]) -> Result<String> {
+        if !self.is_encrypted {
+            return String::from_utf8(buffer.to_vec())
+                .map_err(|_| anyhow!("无法解析的字符串{:?}", buffer));
+        }
+
+        let key = Vec::from_hex(&self.config.key)?;
+        let iv = Vec::from_hex(&self.config.iv)?;
+        let buffer = base64::decode(buffer)?;
+        let buffer = decrypt(Cipher::aes_256_cbc(), &key, Some(&iv), &buffer)
+            .map_err(|e| anyhow!("解密失败 {}", e))?;
+        String::from_utf8(buffer).map_err(|_| anyhow!("无法解析的字符串"))
+    }
+
+    async fn on_miner_line(&mut self, buf: &str) -> Result<()> {
+        #[cfg(debug_assertions)]
+        debug!("0:  矿机 -> 矿池 {} 发送 {}", self.worker_name, buf);
+        let res = if let Some(mut rpc) = parse_client_workername(buf) {
+            let method = rpc.method.clone();
+            self.on_client(&method, &mut rpc, buf).await
+        } else if let Some(mut rpc) = parse_client(buf) {
+            let method = rpc.method.clone();
+            self.on_client(&method, &mut rpc, buf).await
+        } else {
+            log::warn!("未知 {} {}", self.addr, buf);
+            self.ip_filter
+                .strike(&self.addr.ip(), "发送了无法解析的数据");
+            return Ok(());
+        };
+
+        // 鉴权或协议错误已经带有原因，其余是写入矿池失败
+        res.map_err(|e| {
+            log::warn!("写入任务错误: {:?}", e);
+            SessionError::PoolWrite.wrap(e)
+        })
+    }
+
+    async fn on_client<T>(&mut self, method: &str, rpc: &mut T, buf: &str) -> Result<()>
+    where
+        T: ClientRpc + Serialize + Debug,
+    {
+        self.rpc_id = rpc.get_id();
+        if !self.authed && !PRE_LOGIN_METHODS.contains(&method) && self.auth.enabled() {
+            if SUBMIT_METHODS.contains(&method) {
+                self.ip_filter.strike(&self.addr.ip(), "未登录就提交份额");
+            }
+            let _ = self
+                .reject_share(rpc.get_id(), 24, "Unauthorized worker")
+                .await;
+            let _ = self.worker_w.shutdown().await;
+            self.close_pool().await;
+            return Err(SessionError::AuthRejected.msg(format!("矿机未登录就发送了 {}", method)));
+        }
+        if let ("eth_submitHashrate" | "eth_getWork", Some(name)) = (method, &self.pool_name) {
+            rpc.set_worker_name(name);
+        }
+        match method {
+            "eth_submitLogin" => self.login(rpc).await,
+            "eth_submitWork" => self.submit(rpc).await,
+            "eth_submitHashrate" => {
+                eth_submitHashrate(&mut self.worker, &mut self.pool_w, rpc, &self.worker_name).await
+            }
+            "eth_getWork" => eth_get_work(&mut self.pool_w, rpc, &self.worker_name).await,
+            "mining.authorize" => {
+                if let Some(user) = rpc.get_wallet() {
+                    let (wallet, name) = auth::split_user(&user);
+                    let password = rpc.get_password().unwrap_or_default();
+                    self.check_auth(wallet, name, &password).await?;
+                    self.pool_name = self.naming.rename(name, &self.addr.ip());
+                }
+                mining_authorize(
+                    &mut self.pool_w,
+                    rpc,
+                    buf,
+                    &self.worker_name,
+                    self.pool_name.as_deref(),
+                )
+                .await?;
+                self.authed = true;
+                Ok(())
+            }
+            "mining.submit" => match &self.pool_name {
+                Some(name) => {
+                    rpc.set_worker_name(name);
+                    let rpc = naming::rename_request(rpc, name)?;
+                    write_to_socket(&mut self.pool_w, &rpc, &self.worker_name).await
+                }
+                None => write_to_socket_string(&mut self.pool_w, buf, &self.worker_name).await,
+            },
+            "mining.subscribe" => {
+                self.stratum = true;
+                subscribe(&mut self.pool_w, rpc, &self.worker_name).await
+            }
+            _ => {
+                log::warn!("Not found method {:?}", rpc);
+                write_to_socket_string(&mut self.pool_w, buf, &self.worker_name).await
+            }
+        }
+    }
+
+    async fn login<T>(&mut self, rpc: &mut T) -> Result<()>
+    where
+        T: ClientRpc + Serialize,
+    {
+        for fee in self.policy.on_login(rpc)? {
+            if let Some(i) = self.channels.iter().position(|c| c.fee == fee) {
+                self.fee_login(i).await?;
+            }
+        }
+
+        // 钱包地址带有 .矿机名 时矿池按钱包地址识别矿机，不再补充矿机名
+        let name = rpc.get_worker_name();
+        let named_wallet = rpc.get_wallet().is_some_and(|w| w.contains('.'));
+        self.pool_name = match name.as_str() {
+            naming::DEFAULT_NAME if named_wallet => None,
+            _ => self.naming.rename(&name, &self.addr.ip()),
+        };
+
+        let res = match self.login_auth(rpc).await {
+            Ok(()) => {
+                eth_submit_login(
+                    &mut self.worker,
+                    &mut self.pool_w,
+                    rpc,
+                    &mut self.worker_name,
+                    self.pool_name.as_deref(),
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        match res {
+            Ok(_) => {
+                self.authed = true;
+                Ok(())
+            }
+            Err(e) => {
+                info!("错误 {} ", e);
+                // 写入矿池失败等不是矿机的问题，不计入封禁
+                match SessionError::of(&e) {
+                    SessionError::AuthRejected => {
+                        self.ip_filter.strike(&self.addr.ip(), "登录鉴权失败")
+                    }
+                    SessionError::ProtocolViolation => {
+                        self.ip_filter.strike(&self.addr.ip(), "登录请求无效")
+                    }
+                    _ => {}
+                }
+                Err(e)
+            }
+        }
+    }
+
+    // ETHPROXY 矿机可以用 钱包.矿机名 登录，按拆分后的钱包和矿机名鉴权
+    async fn login_auth<T: ClientRpc>(&mut self, rpc: &mut T) -> Result<()> {
+        let wallet = match rpc.get_wallet() {
+            Some(wallet) => wallet,
+            None => return Ok(()),
+        };
+        let name = rpc.get_worker_name();
+        let (wallet, worker) = match auth::split_user(&wallet) {
+            (w, worker) if name == naming::DEFAULT_NAME && !worker.is_empty() => (w, worker),
+            (w, _) => (w, name.as_str()),
+        };
+        let password = rpc.get_password().unwrap_or_default();
+        self.check_auth(wallet, worker, &password).await
+    }
+
+    // 鉴权失败时给矿机返回 JSON-RPC 错误，随后断开
+    async fn check_auth(&mut self, wallet: &str, worker: &str, password: &str) -> Result<()> {
+        let reason = match self.auth.check(wallet, worker, password) {
+            Ok(()) => return Ok(()),
+            Err(reason) => reason,
+        };
+        log::warn!("🚫 钱包 {} 登录被拒绝: {}", wallet, reason);
+        let _ = self
+            .reject_share(self.rpc_id, 24, "Unauthorized worker")
+            .await;
+        let _ = self.worker_w.shutdown().await;
+        Err(SessionError::AuthRejected.msg(format!("矿机登录鉴权失败: {} {}", wallet, reason)))
+    }
+
+    // 提交份额。抽水任务的份额交给对应的抽水矿池，并直接返回成功给矿机
+    async fn submit<T>(&mut self, rpc: &mut T) -> Result<()>
+    where
+        T: ClientRpc + Serialize,
+    {
+        if let Some(sent) = rpc
+            .get_job_id()
+            .and_then(|job_id| self.job_sent.pop(&job_id))
+        {
+            self.worker.first_share.record(sent.elapsed());
+        }
+        if let Some(e) = self.check_share(rpc).await {
+            self.worker.share_local_reject(e);
+            return self.reject_share(rpc.get_id(), e.code(), e.message()).await;
+        }
+        if self.is_duplicate(rpc) {
+            self.worker.share_duplicate();
+            return self
+                .reject_share(
+                    rpc.get_id(),
+                    dedup::DUPLICATE_CODE,
+                    dedup::DUPLICATE_MESSAGE,
+                )
+                .await;
+        }
+        if self.check_stale(rpc) {
+            return self
+                .reject_share(rpc.get_id(), tracker::STALE_CODE, tracker::STALE_MESSAGE)
+                .await;
+        }
+
+        if let Some(job_id) = rpc.get_job_id() {
+            if let Some(i) = self.channels.iter().position(|c| c.sent.contains(&job_id)) {
+                let fee = self.channels[i].fee;
+                let name = self.policy.submit_name(fee);
+                self.ledger.share(fee.as_str());
+                let channel = &mut self.channels[i];
+                channel.sent.get(&job_id);
+                rpc.set_worker_name(&name);
+                #[cfg(debug_assertions)]
+                info!("提交{}任务!", fee);
+                if let Err(e) = write_to_socket(&mut channel.w, rpc, &name).await {
+                    log::warn!("提交{}任务失败 {}", fee, e);
+                }
+
+                let s = ServerId {
+                    id: rpc.get_id(),
+                    jsonrpc: "2.0".into(),
+                    result: true,
+                };
+                if self.write_worker(&s).await.is_err() {
+                    #[cfg(debug_assertions)]
+                    debug!("给旷工返回成功写入失败了。");
+                }
+                return Ok(());
+            }
+        }
+
+        self.worker.share_index_add();
+        self.ledger.share(control::POOL_ACCOUNT);
+        rpc.set_id(self.worker.share_index);
+        self.submits.put(self.worker.share_index, Instant::now());
+        if let Some(name) = self.pool_name.clone().or_else(|| self.policy.worker_name()) {
+            rpc.set_worker_name(&name);
+        }
+        write_to_socket(&mut self.pool_w, rpc, &self.worker_name).await
+    }
+
+    // 直接拒绝份额，不提交到矿池
+    async fn reject_share(&mut self, id: u64, code: u64, message: &str) -> Result<()> {
+        let s = ServerError {
+            id,
+            result: false,
+            error: EthError {
+                code,
+                message: message.into(),
+            },
+        };
+        self.write_worker(&s)
+            .await
+            .map_err(|e| SessionError::MinerWrite.wrap(e))
+    }
+
+    // 记录提交的份额。本会话或同一钱包的其他会话最近提交过时返回 true
+    fn is_duplicate<T: ClientRpc>(&mut self, rpc: &T) -> bool {
+        let (nonce, header, mix) = match rpc.get_submit_work() {
+            Some(work) => work,
+            None => return false,
+        };
+        let key = ShareKey::new(&nonce, &header, &mix);
+        if self.shares.put(key.clone(), ()).is_some() {
+            return true;
+        }
+        !self.worker.worker_wallet.is_empty()
+            && !dedup::wallets().insert(&self.worker.worker_wallet, key)
+    }
+
+    // 按任务所属的上游判断份额是否过期并计入矿机统计。
+    // 落后 stale_drop_blocks 个区块及以上时返回 true，份额不再提交
+    fn check_stale<T: ClientRpc>(&mut self, rpc: &mut T) -> bool {
+        let job_id = match rpc.get_job_id() {
+            Some(job_id) => job_id,
+            None => return false,
+        };
+        let jobs = match self.channels.iter().find(|c| c.sent.contains(&job_id)) {
+            Some(channel) => &channel.jobs,
+            None => &self.pool_jobs,
+        };
+        match jobs.classify(&job_id) {
+            Freshness::Fresh => false,
+            Freshness::Unknown => {
+                debug!("矿机 {} 提交了未知任务的份额 {}", self.worker_name, job_id);
+                false
+            }
+            Freshness::Stale(blocks) => {
+                let drop =
+                    self.config.stale_drop_blocks != 0 && blocks >= self.config.stale_drop_blocks;
+                self.worker.share_stale(blocks, drop);
+                drop
+            }
+        }
+    }
+
+    // 本地验证份额。返回 Some 表示份额无效。未开启、任务未知或验证缓存未生成时不验证
+    async fn check_share<T: ClientRpc>(&mut self, rpc: &T) -> Option<ShareError> {
+        if !self.config.verify_shares {
+            return None;
+        }
+        let (nonce, header, mix) = rpc.get_submit_work()?;
+        let (seed, target) = self.jobs.get(&header)?;
+        let target = ethash::parse_h256(target)?;
+        let light = ethash::epochs().get(&ethash::parse_h256(seed)?)?;
+
+        let share = match Share::parse(&nonce, &header, &mix) {
+            Ok(share) => share,
+            Err(e) => return Some(e),
+        };
+        // hashimoto-light 要读取上万个缓存项，放到阻塞线程池执行，不占用会话的工作线程
+        tokio::task::spawn_blocking(move || light.verify(&share, &target).err())
+            .await
+            .unwrap_or(None)
+    }
+
+    // 记录发给矿机的任务及份额难度。开启本地验证时提前生成该纪元的验证缓存
+    fn remember_job(&mut self, job: &ServerJob) {
+        if let Some(job_id) = job.get_job_id() {
+            self.job_sent.put(job_id, Instant::now());
+        }
+        if let Some(target) = job.get_target().and_then(|t| ethash::parse_h256(&t)) {
+            self.worker.share_diff = ethash::difficulty(&target);
+        }
+        if !self.config.verify_shares {
+            return;
+        }
+        if let (Some(header), Some(seed), Some(target)) =
+            (job.get_job_id(), job.get_seed_hash(), job.get_target())
+        {
+            if let Some(seed) = ethash::parse_h256(&seed) {
+                ethash::epochs().get(&seed);
+            }
+            self.jobs.put(header, (seed, target));
+        }
+    }
+
+    // 矿池返回份额结果。记录提交往返耗时，id 不是未返回的份额时返回 false
+    fn submit_returned(&mut self, id: u64) -> bool {
+        match self.submits.pop(&id) {
+            Some(sent) => {
+                self.worker.submit_rtt.record(sent.elapsed());
+                true
+            }
+            None => false,
+        }
+    }
+
+    // received 为读到这一行的时间
+    async fn on_pool_line(&mut self, buf: &str, received: Instant) -> Result<()> {
+        #[cfg(debug_assertions)]
+        log::info!("1    ---- Worker : {}  Send Rpc {}", self.worker_name, buf);
+        match parse_server(buf) {
+            ServerLine::Result(mut result_rpc) => {
+                if result_rpc.id == CLIENT_LOGIN {
+                    if self.client_timeout_sec == 1 {
+                        self.client_timeout_sec = 60;
+                    }
+                    self.worker.logind();
+                } else if result_rpc.id == CLIENT_SUBHASHRATE
+                    || result_rpc.id == CLIENT_GETWORK
+                    || result_rpc.id == SUBSCRIBE
+                {
+                } else if result_rpc.result {
+                    self.submit_returned(result_rpc.id);
+                    self.worker.share_accept();
+                } else if self.submit_returned(result_rpc.id) {
+                    // 矿池可能在之后的份额提交后才拒绝较早的份额
+                    self.worker.share_reject();
+                    log::warn!("拒绝原因 {}", buf);
+                    handle_error_for_worker(&self.worker_name, buf.as_bytes());
+                }
+
+                let rejected_login = result_rpc.id == CLIENT_LOGIN && !result_rpc.result;
+                result_rpc.id = self.rpc_id;
+                if let Err(e) = self.write_worker(&result_rpc).await {
+                    log::error!("Error Worker Write Socket {:?}", e);
+                }
+                if rejected_login {
+                    return Err(SessionError::PoolRejectedLogin
+                        .msg(format!("矿池拒绝矿机登录 : {}", self.worker_name)));
+                }
+            }
+            ServerLine::Job(job) => self.on_pool_job(job, received).await?,
+            ServerLine::Other => {
+                log::warn!("未找到的交易 {}", buf);
+                if let Err(e) =
+                    write_to_socket_string(&mut self.worker_w, buf, &self.worker_name).await
+                {
+                    log::error!("Error Worker Write Socket {:?}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_pool_job(&mut self, mut job: ServerJob, received: Instant) -> Result<()> {
+        self.pool_job_idx = self.pool_job_idx.wrapping_add(1);
+        self.job_diff_change(&job);
+        if let Some(job_id) = job.get_job_id() {
+            self.pool_jobs.push(job_id, job.height());
+        }
+
+        if self.config.share != 0 {
+            match self.share_job(&mut job).await {
+                Some(()) => self.worker.job_delay.record(received.elapsed()),
+                None => log::error!("任务没有分配成功! at_count :{}", self.pool_job_idx),
+            }
+            return Ok(());
+        }
+
+        self.reply_id(&mut job);
+        self.remember_job(&job);
+        self.ledger.job(control::POOL_ACCOUNT);
+        if let Err(e) = self.write_worker(&job).await {
+            info!("{}", e);
+            return Err(SessionError::MinerWrite.wrap(e));
+        }
+        self.worker.job_delay.record(received.elapsed());
+        Ok(())
+    }
+
+    // 抽水矿池发来的一行。与矿机当前高度相同的任务留作抽水任务
+    fn on_fee_line(&mut self, i: usize, buf: &str) {
+        let fee = self.channels[i].fee;
+        match parse_server(buf) {
+            ServerLine::Result(result_rpc) => {
+                #[cfg(debug_assertions)]
+                debug!("收到{}矿机返回 {:?}", fee, result_rpc);
+                let ignore = [CLIENT_LOGIN, CLIENT_SUBHASHRATE, CLIENT_GETWORK, 999];
+                if !result_rpc.result && !ignore.contains(&result_rpc.id) {
+                    handle_error_for_worker(&self.worker_name, buf.as_bytes());
+                }
+            }
+            ServerLine::Job(job) => {
+                #[cfg(debug_assertions)]
+                debug!("收到{}矿机任务 {:?}", fee, job);
+                let diff = job.get_diff();
+                self.job_diff_change(&job);
+                if let Some(job_id) = job.get_job_id() {
+                    self.channels[i].jobs.push(job_id, job.height());
+                }
+                if diff == self.job_diff {
+                    if let Some(job_id) = job.get_job_id() {
+                        self.channels[i]
+                            .unsend
+                            .push_back((job_id, job.into_result()));
+                    }
+                }
+            }
+            ServerLine::Other => {
+                if serde_json::from_str::<ServerRootErrorValue>(buf).is_err() {
+                    log::error!("未找到的交易 {} {}", fee, buf);
+                }
+            }
+        }
+    }
+
+    // 区块高度增加时，旧高度的抽水任务全部作废
+    fn job_diff_change(&mut self, job: &ServerJob) {
+        let diff = job.get_diff();
+        if diff > self.job_diff {
+            self.job_diff = diff;
+            for channel in &mut self.channels {
+                channel.unsend.clear();
+            }
+        }
+    }
+
+    // 矿机请求任务或提交份额时收到的任务，id 改回矿机请求的 id
+    fn reply_id(&self, job: &mut ServerJob) {
+        let id = job.get_id();
+        if id != 0 && (id == CLIENT_GETWORK || id == self.worker.share_index) {
+            job.set_id(self.rpc_id);
+        }
+    }
+
+    // 把矿池任务分给矿机。按策略顺序尝试换成抽水任务，都不抽水时发送原任务
+    async fn share_job(&mut self, job: &mut ServerJob) -> Option<()> {
+        let mut normal_job = job.clone();
+        let diff = format!("0x{:x}", self.job_diff);
+        for i in 0..self.channels.len() {
+            if self.take_fee_job(i, job, &diff) {
+                let fee = self.channels[i].fee;
+                self.remember_job(job);
+                self.ledger.job(fee.as_str());
+                return match self.write_worker(job).await {
+                    Ok(_) => {
+                        #[cfg(debug_assertions)]
+                        info!("写入成功{}任务 {:?}", fee, job);
+                        Some(())
+                    }
+                    Err(e) => {
+                        log::error!("{} {}", fee, e);
+                        None
+                    }
+                };
+            }
+        }
+
+        self.reply_id(&mut normal_job);
+        let job_id = normal_job.get_job_id()?;
+        if self.channels.iter().any(|c| c.sent.contains(&job_id)) {
+            return Some(());
+        }
+
+        self.send_normal_jobs.put(job_id, 0);
+        self.remember_job(&normal_job);
+        self.ledger.job(control::POOL_ACCOUNT);
+        match self.write_worker(&normal_job).await {
+            Ok(_) => {
+                #[cfg(debug_assertions)]
+                info!("写入成功普通任务 {:?}", normal_job);
+                Some(())
+            }
+            Err(e) => {
+                info!("{}", e);
+                None
+            }
+        }
+    }
+
+    // 从抽水通道取一个任务替换 job。策略不抽水或没有可用任务时返回 false
+    fn take_fee_job(&mut self, i: usize, job: &mut ServerJob, diff: &str) -> bool {
+        if !self.policy.take(self.channels[i].fee, self.pool_job_idx) {
+            return false;
+        }
+
+        let fee_job = loop {
+            let fee_job = match self.channels[i].unsend.pop_back() {
+                Some(fee_job) => fee_job,
+                None => {
+                    #[cfg(debug_assertions)]
+                    debug!("!!!!没有{}任务了。", self.channels[i].fee);
+                    return false;
+                }
+            };
+
+            let sent_elsewhere = self
+                .channels
+                .iter()
+                .enumerate()
+                .any(|(j, c)| j != i && c.sent.contains(&fee_job.0));
+            if sent_elsewhere {
+                continue;
+            }
+
+            if self.send_normal_jobs.contains(&fee_job.0) {
+                // 矿机已经从矿池收到过这个任务。拿走这个任务的权限，本次发送原任务
+                let diff = job.get_diff();
+                self.channels[i].sent.put(fee_job.0, (0, diff));
+                return false;
+            }
+            break fee_job;
+        };
+
+        job.set_result(fee_job.1);
+        job.set_diff(diff.to_string());
+        let diff = job.get_diff();
+        self.channels[i].sent.put(fee_job.0, (0, diff)).is_none()
+    }
+}
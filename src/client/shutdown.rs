@@ -0,0 +1,145 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use log::info;
+use tokio::{sync::watch, time::Instant};
+
+// 停机协调。收到停机信号后各监听端口停止接受新连接，
+// 已有会话在排空超时内完成未返回的份额后断开
+#[derive(Debug)]
+pub struct Shutdown {
+    closed: watch::Sender<bool>,
+    stopping: watch::Sender<bool>,
+    finished: watch::Sender<bool>,
+    sessions: AtomicUsize,
+}
+
+// 会话存活期间持有，用于统计未断开的会话数
+#[derive(Debug)]
+pub struct Session {
+    shutdown: Arc<Shutdown>,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.shutdown.sessions.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (closed, _) = watch::channel(false);
+        let (stopping, _) = watch::channel(false);
+        let (finished, _) = watch::channel(false);
+        Self {
+            closed,
+            stopping,
+            finished,
+            sessions: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn session(self: &Arc<Self>) -> Session {
+        self.sessions.fetch_add(1, Ordering::SeqCst);
+        Session {
+            shutdown: self.clone(),
+        }
+    }
+
+    pub fn sessions(&self) -> usize {
+        self.sessions.load(Ordering::SeqCst)
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        *self.stopping.borrow()
+    }
+
+    // 停止接受新连接时收到通知
+    pub fn closed(&self) -> watch::Receiver<bool> {
+        self.closed.subscribe()
+    }
+
+    // 开始停机时收到通知
+    pub fn stopping(&self) -> watch::Receiver<bool> {
+        self.stopping.subscribe()
+    }
+
+    // 排空结束时收到通知
+    pub fn finished(&self) -> watch::Receiver<bool> {
+        self.finished.subscribe()
+    }
+
+    // 停止接受新连接，已有会话继续服务直到矿机断开。超过 timeout 后不再等待
+    pub async fn linger(&self, timeout: Duration) {
+        let _ = self.closed.send(true);
+        self.wait_sessions(timeout).await;
+    }
+
+    // 通知所有会话停机并等待会话断开。超过 timeout 后不再等待
+    pub async fn drain(&self, timeout: Duration) {
+        let _ = self.closed.send(true);
+        let _ = self.stopping.send(true);
+        self.wait_sessions(timeout).await;
+
+        match self.sessions() {
+            0 => info!("✅ 所有连接已排空"),
+            n => info!("⌛ 排空超时 仍有 {} 个连接未断开", n),
+        }
+        let _ = self.finished.send(true);
+    }
+
+    async fn wait_sessions(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        while self.sessions() > 0 && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 等待通知。发送端已关闭时直接返回
+pub async fn wait(rx: &mut watch::Receiver<bool>) {
+    while !*rx.borrow() {
+        if rx.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+#[test]
+fn test_drain() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let shutdown = Arc::new(Shutdown::new());
+        let mut stopping = shutdown.stopping();
+        let session = shutdown.session();
+        assert_eq!(shutdown.sessions(), 1);
+
+        // 平滑升级时只停止接受新连接，会话不受影响
+        shutdown.linger(Duration::from_millis(200)).await;
+        assert!(!shutdown.is_stopping());
+        assert_eq!(shutdown.sessions(), 1);
+
+        let s = shutdown.clone();
+        let handle = tokio::spawn(async move { s.drain(Duration::from_secs(5)).await });
+
+        wait(&mut stopping).await;
+        assert!(shutdown.is_stopping());
+        drop(session);
+
+        let mut finished = shutdown.finished();
+        wait(&mut finished).await;
+        assert_eq!(shutdown.sessions(), 0);
+        handle.await.unwrap();
+    });
+}
@@ -0,0 +1,158 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use futures::future::select_all;
+use socket2::{Domain, Socket, Type};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::client::{
+    acl::Listener,
+    upgrade::{self, Inherited},
+};
+
+// 解析监听或出口地址。支持 0.0.0.0 / :: / [::1] 格式
+pub fn parse_ip(ip: &str) -> Result<IpAddr> {
+    let ip = ip.trim().trim_start_matches('[').trim_end_matches(']');
+    match ip.parse() {
+        Ok(ip) => Ok(ip),
+        Err(_) => bail!("无法解析的IP地址 {}", ip),
+    }
+}
+
+// 出口地址为空时由系统选择
+pub fn parse_source(source: &str) -> Result<Option<IpAddr>> {
+    if source.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(parse_ip(source)?))
+}
+
+// 按配置的地址列表监听同一端口。列表为空时监听 0.0.0.0，端口为 0 时不监听。
+// 只配置 :: 时为双栈监听，同时配置了 IPv4 地址时 IPv6 只监听 IPv6。
+// 升级时优先使用旧进程传递过来的监听 socket
+pub fn bind_listeners(
+    listener: Listener,
+    addrs: &[String],
+    port: u16,
+    inherited: &mut Inherited,
+) -> Result<Vec<TcpListener>> {
+    if port == 0 {
+        return Ok(vec![]);
+    }
+
+    let mut ips = addrs
+        .iter()
+        .filter(|a| !a.trim().is_empty())
+        .map(|a| parse_ip(a))
+        .collect::<Result<Vec<IpAddr>>>()?;
+    if ips.is_empty() {
+        ips.push(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    }
+
+    let only_v6 = ips.iter().any(|ip| ip.is_ipv4());
+    let mut listeners = vec![];
+    for ip in ips {
+        let addr = SocketAddr::new(ip, port);
+        if let Some(l) = upgrade::take(inherited, &upgrade::listener_key(listener, &addr))? {
+            listeners.push(l);
+            continue;
+        }
+
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        socket.set_reuse_address(true)?;
+        if addr.is_ipv6() {
+            socket.set_only_v6(only_v6)?;
+        }
+        if let Err(e) = socket.bind(&addr.into()) {
+            bail!("监听 {} 失败: {}", addr, e);
+        }
+        socket.listen(1024)?;
+        socket.set_nonblocking(true)?;
+        listeners.push(TcpListener::from_std(socket.into())?);
+    }
+
+    Ok(listeners)
+}
+
+// 在多个监听地址上等待新连接
+pub async fn accept(listeners: &[TcpListener]) -> io::Result<(TcpStream, SocketAddr)> {
+    let (res, _, _) = select_all(listeners.iter().map(|l| Box::pin(l.accept()))).await;
+    res
+}
+
+// 监听地址列表，用于启动日志
+pub fn local_addrs(listeners: &[TcpListener]) -> String {
+    listeners
+        .iter()
+        .filter_map(|l| l.local_addr().ok())
+        .map(|a| a.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+// 连接矿池。多网卡主机可指定出口地址
+pub fn connect_timeout(
+    addr: &SocketAddr,
+    source: Option<IpAddr>,
+    timeout: Duration,
+) -> io::Result<std::net::TcpStream> {
+    let source = match source {
+        Some(source) => source,
+        None => return std::net::TcpStream::connect_timeout(addr, timeout),
+    };
+
+    if source.is_ipv4() != addr.is_ipv4() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("出口地址 {} 与矿池地址 {} 协议不一致", source, addr),
+        ));
+    }
+
+    let socket = Socket::new(Domain::for_address(*addr), Type::STREAM, None)?;
+    socket.bind(&SocketAddr::new(source, 0).into())?;
+    socket.connect_timeout(&(*addr).into(), timeout)?;
+    Ok(socket.into())
+}
+
+#[test]
+fn test_parse_ip() {
+    assert_eq!(
+        parse_ip("0.0.0.0").unwrap(),
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    );
+    assert_eq!(parse_ip("[::]").unwrap(), "::".parse::<IpAddr>().unwrap());
+    assert!(parse_ip("localhost").is_err());
+    assert_eq!(parse_source("").unwrap(), None);
+}
+
+#[test]
+fn test_bind_and_connect() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let mut inherited = Inherited::new();
+        assert!(bind_listeners(Listener::Tcp, &[], 0, &mut inherited)
+            .unwrap()
+            .is_empty());
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let listeners =
+            bind_listeners(Listener::Tcp, &["127.0.0.1".into()], port, &mut inherited).unwrap();
+        let addr = listeners[0].local_addr().unwrap();
+
+        let source = parse_source("127.0.0.1").unwrap();
+        let stream = connect_timeout(&addr, source, Duration::from_secs(1)).unwrap();
+        let (_, peer) = accept(&listeners).await.unwrap();
+        assert_eq!(peer, stream.local_addr().unwrap());
+
+        let v6: SocketAddr = "[::1]:1".parse().unwrap();
+        assert!(connect_timeout(&v6, source, Duration::from_secs(1)).is_err());
+    });
+}
@@ -10,66 +10,74 @@ use tokio::sync::broadcast;
 
 use tokio::sync::mpsc::UnboundedSender;
 
-use crate::jobs::JobQueue;
+use crate::client::acl::Listener;
+use crate::client::shutdown;
+use crate::client::record::{Recorder, Side, Tap};
+use crate::client::{proxy_protocol, socket};
 
-use crate::state::Worker;
-use crate::util::config::Settings;
+use crate::util::logger;
 
 use super::*;
 pub async fn accept_tcp(
-    worker_queue: tokio::sync::mpsc::Sender<Worker>,
-    mine_jobs_queue: Arc<JobQueue>,
-    develop_jobs_queue: Arc<JobQueue>,
-    config: Settings,
+    ctx: Arc<Context>,
     _job_send: broadcast::Sender<String>,
-    proxy_fee_sender: broadcast::Sender<(u64, String)>,
-    develop_fee_sender: broadcast::Sender<(u64, String)>,
     _state_send: UnboundedSender<(u64, String)>,
     _dev_state_send: UnboundedSender<(u64, String)>,
+    listeners: Vec<TcpListener>,
 ) -> Result<()> {
-    let address = format!("0.0.0.0:{}", config.tcp_port);
-    let listener = TcpListener::bind(address.clone()).await?;
-    info!("😄 Accepting Tcp On: {}", &address);
+    if listeners.is_empty() {
+        info!("TCP端口未开启");
+        return Ok(());
+    }
 
-    loop {
-        let (stream, addr) = listener.accept().await?;
-        info!("😄 Accepting Tcp connection from {}", addr);
+    info!("😄 Accepting Tcp On: {}", socket::local_addrs(&listeners));
 
-        let config = config.clone();
-        let workers = worker_queue.clone();
+    let mut closed = ctx.shutdown.closed();
+    loop {
+        let (stream, addr) = tokio::select! {
+            res = socket::accept(&listeners) => res?,
+            _ = shutdown::wait(&mut closed) => {
+                info!("🛑 停止接受TCP连接");
+                return Ok(());
+            }
+        };
+        if ctx.control.is_draining(Listener::Tcp) {
+            info!("🚰 {}端口排空中 拒绝连接 {}", Listener::Tcp, addr);
+            continue;
+        }
 
-        let mine_jobs_queue = mine_jobs_queue.clone();
-        let develop_jobs_queue = develop_jobs_queue.clone();
-        let proxy_fee_sender = proxy_fee_sender.clone();
-        let develop_fee_sender = develop_fee_sender.clone();
+        let ctx = ctx.clone();
 
-        tokio::spawn(async move {
-            transfer(
-                workers,
-                stream,
-                &config,
-                mine_jobs_queue,
-                develop_jobs_queue,
-                proxy_fee_sender,
-                develop_fee_sender,
-            )
-            .await
-        });
+        tokio::spawn(logger::session(addr, async move {
+            transfer(&ctx, stream, addr).await
+        }));
     }
 }
 
-async fn transfer(
-    worker_queue: tokio::sync::mpsc::Sender<Worker>,
-    tcp_stream: TcpStream,
-    config: &Settings,
-    mine_jobs_queue: Arc<JobQueue>,
-    develop_jobs_queue: Arc<JobQueue>,
-    proxy_fee_sender: broadcast::Sender<(u64, String)>,
-    develop_fee_sender: broadcast::Sender<(u64, String)>,
-) -> Result<()> {
+async fn transfer(ctx: &Context, mut tcp_stream: TcpStream, addr: SocketAddr) -> Result<()> {
+    let config = &ctx.config;
+    let (addr, local) = match proxy_protocol::accept(
+        &mut tcp_stream,
+        addr,
+        config.tcp_proxy_protocol,
+        Listener::Tcp,
+        &ctx.ip_filter,
+    )
+    .await
+    {
+        Some(addr) => addr,
+        None => return Ok(()),
+    };
+    logger::set_peer(&addr);
+    let session = ctx.control.register(Listener::Tcp, addr);
+    info!("😄 Accepting Tcp connection from {}", addr);
+    let proxy_header = proxy_protocol::upstream_header(config.pool_proxy_protocol, addr, local);
+
+    let recorder = Recorder::open(config, &addr);
+    let tcp_stream = Tap::new(tcp_stream, recorder.clone(), Side::Miner);
     let (worker_r, worker_w) = split(tcp_stream);
     let worker_r = BufReader::new(worker_r);
-    let (stream_type, pools) = match crate::client::get_pool_ip_and_type(&config) {
+    let (stream_type, pools) = match ctx.control.pools(config) {
         Some(pool) => pool,
         None => {
             info!("未匹配到矿池 或 均不可链接。请修改后重试");
@@ -77,34 +85,16 @@ async fn transfer(
         }
     };
 
+    let conn = Conn {
+        addr,
+        is_encrypted: false,
+        control: session,
+        recorder,
+    };
     if stream_type == crate::client::TCP {
-        handle_tcp_pool(
-            worker_queue,
-            worker_r,
-            worker_w,
-            &pools,
-            &config,
-            mine_jobs_queue,
-            develop_jobs_queue,
-            proxy_fee_sender,
-            develop_fee_sender,
-            false,
-        )
-        .await
+        handle_tcp_pool(ctx, worker_r, worker_w, &pools, conn, proxy_header).await
     } else if stream_type == crate::client::SSL {
-        handle_tls_pool(
-            worker_queue,
-            worker_r,
-            worker_w,
-            &pools,
-            &config,
-            mine_jobs_queue,
-            develop_jobs_queue,
-            proxy_fee_sender,
-            develop_fee_sender,
-            false,
-        )
-        .await
+        handle_tls_pool(ctx, worker_r, worker_w, &pools, conn, proxy_header).await
     } else {
         log::error!("致命错误：未找到支持的矿池BUG 请上报");
         return Ok(());
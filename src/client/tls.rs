@@ -12,74 +12,95 @@ use tokio::sync::broadcast;
 use tokio::sync::mpsc::UnboundedSender;
 
 use super::*;
-use crate::jobs::JobQueue;
+use crate::client::acl::Listener;
+use crate::client::error::{self, SessionError};
+use crate::client::shutdown;
+use crate::client::record::{Recorder, Side, Tap};
+use crate::client::{proxy_protocol, socket};
 use crate::state::Worker;
-use crate::util::config::Settings;
+use crate::util::logger;
 
 pub async fn accept_tcp_with_tls(
-    worker_queue: tokio::sync::mpsc::Sender<Worker>,
-    mine_jobs_queue: Arc<JobQueue>,
-    develop_jobs_queue: Arc<JobQueue>,
-    config: Settings,
+    ctx: Arc<Context>,
     _job_send: broadcast::Sender<String>,
-    proxy_fee_sender: broadcast::Sender<(u64, String)>,
-    develop_fee_sender: broadcast::Sender<(u64, String)>,
     _state_send: UnboundedSender<(u64, String)>,
     _dev_state_send: UnboundedSender<(u64, String)>,
+    listeners: Vec<TcpListener>,
     cert: Identity,
 ) -> Result<()> {
-    let address = format!("0.0.0.0:{}", config.ssl_port);
-    let listener = TcpListener::bind(address.clone()).await?;
-    info!("😄 Accepting Tls On: {}", &address);
+    if listeners.is_empty() {
+        info!("SSL端口未开启");
+        return Ok(());
+    }
+
+    info!("😄 Accepting Tls On: {}", socket::local_addrs(&listeners));
 
     let tls_acceptor =
         tokio_native_tls::TlsAcceptor::from(native_tls::TlsAcceptor::builder(cert).build()?);
+    let mut closed = ctx.shutdown.closed();
     loop {
         // Asynchronously wait for an inbound TcpStream.
-        let (stream, addr) = listener.accept().await?;
-        info!("😄 accept connection from {}", addr);
-        let workers = worker_queue.clone();
+        let (stream, addr) = tokio::select! {
+            res = socket::accept(&listeners) => res?,
+            _ = shutdown::wait(&mut closed) => {
+                info!("🛑 停止接受SSL连接");
+                return Ok(());
+            }
+        };
+        if ctx.control.is_draining(Listener::Ssl) {
+            info!("🚰 {}端口排空中 拒绝连接 {}", Listener::Ssl, addr);
+            continue;
+        }
 
-        let config = config.clone();
+        let ctx = ctx.clone();
         let acceptor = tls_acceptor.clone();
-        let mine_jobs_queue = mine_jobs_queue.clone();
-        let develop_jobs_queue = develop_jobs_queue.clone();
-        let proxy_fee_sender = proxy_fee_sender.clone();
-        let develop_fee_sender = develop_fee_sender.clone();
 
-        tokio::spawn(async move {
-            transfer_ssl(
-                workers,
-                stream,
-                acceptor,
-                &config,
-                mine_jobs_queue,
-                develop_jobs_queue,
-                proxy_fee_sender,
-                develop_fee_sender,
-            )
-            .await
-        });
+        tokio::spawn(logger::session(addr, async move {
+            transfer_ssl(&ctx, stream, acceptor, addr).await
+        }));
     }
 }
 
 async fn transfer_ssl(
-    worker_queue: tokio::sync::mpsc::Sender<Worker>,
-    tcp_stream: TcpStream,
+    ctx: &Context,
+    mut tcp_stream: TcpStream,
     tls_acceptor: tokio_native_tls::TlsAcceptor,
-    config: &Settings,
-    mine_jobs_queue: Arc<JobQueue>,
-    develop_jobs_queue: Arc<JobQueue>,
-    proxy_fee_sender: broadcast::Sender<(u64, String)>,
-    develop_fee_sender: broadcast::Sender<(u64, String)>,
+    addr: SocketAddr,
 ) -> Result<()> {
-    let client_stream = tls_acceptor.accept(tcp_stream).await?;
+    let config = &ctx.config;
+    let (addr, local) = match proxy_protocol::accept(
+        &mut tcp_stream,
+        addr,
+        config.ssl_proxy_protocol,
+        Listener::Ssl,
+        &ctx.ip_filter,
+    )
+    .await
+    {
+        Some(addr) => addr,
+        None => return Ok(()),
+    };
+    logger::set_peer(&addr);
+    let session = ctx.control.register(Listener::Ssl, addr);
+    info!("😄 accept connection from {}", addr);
+    let proxy_header = proxy_protocol::upstream_header(config.pool_proxy_protocol, addr, local);
+
+    let client_stream = match tls_acceptor.accept(tcp_stream).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            let e = SessionError::TlsHandshake.wrap(e.into());
+            error::report(&ctx.worker_queue, Worker::default(), &addr, &e).await;
+            return Err(e);
+        }
+    };
+    let recorder = Recorder::open(config, &addr);
+    let client_stream = Tap::new(client_stream, recorder.clone(), Side::Miner);
     let (worker_r, worker_w) = split(client_stream);
     let worker_r = BufReader::new(worker_r);
 
     info!("😄 tls_acceptor Success!");
 
-    let (stream_type, pools) = match crate::client::get_pool_ip_and_type(&config) {
+    let (stream_type, pools) = match ctx.control.pools(config) {
         Some(pool) => pool,
         None => {
             info!("未匹配到矿池 或 均不可链接。请修改后重试");
@@ -87,34 +108,16 @@ async fn transfer_ssl(
         }
     };
 
+    let conn = Conn {
+        addr,
+        is_encrypted: false,
+        control: session,
+        recorder,
+    };
     if stream_type == crate::client::TCP {
-        handle_tcp_pool(
-            worker_queue,
-            worker_r,
-            worker_w,
-            &pools,
-            &config,
-            mine_jobs_queue,
-            develop_jobs_queue,
-            proxy_fee_sender,
-            develop_fee_sender,
-            false,
-        )
-        .await
+        handle_tcp_pool(ctx, worker_r, worker_w, &pools, conn, proxy_header).await
     } else if stream_type == crate::client::SSL {
-        handle_tls_pool(
-            worker_queue,
-            worker_r,
-            worker_w,
-            &pools,
-            &config,
-            mine_jobs_queue,
-            develop_jobs_queue,
-            proxy_fee_sender,
-            develop_fee_sender,
-            false,
-        )
-        .await
+        handle_tls_pool(ctx, worker_r, worker_w, &pools, conn, proxy_header).await
     } else {
         log::error!("致命错误：未找到支持的矿池BUG 请上报");
         return Ok(());
@@ -0,0 +1,81 @@
+use lru::LruCache;
+
+// 每个上游记住的最近任务数
+const RECENT_JOBS: usize = 100;
+
+// 过期份额返回给矿机的错误。与矿池的错误码一致
+pub const STALE_CODE: u64 = 21;
+pub const STALE_MESSAGE: &str = "Stale share";
+
+// 提交份额时任务的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    // 任务属于当前区块
+    Fresh,
+    // 任务属于旧区块。值为落后的区块数
+    Stale(u64),
+    // 没有下发过或已经太旧
+    Unknown,
+}
+
+// 一个上游(矿池或抽水矿池)最近下发的任务及其区块高度
+pub struct JobTracker {
+    jobs: LruCache<String, u64>,
+    // 当前区块高度
+    height: u64,
+}
+
+impl JobTracker {
+    pub fn new() -> Self {
+        Self {
+            jobs: LruCache::new(RECENT_JOBS),
+            height: 0,
+        }
+    }
+
+    // 记录任务。任务不带高度时算作当前区块
+    pub fn push(&mut self, job_id: String, height: Option<u64>) {
+        let height = match height {
+            Some(height) => {
+                self.height = self.height.max(height);
+                height
+            }
+            None => self.height,
+        };
+        self.jobs.put(job_id, height);
+    }
+
+    pub fn classify(&self, job_id: &str) -> Freshness {
+        match self.jobs.peek(job_id) {
+            None => Freshness::Unknown,
+            Some(height) if *height < self.height => Freshness::Stale(self.height - height),
+            Some(_) => Freshness::Fresh,
+        }
+    }
+}
+
+impl Default for JobTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_job_tracker() {
+    let mut jobs = JobTracker::new();
+    jobs.push("0xa".into(), Some(10));
+    jobs.push("0xb".into(), None);
+    assert_eq!(jobs.classify("0xa"), Freshness::Fresh);
+    assert_eq!(jobs.classify("0xb"), Freshness::Fresh);
+    assert_eq!(jobs.classify("0xc"), Freshness::Unknown);
+
+    jobs.push("0xc".into(), Some(12));
+    assert_eq!(jobs.classify("0xa"), Freshness::Stale(2));
+    assert_eq!(jobs.classify("0xb"), Freshness::Stale(2));
+    assert_eq!(jobs.classify("0xc"), Freshness::Fresh);
+
+    // 高度回退的任务不改变当前高度
+    jobs.push("0xd".into(), Some(11));
+    assert_eq!(jobs.classify("0xd"), Freshness::Stale(1));
+    assert_eq!(jobs.classify("0xc"), Freshness::Fresh);
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsStr,
+    io,
+    net::TcpListener as StdTcpListener,
+    os::linux::net::SocketAddrExt,
+    os::unix::{
+        ffi::OsStrExt,
+        io::{AsRawFd, FromRawFd, RawFd},
+        net::{SocketAddr as UnixAddr, UnixDatagram},
+    },
+    process::{Child, Command},
+};
+
+use log::{info, warn};
+use tokio::net::TcpListener;
+
+use crate::client::acl::Listener;
+
+// 升级时通过环境变量把监听 socket 传给新进程
+// 格式: TCP@0.0.0.0:14444=3;SSL@[::]:8443=4
+const INHERIT_ENV: &str = "PROXY_INHERIT_FDS";
+
+// systemd Type=notify 时的通知 socket
+const NOTIFY_ENV: &str = "NOTIFY_SOCKET";
+
+// 监听地址 -> 文件描述符
+pub type Inherited = HashMap<String, RawFd>;
+
+pub fn listener_key(listener: Listener, addr: &std::net::SocketAddr) -> String {
+    format!("{}@{}", listener, addr)
+}
+
+// 读取旧进程传递过来的监听 socket。读取后清除环境变量
+pub fn inherited_listeners() -> Inherited {
+    let mut fds = Inherited::new();
+    let val = match env::var(INHERIT_ENV) {
+        Ok(val) => val,
+        Err(_) => return fds,
+    };
+    env::remove_var(INHERIT_ENV);
+
+    for item in val.split(';').filter(|s| !s.is_empty()) {
+        match item
+            .rsplit_once('=')
+            .map(|(k, fd)| (k, fd.parse::<RawFd>()))
+        {
+            Some((key, Ok(fd))) => {
+                fds.insert(key.to_string(), fd);
+            }
+            _ => warn!("无法解析的继承监听 {}", item),
+        }
+    }
+    fds
+}
+
+// 取出继承的监听 socket
+pub fn take(inherited: &mut Inherited, key: &str) -> io::Result<Option<TcpListener>> {
+    let fd = match inherited.remove(key) {
+        Some(fd) => fd,
+        None => return Ok(None),
+    };
+
+    // fd 由旧进程通过 exec 继承，只会在这里被取出一次
+    let listener = unsafe { StdTcpListener::from_raw_fd(fd) };
+    listener.set_nonblocking(true)?;
+    info!("♻️ 继承监听 {}", key);
+    Ok(Some(TcpListener::from_std(listener)?))
+}
+
+// 新配置中已不存在的监听直接关闭
+pub fn close_unused(inherited: Inherited) {
+    for (key, fd) in inherited {
+        info!("♻️ 关闭不再使用的监听 {}", key);
+        drop(unsafe { StdTcpListener::from_raw_fd(fd) });
+    }
+}
+
+// 当前进程所有监听 socket 的 fd，用于升级时传递
+pub fn listener_fds(listener: Listener, listeners: &[TcpListener]) -> Vec<(String, RawFd)> {
+    listeners
+        .iter()
+        .filter_map(|l| {
+            l.local_addr()
+                .ok()
+                .map(|addr| (listener_key(listener, &addr), l.as_raw_fd()))
+        })
+        .collect()
+}
+
+// 以相同的启动参数启动新进程，并把监听 socket 传给它
+pub fn spawn(fds: &[(String, RawFd)]) -> io::Result<Child> {
+    for (_, fd) in fds {
+        clear_cloexec(*fd)?;
+    }
+
+    let val = fds
+        .iter()
+        .map(|(key, fd)| format!("{}={}", key, fd))
+        .collect::<Vec<String>>()
+        .join(";");
+
+    let mut args = env::args_os();
+    let exe = match args.next() {
+        Some(exe) => exe,
+        None => env::current_exe()?.into_os_string(),
+    };
+
+    Command::new(exe).args(args).env(INHERIT_ENV, val).spawn()
+}
+
+// 通知 systemd 本进程已就绪并成为服务的主进程。平滑升级后新进程接替旧进程，
+// 旧进程退出时 systemd 不会结束服务。未由 systemd 启动时不做任何事
+pub fn notify_ready() -> io::Result<()> {
+    let path = match env::var_os(NOTIFY_ENV) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let msg = format!("READY=1\nMAINPID={}", std::process::id());
+
+    let socket = UnixDatagram::unbound()?;
+    let path = path.as_bytes();
+    match path.strip_prefix(b"@") {
+        // 抽象命名空间的 socket
+        Some(name) => socket.send_to_addr(msg.as_bytes(), &UnixAddr::from_abstract_name(name)?)?,
+        None => socket.send_to(msg.as_bytes(), OsStr::from_bytes(path))?,
+    };
+    Ok(())
+}
+
+fn clear_cloexec(fd: RawFd) -> io::Result<()> {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_inherit_listener() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let std = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = std.local_addr().unwrap();
+        let key = listener_key(Listener::Tcp, &addr);
+        let fd = std::os::unix::io::IntoRawFd::into_raw_fd(std);
+
+        env::set_var(INHERIT_ENV, format!("{}={};bad", key, fd));
+        let mut inherited = inherited_listeners();
+        assert!(env::var(INHERIT_ENV).is_err());
+        assert_eq!(inherited.get(&key), Some(&fd));
+
+        let listener = take(&mut inherited, &key).unwrap().unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+        assert!(take(&mut inherited, &key).unwrap().is_none());
+        assert_eq!(listener_fds(Listener::Tcp, &[listener]), vec![(key, fd)]);
+    });
+}
+
+#[test]
+fn test_notify_ready() {
+    let path = env::temp_dir().join(format!("proxy-notify-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let socket = UnixDatagram::bind(&path).unwrap();
+
+    env::set_var(NOTIFY_ENV, &path);
+    notify_ready().unwrap();
+    env::remove_var(NOTIFY_ENV);
+
+    let mut buf = [0u8; 128];
+    let n = socket.recv(&mut buf).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        format!("READY=1\nMAINPID={}", std::process::id())
+    );
+}
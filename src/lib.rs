@@ -1,9 +1,12 @@
 const SPLIT: u8 = b'\n';
 const WALLET: &'static str = "0x98be5c44d574b96b320dffb0ccff116bda433b8e";
 
+pub mod admin;
 pub mod agent;
+pub mod alert;
 pub mod client;
 pub mod jobs;
+pub mod mock;
 pub mod protocol;
 pub mod state;
 pub mod util;
@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use log::{debug, warn};
+use serde_json::{json, Value};
+use tokio::{
+    io::{split, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, WriteHalf},
+    net::TcpStream,
+    time,
+};
+
+// 模拟矿机配置
+#[derive(Debug, Clone)]
+pub struct MinerConfig {
+    // 代理地址 host:port
+    pub server: String,
+    pub tls: bool,
+    // 使用 EthereumStratum 协议，否则使用 ETHPROXY
+    pub stratum: bool,
+    pub wallet: String,
+    // 矿机名前缀，第 i 台矿机名为 前缀+i
+    pub worker: String,
+    // 上报的算力 (H/s)
+    pub hashrate: u64,
+    // 提交份额间隔(毫秒)
+    pub share_interval: u64,
+    // 上报算力间隔(毫秒)
+    pub hashrate_interval: u64,
+    // 断线后重连间隔(毫秒)
+    pub reconnect_delay: u64,
+}
+
+impl Default for MinerConfig {
+    fn default() -> Self {
+        Self {
+            server: "127.0.0.1:4444".into(),
+            tls: false,
+            stratum: false,
+            wallet: "0x98be5c44d574b96b320dffb0ccff116bda433b8e".into(),
+            worker: "sim".into(),
+            hashrate: 100_000_000,
+            share_interval: 10_000,
+            hashrate_interval: 30_000,
+            reconnect_delay: 1000,
+        }
+    }
+}
+
+impl MinerConfig {
+    pub fn worker_name(&self, index: usize) -> String {
+        format!("{}{}", self.worker, index)
+    }
+}
+
+// 单台模拟矿机的统计
+#[derive(Debug, Default, Clone)]
+pub struct MinerStats {
+    pub name: String,
+    pub connects: u64,
+    pub disconnects: u64,
+    pub jobs: u64,
+    pub submitted: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub stale: u64,
+    pub malformed: u64,
+    pub latency_count: u64,
+    pub latency_total: Duration,
+    pub latency_max: Duration,
+}
+
+impl MinerStats {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            ..Default::default()
+        }
+    }
+
+    // 已返回结果的份额中被接受的比例
+    pub fn accept_ratio(&self) -> f64 {
+        match self.accepted + self.rejected + self.stale {
+            0 => 0.0,
+            n => self.accepted as f64 / n as f64,
+        }
+    }
+
+    // 份额提交到返回结果的平均延迟
+    pub fn avg_latency(&self) -> Duration {
+        match self.latency_count {
+            0 => Duration::ZERO,
+            n => self.latency_total / n as u32,
+        }
+    }
+
+    pub fn merge(&mut self, other: &MinerStats) {
+        self.connects += other.connects;
+        self.disconnects += other.disconnects;
+        self.jobs += other.jobs;
+        self.submitted += other.submitted;
+        self.accepted += other.accepted;
+        self.rejected += other.rejected;
+        self.stale += other.stale;
+        self.malformed += other.malformed;
+        self.latency_count += other.latency_count;
+        self.latency_total += other.latency_total;
+        self.latency_max = self.latency_max.max(other.latency_max);
+    }
+
+    fn latency(&mut self, latency: Duration) {
+        self.latency_count += 1;
+        self.latency_total += latency;
+        self.latency_max = self.latency_max.max(latency);
+    }
+}
+
+// 一直运行的模拟矿机。断线后按配置的间隔重连
+pub async fn run(config: Arc<MinerConfig>, index: usize, stats: Arc<Mutex<MinerStats>>) {
+    let name = config.worker_name(index);
+    loop {
+        if let Err(e) = connect(&config, &name, &stats).await {
+            debug!("模拟矿机 {} 断开: {}", name, e);
+        }
+        stats.lock().unwrap().disconnects += 1;
+        time::sleep(Duration::from_millis(config.reconnect_delay)).await;
+    }
+}
+
+async fn connect(config: &MinerConfig, name: &str, stats: &Arc<Mutex<MinerStats>>) -> Result<()> {
+    let stream = TcpStream::connect(&config.server).await?;
+    stream.set_nodelay(true)?;
+
+    if config.tls {
+        // 本地测试多为自签名证书
+        let connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .build()?;
+        let connector = tokio_native_tls::TlsConnector::from(connector);
+        let host = config.server.rsplit_once(':').map_or("", |(host, _)| host);
+        let stream = connector.connect(host, stream).await?;
+        session(config, name, stream, stats).await
+    } else {
+        session(config, name, stream, stats).await
+    }
+}
+
+async fn session<S>(
+    config: &MinerConfig,
+    name: &str,
+    stream: S,
+    stats: &Arc<Mutex<MinerStats>>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    stats.lock().unwrap().connects += 1;
+    let (r, mut w) = split(stream);
+    let mut lines = BufReader::new(r).lines();
+    let user = format!("{}.{}", config.wallet, name);
+
+    if config.stratum {
+        let subscribe = json!({"id": 1, "method": "mining.subscribe", "params": [name, "EthereumStratum/1.0.0"]});
+        write_line(&mut w, &subscribe).await?;
+        let authorize = json!({"id": 2, "method": "mining.authorize", "params": [user, "x"]});
+        write_line(&mut w, &authorize).await?;
+    } else {
+        let login = json!({"id": 1, "method": "eth_submitLogin", "params": [config.wallet, "x"], "worker": name});
+        write_line(&mut w, &login).await?;
+        let work = json!({"id": 5, "method": "eth_getWork", "params": []});
+        write_line(&mut w, &work).await?;
+    }
+
+    // 错开各矿机的提交时间
+    let offset = rand::random::<u64>() % config.share_interval.max(1);
+    let mut share_ticker = time::interval_at(
+        time::Instant::now() + Duration::from_millis(offset),
+        Duration::from_millis(config.share_interval.max(1)),
+    );
+    let mut hashrate_ticker =
+        time::interval(Duration::from_millis(config.hashrate_interval.max(1)));
+    share_ticker.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+    hashrate_ticker.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+    let mut job: Option<String> = None;
+    let mut pending: HashMap<u64, Instant> = HashMap::new();
+    let mut next_id = 100;
+    let mut nonce: u64 = rand::random();
+
+    loop {
+        tokio::select! {
+            res = lines.next_line() => {
+                let line = match res? {
+                    Some(line) => line,
+                    None => bail!("连接已关闭"),
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let rpc: Value = match serde_json::from_str(&line) {
+                    Ok(rpc) => rpc,
+                    Err(_) => {
+                        stats.lock().unwrap().malformed += 1;
+                        continue;
+                    }
+                };
+
+                if let Some(id) = rpc["id"].as_u64() {
+                    if let Some(sent) = pending.remove(&id) {
+                        let mut stats = stats.lock().unwrap();
+                        stats.latency(sent.elapsed());
+                        if rpc["result"] == true {
+                            stats.accepted += 1;
+                        } else if rpc["error"][0] == 21 {
+                            stats.stale += 1;
+                        } else {
+                            stats.rejected += 1;
+                        }
+                        continue;
+                    }
+                }
+
+                let new_job = if rpc["method"] == "mining.notify" {
+                    rpc["params"][0].as_str()
+                } else if rpc["result"].is_array() && !config.stratum {
+                    rpc["result"][0].as_str()
+                } else {
+                    None
+                };
+                if let Some(new_job) = new_job {
+                    if job.as_deref() != Some(new_job) {
+                        stats.lock().unwrap().jobs += 1;
+                        job = Some(new_job.to_string());
+                    }
+                } else if rpc["error"].is_array() || rpc["error"].is_object() {
+                    warn!("模拟矿机 {} 收到错误 {}", name, line);
+                }
+            },
+            _ = share_ticker.tick() => {
+                let job = match &job {
+                    Some(job) => job,
+                    None => continue,
+                };
+                nonce = nonce.wrapping_add(1);
+                next_id += 1;
+                let submit = if config.stratum {
+                    json!({"id": next_id, "method": "mining.submit", "params": [user, job, format!("{:012x}", nonce & 0xffff_ffff_ffff)]})
+                } else {
+                    json!({
+                        "id": next_id,
+                        "method": "eth_submitWork",
+                        "params": [format!("0x{:016x}", nonce), job, format!("0x{:064x}", nonce)],
+                        "worker": name
+                    })
+                };
+                pending.insert(next_id, Instant::now());
+                stats.lock().unwrap().submitted += 1;
+                write_line(&mut w, &submit).await?;
+            },
+            _ = hashrate_ticker.tick() => {
+                let hashrate = json!({
+                    "id": 6,
+                    "method": "eth_submitHashrate",
+                    "params": [format!("0x{:x}", config.hashrate), format!("0x{:064x}", nonce)],
+                    "worker": name
+                });
+                write_line(&mut w, &hashrate).await?;
+            }
+        }
+    }
+}
+
+async fn write_line<W>(w: &mut WriteHalf<W>, rpc: &Value) -> Result<()>
+where
+    W: AsyncWrite,
+{
+    w.write_all(format!("{}\n", rpc).as_bytes()).await?;
+    Ok(())
+}
+
+#[test]
+fn test_miner_against_mock_pool() {
+    use super::pool::{MockPool, Script};
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let pool = MockPool::new(Script {
+            job_interval: 100,
+            reject_every: 2,
+            ..Default::default()
+        });
+        tokio::spawn(pool.clone().serve_tcp(listener));
+
+        for stratum in [false, true] {
+            let config = Arc::new(MinerConfig {
+                server: server.clone(),
+                stratum,
+                share_interval: 20,
+                ..Default::default()
+            });
+            let stats = Arc::new(Mutex::new(MinerStats::new(config.worker_name(0))));
+            let miner = tokio::spawn(run(config, 0, stats.clone()));
+            time::sleep(Duration::from_millis(500)).await;
+            miner.abort();
+
+            let stats = stats.lock().unwrap();
+            assert_eq!(stats.connects, 1);
+            assert!(stats.jobs > 0);
+            assert!(stats.accepted > 0);
+            assert!(stats.rejected > 0);
+            assert!(stats.accept_ratio() > 0.0 && stats.accept_ratio() < 1.0);
+            assert!(stats.latency_count > 0);
+        }
+    });
+}
@@ -0,0 +1,31 @@
+// 本地模拟矿池，用于在没有真实矿池的情况下测试代理
+pub mod miner;
+pub mod pool;
+pub mod replay;
+
+// 难度对应的份额目标值 (2^256-1)/difficulty，64 位十六进制
+pub fn target(difficulty: u64) -> String {
+    let d = difficulty.max(1) as u128;
+    let mut rem: u128 = 0;
+    let mut target = String::with_capacity(64);
+    for _ in 0..4 {
+        let cur = (rem << 64) | u64::MAX as u128;
+        target += &format!("{:016x}", (cur / d) as u64);
+        rem = cur % d;
+    }
+    target
+}
+
+// EthereumStratum 协议下 mining.set_difficulty 的难度。1.0 对应 2^32
+pub fn stratum_difficulty(difficulty: u64) -> f64 {
+    difficulty as f64 / 4294967296.0
+}
+
+#[test]
+fn test_target() {
+    assert_eq!(target(1), "f".repeat(64));
+    assert_eq!(target(0), "f".repeat(64));
+    assert_eq!(target(1 << 32), format!("00000000{}", "f".repeat(56)));
+    assert_eq!(&target(4_000_000_000)[..16], "0000000112e0be82");
+    assert_eq!(stratum_difficulty(1 << 31), 0.5);
+}
@@ -0,0 +1,568 @@
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use config::{Config, ConfigError, File};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{
+    io::{split, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, WriteHalf},
+    net::TcpListener,
+    time,
+};
+use tokio_native_tls::TlsAcceptor;
+
+use super::{stratum_difficulty, target};
+
+// 格式错误的数据行
+pub const MALFORMED_LINE: &str = "{\"id\":0,\"jsonrpc\":\"2.0\",\"result\":[\"0x";
+
+// 模拟矿池的行为脚本。所有规则按计数触发，结果可重现
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Script {
+    // 下发任务间隔(毫秒)
+    pub job_interval: u64,
+    // 份额难度
+    pub difficulty: u64,
+    // 起始区块高度。切换区块时加一
+    pub height: u64,
+    // 每个连接保留的有效任务数，提交更早的任务按过期处理
+    pub keep_jobs: usize,
+    // 每 N 个任务切换一次区块，之前的任务全部过期。0 为不切换
+    pub clean_every: u64,
+    // 每 N 个份额拒绝一次。0 为不拒绝
+    pub reject_every: u64,
+    // 拒绝这些矿机的全部份额。可以是 钱包.矿机名 或 矿机名
+    pub reject_workers: Vec<String>,
+    // 收到第 N 个请求后不回复直接断开。0 为不断开
+    pub disconnect_after: u64,
+    // 每 N 个请求延迟 slow_delay 毫秒后回复。0 为不延迟
+    pub slow_every: u64,
+    pub slow_delay: u64,
+    // 每 N 个任务前多下发一行格式错误的数据。0 为不下发
+    pub malformed_every: u64,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Self {
+            job_interval: 5000,
+            difficulty: 4_000_000_000,
+            height: 1,
+            keep_jobs: 4,
+            clean_every: 0,
+            reject_every: 0,
+            reject_workers: vec![],
+            disconnect_after: 0,
+            slow_every: 0,
+            slow_delay: 1000,
+            malformed_every: 0,
+        }
+    }
+}
+
+impl Script {
+    pub fn new(file_path: &str) -> Result<Self, ConfigError> {
+        let mut s = Config::default();
+        s.merge(File::with_name(file_path).required(false))?;
+        s.try_into()
+    }
+
+    fn reject_worker(&self, worker: &str) -> bool {
+        let name = worker.split_once('.').map(|(_, name)| name);
+        self.reject_workers
+            .iter()
+            .any(|w| w == worker || Some(w.as_str()) == name)
+    }
+}
+
+// 模拟矿池统计，供测试断言
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub connections: AtomicU64,
+    pub logins: AtomicU64,
+    pub jobs: AtomicU64,
+    pub hashrates: AtomicU64,
+    pub accepted: AtomicU64,
+    pub rejected: AtomicU64,
+    pub stale: AtomicU64,
+}
+
+impl Stats {
+    pub fn get(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Job {
+    id: String,
+    header: String,
+    seed: String,
+    height: u64,
+}
+
+impl Job {
+    // header 带上矿池实例标识，多个模拟矿池同一高度的任务互不相同
+    fn new(pool: u64, seq: u64, height: u64) -> Self {
+        Self {
+            id: format!("{:x}", seq),
+            header: format!("{:016x}{:048x}", pool, seq),
+            seed: format!("{:064x}", height / 30000 + 1),
+            height,
+        }
+    }
+
+    // ETHPROXY 以 header 作为任务标识，EthereumStratum 以任务ID作为标识
+    fn matches(&self, job_id: &str) -> bool {
+        let job_id = job_id.trim_start_matches("0x");
+        job_id == self.id || job_id == self.header
+    }
+}
+
+// 单个矿机连接的状态
+#[derive(Debug, Default)]
+struct Session {
+    id: u64,
+    stratum: bool,
+    authorized: bool,
+    worker: String,
+    jobs: VecDeque<Job>,
+    height: u64,
+    requests: u64,
+    submits: u64,
+    sent: u64,
+}
+
+#[derive(Debug)]
+pub struct MockPool {
+    script: Script,
+    stats: Stats,
+    id: u64,
+    seq: AtomicU64,
+}
+
+impl MockPool {
+    pub fn new(script: Script) -> Arc<Self> {
+        Arc::new(Self {
+            script,
+            stats: Stats::default(),
+            id: rand::random(),
+            seq: AtomicU64::new(0),
+        })
+    }
+
+    pub fn script(&self) -> &Script {
+        &self.script
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub async fn serve_tcp(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        info!("😄 模拟矿池 TCP 监听 {}", listener.local_addr()?);
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let pool = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = pool.session(stream, addr).await {
+                    warn!("模拟矿池连接 {} 断开: {}", addr, e);
+                }
+            });
+        }
+    }
+
+    pub async fn serve_tls(
+        self: Arc<Self>,
+        listener: TcpListener,
+        acceptor: TlsAcceptor,
+    ) -> Result<()> {
+        info!("😄 模拟矿池 SSL 监听 {}", listener.local_addr()?);
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let pool = self.clone();
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let stream = match acceptor.accept(stream).await {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("模拟矿池 SSL 握手失败 {}: {}", addr, e);
+                        return;
+                    }
+                };
+                if let Err(e) = pool.session(stream, addr).await {
+                    warn!("模拟矿池连接 {} 断开: {}", addr, e);
+                }
+            });
+        }
+    }
+
+    async fn session<S>(self: Arc<Self>, stream: S, addr: SocketAddr) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite,
+    {
+        let mut session = Session {
+            id: self.stats.connections.fetch_add(1, Ordering::SeqCst) + 1,
+            height: self.script.height,
+            ..Default::default()
+        };
+        info!("✅ 模拟矿池新连接 {} 会话 {}", addr, session.id);
+
+        let (r, mut w) = split(stream);
+        let mut lines = BufReader::new(r).lines();
+        let mut ticker = time::interval(Duration::from_millis(self.script.job_interval.max(1)));
+        ticker.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        ticker.tick().await;
+
+        loop {
+            tokio::select! {
+                res = lines.next_line() => {
+                    let line = match res? {
+                        Some(line) => line,
+                        None => {
+                            info!("模拟矿池连接 {} 已关闭", addr);
+                            return Ok(());
+                        }
+                    };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    session.requests += 1;
+                    if self.script.disconnect_after != 0
+                        && session.requests >= self.script.disconnect_after
+                    {
+                        info!("🔌 模拟矿池主动断开 {}", addr);
+                        w.shutdown().await?;
+                        return Ok(());
+                    }
+                    if self.script.slow_every != 0 && session.requests.is_multiple_of(self.script.slow_every) {
+                        time::sleep(Duration::from_millis(self.script.slow_delay)).await;
+                    }
+
+                    for reply in self.handle(&mut session, &line) {
+                        write_line(&mut w, &reply).await?;
+                    }
+                },
+                _ = ticker.tick(), if session.authorized => {
+                    for line in self.new_job(&mut session) {
+                        write_line(&mut w, &line).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle(&self, s: &mut Session, line: &str) -> Vec<String> {
+        let req: Value = match serde_json::from_str(line) {
+            Ok(req) => req,
+            Err(_) => {
+                warn!("模拟矿池收到无法解析的请求 {}", line);
+                return vec![];
+            }
+        };
+
+        let id = req["id"].clone();
+        let params = req["params"].as_array().cloned().unwrap_or_default();
+        let param = |i: usize| params.get(i).and_then(|p| p.as_str()).unwrap_or("");
+
+        match req["method"].as_str().unwrap_or("") {
+            "eth_submitLogin" => {
+                s.worker = match req["worker"].as_str() {
+                    Some(worker) => format!("{}.{}", param(0), worker),
+                    None => param(0).to_string(),
+                };
+                s.authorized = true;
+                self.stats.logins.fetch_add(1, Ordering::SeqCst);
+                let mut replies =
+                    vec![json!({"id": id, "jsonrpc": "2.0", "result": true}).to_string()];
+                replies.append(&mut self.new_job(s));
+                replies
+            }
+            "eth_getWork" => {
+                if s.jobs.is_empty() {
+                    self.next_job(s);
+                }
+                vec![json!({"id": id, "jsonrpc": "2.0", "result": self.eth_job(s)}).to_string()]
+            }
+            "eth_submitHashrate" => {
+                self.stats.hashrates.fetch_add(1, Ordering::SeqCst);
+                vec![json!({"id": id, "jsonrpc": "2.0", "result": true}).to_string()]
+            }
+            "eth_submitWork" => {
+                let worker = match req["worker"].as_str() {
+                    Some(worker) => worker.to_string(),
+                    None => s.worker.clone(),
+                };
+                vec![self.submit(s, id, param(1), &worker)]
+            }
+            "mining.subscribe" => {
+                s.stratum = true;
+                vec![json!({
+                    "id": id,
+                    "result": [
+                        ["mining.notify", format!("{:016x}", s.id), "EthereumStratum/1.0.0"],
+                        format!("{:04x}", s.id & 0xffff)
+                    ],
+                    "error": null
+                })
+                .to_string()]
+            }
+            "mining.extranonce.subscribe" => {
+                vec![json!({"id": id, "result": true, "error": null}).to_string()]
+            }
+            "mining.authorize" => {
+                s.stratum = true;
+                s.worker = param(0).to_string();
+                s.authorized = true;
+                self.stats.logins.fetch_add(1, Ordering::SeqCst);
+                let mut replies = vec![
+                    json!({"id": id, "result": true, "error": null}).to_string(),
+                    json!({
+                        "id": null,
+                        "method": "mining.set_difficulty",
+                        "params": [stratum_difficulty(self.script.difficulty)]
+                    })
+                    .to_string(),
+                ];
+                replies.append(&mut self.new_job(s));
+                replies
+            }
+            "mining.submit" => {
+                let worker = param(0).to_string();
+                vec![self.submit(s, id, param(1), &worker)]
+            }
+            method => {
+                warn!("模拟矿池不支持的方法 {}", method);
+                vec![
+                    json!({"id": id, "result": null, "error": [20, "Unsupported method", null]})
+                        .to_string(),
+                ]
+            }
+        }
+    }
+
+    fn submit(&self, s: &mut Session, id: Value, job_id: &str, worker: &str) -> String {
+        s.submits += 1;
+        let (code, message) = if !s.jobs.iter().any(|j| j.matches(job_id)) {
+            self.stats.stale.fetch_add(1, Ordering::SeqCst);
+            (21, "Job not found (=stale)")
+        } else if self.script.reject_worker(worker)
+            || (self.script.reject_every != 0 && s.submits.is_multiple_of(self.script.reject_every))
+        {
+            self.stats.rejected.fetch_add(1, Ordering::SeqCst);
+            (23, "Low difficulty share")
+        } else {
+            self.stats.accepted.fetch_add(1, Ordering::SeqCst);
+            return match s.stratum {
+                true => json!({"id": id, "result": true, "error": null}).to_string(),
+                false => json!({"id": id, "jsonrpc": "2.0", "result": true}).to_string(),
+            };
+        };
+
+        json!({"id": id, "result": false, "error": [code, message, null]}).to_string()
+    }
+
+    // 生成新任务，返回需要下发的数据行
+    fn new_job(&self, s: &mut Session) -> Vec<String> {
+        let clean = self.next_job(s);
+        let mut lines = vec![];
+        if self.script.malformed_every != 0 && s.sent.is_multiple_of(self.script.malformed_every) {
+            lines.push(MALFORMED_LINE.to_string());
+        }
+
+        let job = s.jobs.back().unwrap();
+        if s.stratum {
+            lines.push(
+                json!({
+                    "id": null,
+                    "method": "mining.notify",
+                    "params": [job.id, job.seed, job.header, clean]
+                })
+                .to_string(),
+            );
+        } else {
+            lines.push(json!({"id": 0, "jsonrpc": "2.0", "result": self.eth_job(s)}).to_string());
+        }
+        lines
+    }
+
+    // 返回是否切换了区块
+    fn next_job(&self, s: &mut Session) -> bool {
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst) + 1;
+        self.stats.jobs.fetch_add(1, Ordering::SeqCst);
+        s.sent += 1;
+
+        let clean = self.script.clean_every != 0 && s.sent.is_multiple_of(self.script.clean_every);
+        if clean {
+            s.jobs.clear();
+            s.height += 1;
+        }
+        s.jobs.push_back(Job::new(self.id, seq, s.height));
+        while s.jobs.len() > self.script.keep_jobs.max(1) {
+            s.jobs.pop_front();
+        }
+        clean
+    }
+
+    fn eth_job(&self, s: &Session) -> Vec<String> {
+        let job = s.jobs.back().unwrap();
+        vec![
+            format!("0x{}", job.header),
+            format!("0x{}", job.seed),
+            format!("0x{}", target(self.script.difficulty)),
+            format!("0x{:x}", job.height),
+        ]
+    }
+}
+
+async fn write_line<W>(w: &mut WriteHalf<W>, line: &str) -> Result<()>
+where
+    W: AsyncWrite,
+{
+    w.write_all(format!("{}\n", line).as_bytes()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+async fn start(script: Script) -> (Arc<MockPool>, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let pool = MockPool::new(script);
+    tokio::spawn(pool.clone().serve_tcp(listener));
+    (pool, addr)
+}
+
+#[cfg(test)]
+async fn request<R>(
+    w: &mut tokio::net::tcp::OwnedWriteHalf,
+    lines: &mut tokio::io::Lines<R>,
+    req: Value,
+    replies: usize,
+) -> Vec<Value>
+where
+    R: tokio::io::AsyncBufRead + Unpin,
+{
+    w.write_all(format!("{}\n", req).as_bytes()).await.unwrap();
+    let mut res = vec![];
+    while res.len() < replies {
+        let line = lines.next_line().await.unwrap().unwrap();
+        let rpc: Value = serde_json::from_str(&line).unwrap_or(Value::Null);
+        // 跳过定时下发的任务，只保留请求的回复及紧随其后的通知
+        if res.is_empty() && rpc["id"] != req["id"] {
+            continue;
+        }
+        res.push(rpc);
+    }
+    res
+}
+
+#[test]
+fn test_eth_proxy_submit() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let script = Script {
+            job_interval: 60_000,
+            keep_jobs: 1,
+            reject_every: 2,
+            ..Default::default()
+        };
+        let (pool, addr) = start(script).await;
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (r, mut w) = stream.into_split();
+        let mut lines = BufReader::new(r).lines();
+
+        let login = json!({"id": 1, "method": "eth_submitLogin", "params": ["0x00", "x"], "worker": "w1"});
+        let res = request(&mut w, &mut lines, login, 2).await;
+        assert_eq!(res[0]["result"], true);
+        let header = res[1]["result"][0].as_str().unwrap().to_string();
+        assert_eq!(res[1]["result"][2], format!("0x{}", target(4_000_000_000)));
+
+        let submit = |id: u64, header: &str| {
+            json!({"id": id, "method": "eth_submitWork", "params": ["0x01", header, "0x02"]})
+        };
+        let res = request(&mut w, &mut lines, submit(2, &header), 1).await;
+        assert_eq!(res[0]["result"], true);
+        let res = request(&mut w, &mut lines, submit(3, &header), 1).await;
+        assert_eq!(res[0]["error"][0], 23);
+
+        let work = json!({"id": 4, "method": "eth_getWork", "params": []});
+        let res = request(&mut w, &mut lines, work, 1).await;
+        assert_eq!(res[0]["result"][0], header);
+
+        let res = request(&mut w, &mut lines, submit(5, "0xdead"), 1).await;
+        assert_eq!(res[0]["error"][0], 21);
+
+        let stats = pool.stats();
+        assert_eq!(Stats::get(&stats.accepted), 1);
+        assert_eq!(Stats::get(&stats.rejected), 1);
+        assert_eq!(Stats::get(&stats.stale), 1);
+    });
+}
+
+#[test]
+fn test_stratum_rules() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let script = Script {
+            job_interval: 200,
+            clean_every: 2,
+            malformed_every: 2,
+            reject_workers: vec!["bad".into()],
+            disconnect_after: 5,
+            ..Default::default()
+        };
+        let (_pool, addr) = start(script).await;
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (r, mut w) = stream.into_split();
+        let mut lines = BufReader::new(r).lines();
+
+        let subscribe = json!({"id": 1, "method": "mining.subscribe", "params": ["miner", "EthereumStratum/1.0.0"]});
+        let res = request(&mut w, &mut lines, subscribe, 1).await;
+        assert_eq!(res[0]["result"][0][2], "EthereumStratum/1.0.0");
+
+        let authorize = json!({"id": 2, "method": "mining.authorize", "params": ["0x00.bad", "x"]});
+        let res = request(&mut w, &mut lines, authorize, 3).await;
+        assert_eq!(res[0]["result"], true);
+        assert_eq!(res[1]["method"], "mining.set_difficulty");
+        assert_eq!(res[2]["method"], "mining.notify");
+        let first = res[2]["params"][0].as_str().unwrap().to_string();
+
+        // 第二个任务切换区块，并且前面多一行错误数据
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert_eq!(line, MALFORMED_LINE);
+        let line = lines.next_line().await.unwrap().unwrap();
+        let notify: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(notify["params"][3], true);
+        let second = notify["params"][0].as_str().unwrap().to_string();
+
+        let submit = |id: u64, job: &str| {
+            json!({"id": id, "method": "mining.submit", "params": ["0x00.bad", job, "01"]})
+        };
+        let res = request(&mut w, &mut lines, submit(3, &first), 1).await;
+        assert_eq!(res[0]["error"][0], 21);
+        let res = request(&mut w, &mut lines, submit(4, &second), 1).await;
+        assert_eq!(res[0]["error"][0], 23);
+
+        // 第 5 个请求不回复直接断开
+        w.write_all(b"{\"id\":5,\"method\":\"mining.extranonce.subscribe\",\"params\":[]}\n")
+            .await
+            .unwrap();
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) => assert!(!line.contains("\"id\":5")),
+                _ => break,
+            }
+        }
+    });
+}
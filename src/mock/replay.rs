@@ -0,0 +1,194 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+use tokio::{
+    io::{split, AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader, Lines},
+    net::TcpListener,
+    sync::{broadcast, mpsc},
+    time,
+};
+
+use super::pool::{MockPool, Script};
+use crate::{
+    client::{
+        acl::{IpFilter, Listener},
+        auth::Auth,
+        control::Control,
+        handle_tcp_pool,
+        record::{Direction, Event},
+        shutdown::Shutdown,
+        Conn, Context,
+    },
+    jobs::JobQueue,
+    state::Worker,
+    util::config::Settings,
+};
+
+// 代理输出与录制不一致的一行
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    // 录制文件中的事件序号
+    pub index: usize,
+    pub dir: Direction,
+    pub expected: String,
+    // None 表示等待超时
+    pub actual: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    // 需要比对的代理输出行数
+    pub checked: usize,
+    pub mismatches: Vec<Mismatch>,
+    // 回放结束后代理上报的矿机统计
+    pub worker: Option<Worker>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+// 把录制的会话重新送入代理。
+// 录制中矿机和矿池发出的行按原顺序发给代理，代理发出的行与录制逐行比对。
+// 代理连接的主矿池由回放驱动，抽水矿池使用模拟矿池。
+pub async fn replay(events: &[Event], config: &Settings, timeout: Duration) -> Result<Report> {
+    let pool = TcpListener::bind("127.0.0.1:0").await?;
+    let pools = vec![pool.local_addr()?.to_string()];
+
+    let fee = TcpListener::bind("127.0.0.1:0").await?;
+    let mut config = config.clone();
+    config.share_tcp_address = vec![fee.local_addr()?.to_string()];
+    config.share = 0;
+    config.record_path = String::new();
+    tokio::spawn(
+        MockPool::new(Script {
+            job_interval: 60_000,
+            ..Default::default()
+        })
+        .serve_tcp(fee),
+    );
+
+    let (proxy_end, miner_end) = tokio::io::duplex(64 * 1024);
+    let (worker_r, worker_w) = split(proxy_end);
+    let (workers_tx, mut workers) = mpsc::channel::<Worker>(10);
+    let (proxy_fee_sender, _) = broadcast::channel::<(u64, String)>(10);
+    let (develop_fee_sender, _) = broadcast::channel::<(u64, String)>(10);
+    let addr: SocketAddr = "127.0.0.1:0".parse()?;
+    let shutdown = Arc::new(Shutdown::new());
+    let control = Arc::new(Control::new());
+    let conn = Conn {
+        addr,
+        is_encrypted: false,
+        control: control.register(Listener::Tcp, addr),
+        recorder: None,
+    };
+    // 开发者抽水也连接本地模拟矿池
+    let ctx = Context {
+        ip_filter: Arc::new(IpFilter::new(&config)?),
+        auth: Arc::new(Auth::new(&config)),
+        develop_pools: config.share_tcp_address.clone(),
+        config,
+        worker_queue: workers_tx,
+        mine_jobs_queue: Arc::new(JobQueue::new(100)),
+        develop_jobs_queue: Arc::new(JobQueue::new(100)),
+        proxy_fee_sender,
+        develop_fee_sender,
+        shutdown: shutdown.clone(),
+        control,
+    };
+
+    tokio::spawn(async move {
+        let _ = handle_tcp_pool(&ctx, BufReader::new(worker_r), worker_w, &pools, conn, None).await;
+    });
+
+    let (pool_stream, _) = match time::timeout(timeout, pool.accept()).await {
+        Ok(res) => res?,
+        Err(_) => bail!("代理没有连接回放矿池"),
+    };
+    let (pool_r, mut pool_w) = split(pool_stream);
+    let mut pool_lines = BufReader::new(pool_r).lines();
+    let (miner_r, mut miner_w) = split(miner_end);
+    let mut miner_lines = BufReader::new(miner_r).lines();
+
+    let mut report = Report::default();
+    for (index, event) in events.iter().enumerate() {
+        let line = format!("{}\n", event.line);
+        match event.dir {
+            Direction::MinerToProxy => miner_w.write_all(line.as_bytes()).await?,
+            Direction::PoolToProxy => pool_w.write_all(line.as_bytes()).await?,
+            Direction::ProxyToMiner | Direction::ProxyToPool => {
+                let actual = if event.dir == Direction::ProxyToMiner {
+                    next_line(&mut miner_lines, timeout).await
+                } else {
+                    next_line(&mut pool_lines, timeout).await
+                };
+                report.checked += 1;
+                if !same_line(&event.line, actual.as_deref()) {
+                    report.mismatches.push(Mismatch {
+                        index,
+                        dir: event.dir,
+                        expected: event.line.clone(),
+                        actual,
+                    });
+                }
+            }
+        }
+    }
+
+    // 停机时会话上报矿机统计
+    tokio::spawn(async move { shutdown.drain(timeout).await });
+    report.worker = time::timeout(timeout, workers.recv()).await.ok().flatten();
+    Ok(report)
+}
+
+async fn next_line<R: AsyncRead + Unpin>(
+    lines: &mut Lines<BufReader<R>>,
+    timeout: Duration,
+) -> Option<String> {
+    loop {
+        match time::timeout(timeout, lines.next_line()).await {
+            Ok(Ok(Some(line))) if line.trim().is_empty() => continue,
+            Ok(Ok(Some(line))) => return Some(line),
+            _ => return None,
+        }
+    }
+}
+
+// JSON 按值比较，忽略字段顺序和空白
+fn same_line(expected: &str, actual: Option<&str>) -> bool {
+    let actual = match actual {
+        Some(actual) => actual,
+        None => return false,
+    };
+    match (
+        serde_json::from_str::<Value>(expected),
+        serde_json::from_str::<Value>(actual),
+    ) {
+        (Ok(expected), Ok(actual)) => expected == actual,
+        _ => expected == actual,
+    }
+}
+
+#[test]
+fn test_replay_height_jobs() {
+    let events = crate::client::record::parse(include_str!("captures/height_jobs.jsonl")).unwrap();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let report = replay(&events, &Settings::default(), Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert_eq!(report.checked, 9);
+        assert!(report.is_ok(), "{:?}", report.mismatches);
+
+        // 币印的任务带 height 字段，代理需要原样转发
+        let worker = report.worker.unwrap();
+        assert_eq!(
+            worker.worker,
+            "0x0000000000000000000000000000000000000001.rig1"
+        );
+        assert_eq!(worker.accept_index, 1);
+    });
+}
@@ -0,0 +1,509 @@
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    sync::{Arc, Mutex, OnceLock},
+    time::Instant,
+};
+
+use log::{info, warn};
+use lru::LruCache;
+use tiny_keccak::{Hasher, Keccak};
+
+pub const EPOCH_LENGTH: u64 = 30000;
+
+const CACHE_BYTES_INIT: u64 = 1 << 24;
+const CACHE_BYTES_GROWTH: u64 = 1 << 17;
+const DATASET_BYTES_INIT: u64 = 1 << 30;
+const DATASET_BYTES_GROWTH: u64 = 1 << 23;
+const HASH_BYTES: u64 = 64;
+const MIX_BYTES: u64 = 128;
+const HASH_WORDS: usize = 16;
+const MIX_WORDS: usize = 32;
+const DATASET_PARENTS: u32 = 256;
+const CACHE_ROUNDS: usize = 3;
+const ACCESSES: u32 = 64;
+// 按种子查找纪元时最多尝试的纪元数
+const MAX_EPOCH: u64 = 2048;
+
+pub type H256 = [u8; 32];
+
+fn keccak256(data: &[u8]) -> H256 {
+    let mut out = [0u8; 32];
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    hasher.finalize(&mut out);
+    out
+}
+
+fn keccak512(data: &[u8]) -> [u8; 64] {
+    let mut out = [0u8; 64];
+    let mut hasher = Keccak::v512();
+    hasher.update(data);
+    hasher.finalize(&mut out);
+    out
+}
+
+fn fnv(a: u32, b: u32) -> u32 {
+    a.wrapping_mul(0x01000193) ^ b
+}
+
+fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    let mut i = 2;
+    while i * i <= n {
+        if n.is_multiple_of(i) {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+// 小端字节转为字
+fn to_words(bytes: &[u8], out: &mut [u32]) {
+    for (w, b) in out.iter_mut().zip(bytes.chunks_exact(4)) {
+        *w = u32::from_le_bytes(b.try_into().unwrap());
+    }
+}
+
+fn hash_words(words: &[u32; HASH_WORDS]) -> [u32; HASH_WORDS] {
+    let mut bytes = [0u8; 64];
+    for (b, w) in bytes.chunks_exact_mut(4).zip(words) {
+        b.copy_from_slice(&w.to_le_bytes());
+    }
+    let mut out = [0u32; HASH_WORDS];
+    to_words(&keccak512(&bytes), &mut out);
+    out
+}
+
+pub fn cache_size(epoch: u64) -> u64 {
+    let mut size = CACHE_BYTES_INIT + CACHE_BYTES_GROWTH * epoch - HASH_BYTES;
+    while !is_prime(size / HASH_BYTES) {
+        size -= 2 * HASH_BYTES;
+    }
+    size
+}
+
+pub fn dataset_size(epoch: u64) -> u64 {
+    let mut size = DATASET_BYTES_INIT + DATASET_BYTES_GROWTH * epoch - MIX_BYTES;
+    while !is_prime(size / MIX_BYTES) {
+        size -= 2 * MIX_BYTES;
+    }
+    size
+}
+
+pub fn seed_hash(epoch: u64) -> H256 {
+    let mut seed = [0u8; 32];
+    for _ in 0..epoch {
+        seed = keccak256(&seed);
+    }
+    seed
+}
+
+pub fn epoch_of_seed(seed: &H256) -> Option<u64> {
+    let mut s = [0u8; 32];
+    for epoch in 0..MAX_EPOCH {
+        if &s == seed {
+            return Some(epoch);
+        }
+        s = keccak256(&s);
+    }
+    None
+}
+
+// 解析 32 字节十六进制数，可带 0x 前缀。不足 64 位时按数值在前面补零
+pub fn parse_h256(s: &str) -> Option<H256> {
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    if s.is_empty() || s.len() > 64 {
+        return None;
+    }
+    hex::decode(format!("{:0>64}", s)).ok()?.try_into().ok()
+}
+
+// 难度目标对应的份额难度 2^256 / target
+pub fn difficulty(target: &H256) -> u64 {
+    let target = target.iter().fold(0f64, |acc, b| acc * 256.0 + *b as f64);
+    if target == 0.0 {
+        return 0;
+    }
+    (2f64.powi(256) / target).round() as u64
+}
+
+// 本地验证不通过的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareError {
+    // 参数无法解析
+    Malformed,
+    // 矿机提交的 mix digest 与本地计算不一致。通常是显卡计算出错
+    MixMismatch,
+    // 结果未达到任务难度
+    LowDifficulty,
+}
+
+impl ShareError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareError::Malformed => "malformed",
+            ShareError::MixMismatch => "mix_mismatch",
+            ShareError::LowDifficulty => "low_difficulty",
+        }
+    }
+
+    // 返回给矿机的错误码和错误信息
+    pub fn code(&self) -> u64 {
+        match self {
+            ShareError::LowDifficulty => 23,
+            _ => 20,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            ShareError::Malformed => "Malformed share",
+            ShareError::MixMismatch => "Invalid mix digest",
+            ShareError::LowDifficulty => "Low difficulty share",
+        }
+    }
+}
+
+impl std::fmt::Display for ShareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShareError::Malformed => write!(f, "份额格式错误"),
+            ShareError::MixMismatch => write!(f, "份额哈希错误"),
+            ShareError::LowDifficulty => write!(f, "份额难度不足"),
+        }
+    }
+}
+
+// eth_submitWork 提交的份额
+#[derive(Debug, Clone, PartialEq)]
+pub struct Share {
+    pub nonce: u64,
+    pub header: H256,
+    pub mix: H256,
+}
+
+impl Share {
+    pub fn parse(nonce: &str, header: &str, mix: &str) -> Result<Self, ShareError> {
+        let nonce = nonce
+            .strip_prefix("0x")
+            .or_else(|| nonce.strip_prefix("0X"))
+            .unwrap_or(nonce);
+        Ok(Self {
+            nonce: u64::from_str_radix(nonce, 16).map_err(|_| ShareError::Malformed)?,
+            header: parse_h256(header).ok_or(ShareError::Malformed)?,
+            mix: parse_h256(mix).ok_or(ShareError::Malformed)?,
+        })
+    }
+}
+
+// 一个纪元的验证缓存。按需计算数据集条目，不生成完整数据集
+pub struct LightCache {
+    pub epoch: u64,
+    // 每个节点 16 个字
+    cache: Vec<u32>,
+    dataset_size: u64,
+}
+
+impl LightCache {
+    // 生成较慢(数秒)，不要在异步任务中调用
+    pub fn new(epoch: u64) -> Self {
+        Self::build(
+            epoch,
+            cache_size(epoch),
+            dataset_size(epoch),
+            &seed_hash(epoch),
+        )
+    }
+
+    pub(crate) fn build(epoch: u64, cache_size: u64, dataset_size: u64, seed: &H256) -> Self {
+        let n = (cache_size / HASH_BYTES) as usize;
+        let mut nodes: Vec<[u8; 64]> = Vec::with_capacity(n);
+        nodes.push(keccak512(seed));
+        for i in 1..n {
+            let next = keccak512(&nodes[i - 1]);
+            nodes.push(next);
+        }
+
+        for _ in 0..CACHE_ROUNDS {
+            for i in 0..n {
+                let v = u32::from_le_bytes(nodes[i][..4].try_into().unwrap()) as usize % n;
+                let mut x = nodes[(i + n - 1) % n];
+                for (a, b) in x.iter_mut().zip(nodes[v].iter()) {
+                    *a ^= b;
+                }
+                nodes[i] = keccak512(&x);
+            }
+        }
+
+        let mut cache = vec![0u32; n * HASH_WORDS];
+        for (words, node) in cache.chunks_exact_mut(HASH_WORDS).zip(nodes.iter()) {
+            to_words(node, words);
+        }
+        Self {
+            epoch,
+            cache,
+            dataset_size,
+        }
+    }
+
+    fn node(&self, i: usize) -> &[u32] {
+        &self.cache[i * HASH_WORDS..(i + 1) * HASH_WORDS]
+    }
+
+    fn dataset_item(&self, i: u32) -> [u32; HASH_WORDS] {
+        let n = self.cache.len() / HASH_WORDS;
+        let mut mix = [0u32; HASH_WORDS];
+        mix.copy_from_slice(self.node(i as usize % n));
+        mix[0] ^= i;
+        mix = hash_words(&mix);
+
+        for j in 0..DATASET_PARENTS {
+            let parent = fnv(i ^ j, mix[j as usize % HASH_WORDS]) as usize % n;
+            for (m, p) in mix.iter_mut().zip(self.node(parent)) {
+                *m = fnv(*m, *p);
+            }
+        }
+        hash_words(&mix)
+    }
+
+    // 返回 (mix digest, 结果)
+    pub fn hashimoto(&self, header: &H256, nonce: u64) -> (H256, H256) {
+        let mut seed = [0u8; 40];
+        seed[..32].copy_from_slice(header);
+        seed[32..].copy_from_slice(&nonce.to_le_bytes());
+        let s = keccak512(&seed);
+        let mut s_words = [0u32; HASH_WORDS];
+        to_words(&s, &mut s_words);
+
+        let mut mix = [0u32; MIX_WORDS];
+        for (k, m) in mix.iter_mut().enumerate() {
+            *m = s_words[k % HASH_WORDS];
+        }
+
+        let rows = (self.dataset_size / MIX_BYTES) as u32;
+        for i in 0..ACCESSES {
+            let p = fnv(i ^ s_words[0], mix[i as usize % MIX_WORDS]) % rows * 2;
+            let mut data = [0u32; MIX_WORDS];
+            data[..HASH_WORDS].copy_from_slice(&self.dataset_item(p));
+            data[HASH_WORDS..].copy_from_slice(&self.dataset_item(p + 1));
+            for (m, d) in mix.iter_mut().zip(data.iter()) {
+                *m = fnv(*m, *d);
+            }
+        }
+
+        let mut digest = [0u8; 32];
+        for (out, m) in digest.chunks_exact_mut(4).zip(mix.chunks_exact(4)) {
+            let c = fnv(fnv(fnv(m[0], m[1]), m[2]), m[3]);
+            out.copy_from_slice(&c.to_le_bytes());
+        }
+
+        let mut buf = [0u8; 96];
+        buf[..64].copy_from_slice(&s);
+        buf[64..].copy_from_slice(&digest);
+        (digest, keccak256(&buf))
+    }
+
+    // 先核对 mix digest，再检查结果是否达到任务难度
+    pub fn verify(&self, share: &Share, target: &H256) -> Result<(), ShareError> {
+        let (mix, result) = self.hashimoto(&share.header, share.nonce);
+        if mix != share.mix {
+            return Err(ShareError::MixMismatch);
+        }
+        if result > *target {
+            return Err(ShareError::LowDifficulty);
+        }
+        Ok(())
+    }
+}
+
+enum Slot {
+    Building(u64),
+    Ready(Arc<LightCache>),
+}
+
+impl Slot {
+    fn epoch(&self) -> u64 {
+        match self {
+            Slot::Building(epoch) => *epoch,
+            Slot::Ready(light) => light.epoch,
+        }
+    }
+}
+
+// 记录的未知种子数
+const UNKNOWN_SEEDS: usize = 16;
+
+// 按任务种子缓存各纪元的验证缓存。首次遇到新种子时在后台线程生成，生成完成前不验证
+pub struct EpochCache {
+    slots: Mutex<HashMap<H256, Slot>>,
+    // 找不到对应纪元的种子。单独记录，避免挤掉验证缓存及重复查找
+    unknown: Mutex<LruCache<H256, ()>>,
+    capacity: usize,
+}
+
+impl EpochCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            slots: Mutex::new(HashMap::new()),
+            unknown: Mutex::new(LruCache::new(UNKNOWN_SEEDS)),
+            capacity,
+        }
+    }
+
+    pub fn get(self: &Arc<Self>, seed: &H256) -> Option<Arc<LightCache>> {
+        match self.slots.lock().unwrap().get(seed) {
+            Some(Slot::Ready(light)) => return Some(light.clone()),
+            Some(Slot::Building(_)) => return None,
+            None => {}
+        }
+        if self.unknown.lock().unwrap().get(seed).is_some() {
+            return None;
+        }
+
+        // 最多计算 MAX_EPOCH 次 keccak，不持有锁
+        let epoch = match epoch_of_seed(seed) {
+            Some(epoch) => epoch,
+            None => {
+                warn!("未知的任务种子 0x{}", hex::encode(seed));
+                self.unknown.lock().unwrap().put(*seed, ());
+                return None;
+            }
+        };
+
+        let mut slots = self.slots.lock().unwrap();
+        // 查找期间其他会话可能已经开始生成
+        if slots.contains_key(seed) {
+            return None;
+        }
+        slots.insert(*seed, Slot::Building(epoch));
+        self.evict(&mut slots);
+
+        let cache = self.clone();
+        let seed = *seed;
+        std::thread::spawn(move || {
+            info!("⏳ 生成纪元 {} 的验证缓存", epoch);
+            let start = Instant::now();
+            let light = Arc::new(LightCache::new(epoch));
+            info!(
+                "✅ 纪元 {} 验证缓存生成完成 用时 {:?}",
+                epoch,
+                start.elapsed()
+            );
+            cache.slots.lock().unwrap().insert(seed, Slot::Ready(light));
+        });
+        None
+    }
+
+    // 直接放入已生成的缓存
+    pub fn insert(&self, seed: H256, light: LightCache) {
+        let mut slots = self.slots.lock().unwrap();
+        slots.insert(seed, Slot::Ready(Arc::new(light)));
+        self.evict(&mut slots);
+    }
+
+    // 超出容量时去掉纪元最小的缓存。生成中的保留
+    fn evict(&self, slots: &mut HashMap<H256, Slot>) {
+        while slots.len() > self.capacity {
+            let oldest = slots
+                .iter()
+                .filter(|(_, slot)| !matches!(slot, Slot::Building(_)))
+                .min_by_key(|(_, slot)| slot.epoch())
+                .map(|(seed, _)| *seed);
+            match oldest {
+                Some(seed) => slots.remove(&seed),
+                None => break,
+            };
+        }
+    }
+}
+
+// 默认保留当前纪元和下一纪元。每个验证缓存约 16MB + 128KB * 纪元号
+const DEFAULT_EPOCHS: usize = 2;
+
+static EPOCHS: OnceLock<Arc<EpochCache>> = OnceLock::new();
+
+// 设置保留的纪元数(verify_epochs)。需在首次验证份额前调用
+pub fn init_epochs(capacity: usize) {
+    let _ = EPOCHS.set(Arc::new(EpochCache::new(capacity)));
+}
+
+// 全局验证缓存
+pub fn epochs() -> &'static Arc<EpochCache> {
+    EPOCHS.get_or_init(|| Arc::new(EpochCache::new(DEFAULT_EPOCHS)))
+}
+
+#[test]
+fn test_sizes() {
+    assert_eq!(cache_size(0), 16776896);
+    assert_eq!(dataset_size(0), 1073739904);
+    assert_eq!(seed_hash(0), [0u8; 32]);
+    assert_eq!(epoch_of_seed(&seed_hash(5)), Some(5));
+    assert_eq!(epoch_of_seed(&[1u8; 32]), None);
+
+    let target = parse_h256("0x0000000112e0be826d694b2e62d01511f12a6061fbaec8bc02357593e70e52ba");
+    assert_eq!(difficulty(&target.unwrap()), 4_000_000_000);
+    assert_eq!(difficulty(&[0u8; 32]), 0);
+}
+
+#[test]
+fn test_unknown_seed() {
+    let cache = Arc::new(EpochCache::new(1));
+    cache.insert(
+        seed_hash(0),
+        LightCache::build(0, 1024, 32 * 1024, &[0u8; 32]),
+    );
+
+    // 未知种子不占用验证缓存的容量
+    assert!(cache.get(&[1u8; 32]).is_none());
+    assert!(cache.get(&[1u8; 32]).is_none());
+    assert!(cache.get(&seed_hash(0)).is_some());
+    assert_eq!(cache.unknown.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_hashimoto() {
+    // go-ethereum 的测试向量: 1KB 缓存 32KB 数据集
+    let light = LightCache::build(0, 1024, 32 * 1024, &[0u8; 32]);
+    let header =
+        parse_h256("0xc9149cc0386e689d789a1c2f3d5d169a61a6218ed30e74414dc736e442ef3d1f").unwrap();
+    let (mix, result) = light.hashimoto(&header, 0);
+    assert_eq!(
+        hex::encode(mix),
+        "e4073cffaef931d37117cefd9afd27ea0f1cad6a981dd2605c4a1ac97c519800"
+    );
+    assert_eq!(
+        hex::encode(result),
+        "d3539235ee2e6f8db665c0a72169f55b7f6c605712330b778ec3944f0eb5a557"
+    );
+
+    let share = Share::parse(
+        "0x0000000000000000",
+        "0xc9149cc0386e689d789a1c2f3d5d169a61a6218ed30e74414dc736e442ef3d1f",
+        "0xe4073cffaef931d37117cefd9afd27ea0f1cad6a981dd2605c4a1ac97c519800",
+    )
+    .unwrap();
+    assert_eq!(light.verify(&share, &[0xff; 32]), Ok(()));
+    assert_eq!(
+        light.verify(&share, &parse_h256("0xd3539235").unwrap()),
+        Err(ShareError::LowDifficulty)
+    );
+    let bad = Share {
+        mix: [0u8; 32],
+        ..share.clone()
+    };
+    assert_eq!(
+        light.verify(&bad, &[0xff; 32]),
+        Err(ShareError::MixMismatch)
+    );
+    assert_eq!(
+        Share::parse("0xzz", "0x00", "0x00"),
+        Err(ShareError::Malformed)
+    );
+}
@@ -2,6 +2,18 @@ use crate::util::hex_to_int;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+// 解析十六进制数值，可带 0x 前缀。格式错误或超出范围时返回 None
+pub fn parse_hex(s: &str) -> Option<u64> {
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    if s.is_empty() {
+        return None;
+    }
+    hex_to_int(s).map(|h| h as u64)
+}
+
 pub trait ServerRpc {
     fn set_id(&mut self, id: u64) -> bool;
     fn get_id(&mut self) -> u64;
@@ -9,6 +21,10 @@ pub trait ServerRpc {
     fn set_diff(&mut self, diff: String) -> bool;
     fn get_diff(&self) -> u64;
     fn get_job_id(&self) -> Option<String>;
+    // 任务的种子哈希。确定 ethash 纪元
+    fn get_seed_hash(&self) -> Option<String>;
+    // 任务的难度目标。结果不大于目标时份额有效
+    fn get_target(&self) -> Option<String>;
 }
 
 pub trait ClientRpc {
@@ -17,11 +33,15 @@ pub trait ClientRpc {
 
     fn get_job_id(&mut self) -> Option<String>;
     fn get_wallet(&mut self) -> Option<String>;
+    fn get_password(&self) -> Option<String>;
 
     fn get_worker_name(&mut self) -> String;
     fn set_worker_name(&mut self, worker_name: &str) -> bool;
 
     fn get_submit_hashrate(&self) -> u64;
+
+    // eth_submitWork 提交的 (nonce, 任务头, mix digest)
+    fn get_submit_work(&self) -> Option<(String, String, String)>;
 }
 
 //{\"id\":1,\"method\":\"eth_submitLogin\",\"params\":[\"0x98be5c44d574b96b320dffb0ccff116bda433b8e\",\"x\"],\"worker\":\"P0002\"}
@@ -43,17 +63,15 @@ impl ClientRpc for Client {
     }
 
     fn get_job_id(&mut self) -> Option<String> {
-        match self.params.get(1) {
-            Some(s) => Some(s.to_string()),
-            None => todo!(),
-        }
+        self.params.get(1).map(|s| s.to_string())
     }
 
     fn get_wallet(&mut self) -> Option<String> {
-        match self.params.get(0) {
-            Some(s) => Some(s.to_string()),
-            None => todo!(),
-        }
+        self.params.first().map(|s| s.to_string())
+    }
+
+    fn get_password(&self) -> Option<String> {
+        self.params.get(1).map(|s| s.to_string())
     }
 
     fn get_worker_name(&mut self) -> String {
@@ -61,24 +79,22 @@ impl ClientRpc for Client {
     }
 
     fn get_submit_hashrate(&self) -> u64 {
-        if let Some(hashrate) = self.params.get(0) {
-            let hashrate = match hex_to_int(&hashrate[2..hashrate.len()]) {
-                Some(g) => g,
-                None => match hex_to_int(&hashrate[..]) {
-                    Some(h) => h,
-                    None => 0,
-                },
-            };
-
-            hashrate as u64
-        } else {
-            0
-        }
+        self.params
+            .first()
+            .and_then(|hashrate| parse_hex(hashrate))
+            .unwrap_or(0)
     }
 
     fn set_worker_name(&mut self, _worker_name: &str) -> bool {
         true
     }
+
+    fn get_submit_work(&self) -> Option<(String, String, String)> {
+        match self.params.as_slice() {
+            [nonce, header, mix, ..] => Some((nonce.clone(), header.clone(), mix.clone())),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -101,17 +117,15 @@ impl ClientRpc for ClientWithWorkerName {
     }
 
     fn get_job_id(&mut self) -> Option<String> {
-        match self.params.get(1) {
-            Some(s) => Some(s.to_string()),
-            None => todo!(),
-        }
+        self.params.get(1).map(|s| s.to_string())
     }
 
     fn get_wallet(&mut self) -> Option<String> {
-        match self.params.get(0) {
-            Some(s) => Some(s.to_string()),
-            None => todo!(),
-        }
+        self.params.first().map(|s| s.to_string())
+    }
+
+    fn get_password(&self) -> Option<String> {
+        self.params.get(1).map(|s| s.to_string())
     }
 
     fn get_worker_name(&mut self) -> String {
@@ -119,24 +133,23 @@ impl ClientRpc for ClientWithWorkerName {
     }
 
     fn get_submit_hashrate(&self) -> u64 {
-        if let Some(hashrate) = self.params.get(0) {
-            let hashrate = match hex_to_int(&hashrate[2..hashrate.len()]) {
-                Some(g) => g,
-                None => match hex_to_int(&hashrate[..]) {
-                    Some(h) => h,
-                    None => 0,
-                },
-            };
-            hashrate as u64
-        } else {
-            0
-        }
+        self.params
+            .first()
+            .and_then(|hashrate| parse_hex(hashrate))
+            .unwrap_or(0)
     }
 
     fn set_worker_name(&mut self, worker_name: &str) -> bool {
         self.worker = worker_name.to_string();
         true
     }
+
+    fn get_submit_work(&self) -> Option<(String, String, String)> {
+        match self.params.as_slice() {
+            [nonce, header, mix, ..] => Some((nonce.clone(), header.clone(), mix.clone())),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -170,41 +183,37 @@ impl ServerRpc for ServerSideJob {
 
 
     fn set_diff(&mut self, diff: String) -> bool {
-        self.result[3] = diff;
-        true
+        // 部分矿池任务不带区块高度
+        match self.result.len() {
+            0..=2 => false,
+            3 => {
+                self.result.push(diff);
+                true
+            }
+            _ => {
+                self.result[3] = diff;
+                true
+            }
+        }
     }
-    
 
     fn get_diff(&self) -> u64 {
-        let job_diff = match self.result.get(3) {
-            Some(diff) => {
-                if diff.contains("0x") {
-                    if let Some(h) = hex_to_int(&diff[2..diff.len()]) {
-                        h as u64
-                    } else if let Some(h) = hex_to_int(&diff[..]) {
-                        h as u64
-                    } else {
-                        0
-                    }
-                } else {
-                    if let Some(h) = hex_to_int(&diff[..]) {
-                        h as u64
-                    } else {
-                        0
-                    }
-                }
-            }
-            None => 0,
-        };
-
-        job_diff
+        self.result
+            .get(3)
+            .and_then(|diff| parse_hex(diff))
+            .unwrap_or(0)
     }
 
     fn get_job_id(&self) -> Option<String> {
-        match self.result.get(0) {
-            Some(s) => Some(s.to_string()),
-            None => None,
-        }
+        self.result.first().map(|s| s.to_string())
+    }
+
+    fn get_seed_hash(&self) -> Option<String> {
+        self.result.get(1).map(|s| s.to_string())
+    }
+
+    fn get_target(&self) -> Option<String> {
+        self.result.get(2).map(|s| s.to_string())
     }
 
     fn set_id(&mut self, id: u64) -> bool {
@@ -234,40 +243,25 @@ impl ServerRpc for Server {
     }
 
     fn get_diff(&self) -> u64 {
-        let job_diff = match self.result.get(3) {
-            Some(diff) => {
-                if diff.contains("0x") {
-                    if let Some(h) = hex_to_int(&diff[2..diff.len()]) {
-                        h as u64
-                    } else if let Some(h) = hex_to_int(&diff[..]) {
-                        h as u64
-                    } else {
-                        log::error!("收到任务JobId 字段不存在{:?}", self);
-                        0
-                    }
-                } else {
-                    if let Some(h) = hex_to_int(&diff[..]) {
-                        h as u64
-                    } else {
-                        log::error!("收到任务JobId 字段不存在{:?}", self);
-                        0
-                    }
-                }
-            }
+        match self.result.get(3).and_then(|diff| parse_hex(diff)) {
+            Some(diff) => diff,
             None => {
                 log::error!("收到任务JobId 字段不存在{:?}", self);
                 0
             }
-        };
-
-        job_diff
+        }
     }
 
     fn get_job_id(&self) -> Option<String> {
-        match self.result.get(0) {
-            Some(s) => Some(s.to_string()),
-            None => todo!(),
-        }
+        self.result.first().map(|s| s.to_string())
+    }
+
+    fn get_seed_hash(&self) -> Option<String> {
+        self.result.get(1).map(|s| s.to_string())
+    }
+
+    fn get_target(&self) -> Option<String> {
+        self.result.get(2).map(|s| s.to_string())
     }
 
     fn set_id(&mut self, id: u64) -> bool {
@@ -332,10 +326,15 @@ impl ServerRpc for ServerJobsWithHeight {
     }
 
     fn get_job_id(&self) -> Option<String> {
-        match self.result.get(0) {
-            Some(s) => Some(s.to_string()),
-            None => todo!(),
-        }
+        self.result.first().map(|s| s.to_string())
+    }
+
+    fn get_seed_hash(&self) -> Option<String> {
+        self.result.get(1).map(|s| s.to_string())
+    }
+
+    fn get_target(&self) -> Option<String> {
+        self.result.get(2).map(|s| s.to_string())
     }
 
     fn set_id(&mut self, id: u64) -> bool {
@@ -349,6 +348,99 @@ impl ServerRpc for ServerJobsWithHeight {
 }
 //币印 {"id":0,"jsonrpc":"2.0","result":["0x0d08e3f8adaf9b1cf365c3f380f1a0fa4b7dda99d12bb59d9ee8b10a1a1d8b91","0x1bccaca36bfde6e5a161cf470cbf74830d92e1013ee417c3e7c757acd34d8e08","0x000000007fffffffffffffffffffffffffffffffffffffffffffffffffffffff","00"], "height":13834471}
 
+// 矿池下发的任务。序列化时保持原格式
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ServerJob {
+    WithHeight(ServerJobsWithHeight),
+    Side(ServerSideJob),
+    Plain(Server),
+}
+
+macro_rules! each_job {
+    ($job:expr, $j:ident => $e:expr) => {
+        match $job {
+            ServerJob::WithHeight($j) => $e,
+            ServerJob::Side($j) => $e,
+            ServerJob::Plain($j) => $e,
+        }
+    };
+}
+
+impl ServerJob {
+    pub fn into_result(self) -> Vec<String> {
+        each_job!(self, j => j.result)
+    }
+
+    // 任务的区块高度。优先使用 height 字段，没有时取 result 第 4 项
+    pub fn height(&self) -> Option<u64> {
+        match self {
+            ServerJob::WithHeight(j) => Some(j.height),
+            ServerJob::Side(j) => j.result.get(3).and_then(|h| parse_hex(h)),
+            ServerJob::Plain(j) => j.result.get(3).and_then(|h| parse_hex(h)),
+        }
+    }
+}
+
+impl ServerRpc for ServerJob {
+    fn set_id(&mut self, id: u64) -> bool {
+        each_job!(self, j => j.set_id(id))
+    }
+
+    fn get_id(&mut self) -> u64 {
+        each_job!(self, j => j.get_id())
+    }
+
+    fn set_result(&mut self, res: Vec<std::string::String>) -> bool {
+        each_job!(self, j => j.set_result(res))
+    }
+
+    fn set_diff(&mut self, diff: String) -> bool {
+        each_job!(self, j => j.set_diff(diff))
+    }
+
+    fn get_diff(&self) -> u64 {
+        each_job!(self, j => j.get_diff())
+    }
+
+    fn get_job_id(&self) -> Option<String> {
+        each_job!(self, j => j.get_job_id())
+    }
+
+    fn get_seed_hash(&self) -> Option<String> {
+        each_job!(self, j => j.get_seed_hash())
+    }
+
+    fn get_target(&self) -> Option<String> {
+        each_job!(self, j => j.get_target())
+    }
+}
+
+// 矿池发来的一行
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerLine {
+    // 请求的返回结果
+    Result(ServerId1),
+    Job(ServerJob),
+    // 其他消息。由调用方决定原样转发或忽略
+    Other,
+}
+
+// 按 返回结果、带高度的任务、带 jsonrpc 的任务、普通任务 的顺序解析
+pub fn parse_server(buf: &str) -> ServerLine {
+    if let Ok(rpc) = serde_json::from_str::<ServerId1>(buf) {
+        ServerLine::Result(rpc)
+    } else if let Ok(job) = serde_json::from_str::<ServerJobsWithHeight>(buf) {
+        ServerLine::Job(ServerJob::WithHeight(job))
+    } else if let Ok(job) = serde_json::from_str::<ServerSideJob>(buf) {
+        ServerLine::Job(ServerJob::Side(job))
+    } else if let Ok(job) = serde_json::from_str::<Server>(buf) {
+        ServerLine::Job(ServerJob::Plain(job))
+    } else {
+        ServerLine::Other
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ServerId1 {
@@ -364,6 +456,14 @@ pub struct ServerId {
 }
 //{"id":4,"jsonrpc":"2.0","result":true}
 
+// 服务端主动通知 例如 {"id":null,"method":"client.reconnect","params":[]}
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerNotify {
+    pub id: Option<u64>,
+    pub method: String,
+    pub params: Vec<String>,
+}
+
 //{"id":197,"result":false,"error":[21,"Job not found (=stale)",null]}
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -408,3 +508,90 @@ pub fn handle_error_for_worker(worker_name: &String, buf: &[u8]) {
         log::warn!("矿机 {} Share Reject: {:?}", worker_name, buf);
     }
 }
+
+#[test]
+fn test_short_params() {
+    let mut rpc: ClientWithWorkerName =
+        serde_json::from_str(r#"{"id":1,"method":"eth_submitLogin","params":[],"worker":""}"#)
+            .unwrap();
+    assert_eq!(rpc.get_wallet(), None);
+    assert_eq!(rpc.get_job_id(), None);
+    assert_eq!(rpc.get_submit_hashrate(), 0);
+
+    let mut rpc: Client =
+        serde_json::from_str(r#"{"id":1,"method":"eth_submitWork","params":["0x1"]}"#).unwrap();
+    assert_eq!(rpc.get_wallet(), Some("0x1".into()));
+    assert_eq!(rpc.get_job_id(), None);
+
+    let job: Server = serde_json::from_str(r#"{"id":0,"result":[]}"#).unwrap();
+    assert_eq!(job.get_job_id(), None);
+    assert_eq!(job.get_diff(), 0);
+    let job: ServerJobsWithHeight =
+        serde_json::from_str(r#"{"id":0,"jsonrpc":"2.0","result":[],"height":1}"#).unwrap();
+    assert_eq!(job.get_job_id(), None);
+}
+
+#[test]
+fn test_submit_hashrate() {
+    let hashrate = |h: &str| Client {
+        id: 6,
+        method: "eth_submitHashrate".into(),
+        params: vec![h.into()],
+    }
+    .get_submit_hashrate();
+
+    assert_eq!(hashrate("0x5f5e100"), 100_000_000);
+    assert_eq!(hashrate("5f5e100"), 100_000_000);
+    assert_eq!(hashrate("0X5F5E100"), 100_000_000);
+    assert_eq!(hashrate(""), 0);
+    assert_eq!(hashrate("0"), 0);
+    assert_eq!(hashrate("0x"), 0);
+    assert_eq!(hashrate("中文"), 0);
+    assert_eq!(hashrate("0xffffffffffffffffffffffffffffffff"), 0);
+}
+
+#[test]
+fn test_job_diff() {
+    let mut job = ServerSideJob {
+        id: 0,
+        jsonrpc: "2.0".into(),
+        result: vec!["0x1".into(), "0x2".into(), "0x3".into()],
+    };
+    assert_eq!(job.get_diff(), 0);
+    assert!(job.set_diff("0xd3e9a7".into()));
+    assert_eq!(job.get_diff(), 13_887_911);
+    assert!(job.set_diff("d3e9a8".into()));
+    assert_eq!(job.get_diff(), 13_887_912);
+    assert_eq!(job.result.len(), 4);
+
+    job.result = vec![];
+    assert!(!job.set_diff("0x1".into()));
+    job.result = vec!["".into(), "".into(), "".into(), "é0x".into()];
+    assert_eq!(job.get_diff(), 0);
+}
+
+#[test]
+fn test_parse_server() {
+    let line = parse_server(r#"{"id":4,"jsonrpc":"2.0","result":true}"#);
+    assert_eq!(line, ServerLine::Result(ServerId1 { id: 4, result: true }));
+
+    let buf = r#"{"id":0,"jsonrpc":"2.0","result":["0x1","0x2","0x3","00"],"height":13834471}"#;
+    match parse_server(buf) {
+        ServerLine::Job(job @ ServerJob::WithHeight(_)) => {
+            assert_eq!(job.get_diff(), 13834471);
+            // 原样转发，不丢失 height 字段
+            assert_eq!(
+                serde_json::to_value(&job).unwrap(),
+                serde_json::from_str::<Value>(buf).unwrap()
+            );
+        }
+        line => panic!("{:?}", line),
+    }
+
+    let line = parse_server(r#"{"id":0,"jsonrpc":"2.0","result":["0x1","0x2","0x3","0xd3e9a7"]}"#);
+    assert!(matches!(line, ServerLine::Job(ServerJob::Side(_))));
+    let line = parse_server(r#"{"id":0,"result":["0x1","0x2","0x3","0xd3e9a7"]}"#);
+    assert!(matches!(line, ServerLine::Job(ServerJob::Plain(_))));
+    let line = parse_server(r#"{"id":1,"result":null,"error":"Invalid"}"#);
+    assert_eq!(line, ServerLine::Other);
+}
@@ -0,0 +1,305 @@
+use std::{collections::BTreeMap, net::IpAddr, sync::RwLock};
+
+use anyhow::{bail, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use super::Worker;
+use crate::{client::acl::Cidr, util::config::Settings};
+
+// 矿机别名规则。wallet worker ip 为匹配条件，为空时不限制。
+// worker 以 * 结尾时按前缀匹配，ip 可以是网段。alias location group 为空的不设置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorkerAlias {
+    #[serde(default)]
+    pub wallet: String,
+    #[serde(default)]
+    pub worker: String,
+    #[serde(default)]
+    pub ip: String,
+    #[serde(default)]
+    pub alias: String,
+    #[serde(default)]
+    pub location: String,
+    #[serde(default)]
+    pub group: String,
+}
+
+impl WorkerAlias {
+    // 匹配条件相同
+    fn same_target(&self, other: &WorkerAlias) -> bool {
+        self.wallet.eq_ignore_ascii_case(&other.wallet)
+            && self.worker == other.worker
+            && self.ip == other.ip
+    }
+
+    fn is_unlabeled(&self) -> bool {
+        self.alias.is_empty() && self.location.is_empty() && self.group.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    alias: WorkerAlias,
+    wallet: String,
+    ip: Option<Cidr>,
+}
+
+impl Rule {
+    fn new(alias: WorkerAlias) -> Result<Self> {
+        let ip = match alias.ip.as_str() {
+            "" => None,
+            ip => Some(ip.parse::<Cidr>()?),
+        };
+        Ok(Self {
+            wallet: alias.wallet.to_lowercase(),
+            alias,
+            ip,
+        })
+    }
+
+    fn matches(&self, w: &Worker) -> bool {
+        if !self.wallet.is_empty() && self.wallet != w.worker_wallet.to_lowercase() {
+            return false;
+        }
+        let name = &self.alias.worker;
+        let name_ok = match name.strip_suffix('*') {
+            Some(prefix) => w.worker_name.starts_with(prefix),
+            None => name.is_empty() || *name == w.worker_name,
+        };
+        if !name_ok {
+            return false;
+        }
+        match &self.ip {
+            Some(cidr) => match w.ip.parse::<IpAddr>() {
+                Ok(ip) => cidr.contains(&ip),
+                Err(_) => false,
+            },
+            None => true,
+        }
+    }
+}
+
+fn parse_rules(list: &[WorkerAlias]) -> Result<Vec<Rule>> {
+    list.iter().cloned().map(Rule::new).collect()
+}
+
+#[derive(Debug, Default)]
+struct Rules {
+    // 通过管理接口设置，优先于配置文件。重启后失效
+    runtime: Vec<Rule>,
+    config: Vec<Rule>,
+}
+
+// 矿机别名、位置及分组。按规则顺序匹配，每一项取第一个设置了该项的规则
+#[derive(Debug, Default)]
+pub struct Aliases {
+    rules: RwLock<Rules>,
+}
+
+impl Aliases {
+    pub fn new(config: &Settings) -> Result<Self> {
+        Ok(Self {
+            rules: RwLock::new(Rules {
+                runtime: Vec::new(),
+                config: parse_rules(&config.worker_aliases)?,
+            }),
+        })
+    }
+
+    // 重新加载配置文件中的规则。解析失败时保留原规则
+    pub fn reload(&self, config: &Settings) -> Result<()> {
+        let rules = parse_rules(&config.worker_aliases)?;
+        self.rules.write().unwrap().config = rules;
+        info!("✅ 矿机别名已重新加载");
+        Ok(())
+    }
+
+    // 设置管理接口的规则。匹配条件相同的规则被替换，alias location group 都为空时删除
+    pub fn set(&self, alias: WorkerAlias) -> Result<()> {
+        if alias.wallet.is_empty() && alias.worker.is_empty() && alias.ip.is_empty() {
+            bail!("请至少指定 wallet worker ip 中的一项");
+        }
+        let rule = Rule::new(alias)?;
+        let mut rules = self.rules.write().unwrap();
+        rules.runtime.retain(|r| !r.alias.same_target(&rule.alias));
+        if !rule.alias.is_unlabeled() {
+            rules.runtime.push(rule);
+        }
+        Ok(())
+    }
+
+    // (管理接口设置的规则, 配置文件的规则)
+    pub fn list(&self) -> (Vec<WorkerAlias>, Vec<WorkerAlias>) {
+        let rules = self.rules.read().unwrap();
+        let list = |rules: &[Rule]| rules.iter().map(|r| r.alias.clone()).collect();
+        (list(&rules.runtime), list(&rules.config))
+    }
+
+    pub fn label(&self, w: &mut Worker) {
+        let rules = self.rules.read().unwrap();
+        let matched: Vec<&WorkerAlias> = rules
+            .runtime
+            .iter()
+            .chain(rules.config.iter())
+            .filter(|r| r.matches(w))
+            .map(|r| &r.alias)
+            .collect();
+        let first = |field: fn(&WorkerAlias) -> &String| {
+            matched
+                .iter()
+                .map(|a| field(a))
+                .find(|s| !s.is_empty())
+                .cloned()
+                .unwrap_or_default()
+        };
+        w.alias = first(|a| &a.alias);
+        w.location = first(|a| &a.location);
+        w.group = first(|a| &a.group);
+    }
+
+    pub fn apply(&self, mut workers: Vec<Worker>) -> Vec<Worker> {
+        for w in &mut workers {
+            self.label(w);
+        }
+        workers
+    }
+}
+
+// 一个分组的汇总。group 为空的是未分组的矿机
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GroupStats {
+    pub group: String,
+    pub workers: u64,
+    pub online: u64,
+    pub hash: u64,
+    pub share_index: u64,
+    pub accept_index: u64,
+    pub invalid_index: u64,
+    pub local_reject_index: u64,
+    pub duplicate_index: u64,
+    pub stale_index: u64,
+    pub recent_disconnects: u64,
+}
+
+// 按分组汇总矿机统计
+pub fn groups(workers: &[Worker]) -> Vec<GroupStats> {
+    let mut groups: BTreeMap<&str, GroupStats> = BTreeMap::new();
+    for w in workers.iter().filter(|w| !w.worker.is_empty()) {
+        let g = groups.entry(&w.group).or_insert_with(|| GroupStats {
+            group: w.group.clone(),
+            ..Default::default()
+        });
+        g.workers += 1;
+        if w.online {
+            g.online += 1;
+            g.hash += w.hash;
+        }
+        g.share_index += w.share_index;
+        g.accept_index += w.accept_index;
+        g.invalid_index += w.invalid_index;
+        g.local_reject_index += w.local_reject_index;
+        g.duplicate_index += w.duplicate_index;
+        g.stale_index += w.stale_index;
+        g.recent_disconnects += w.recent_disconnects;
+    }
+    groups.into_values().collect()
+}
+
+#[cfg(test)]
+fn worker(wallet: &str, name: &str, ip: &str) -> Worker {
+    let mut w = Worker::new(
+        format!("{}.{}", wallet, name),
+        name.into(),
+        wallet.into(),
+        true,
+    );
+    w.ip = ip.into();
+    w
+}
+
+#[test]
+fn test_alias_label() {
+    let mut config = Settings::default();
+    config.worker_aliases = vec![
+        WorkerAlias {
+            ip: "10.0.1.0/24".into(),
+            location: "room1".into(),
+            group: "customer-a".into(),
+            ..Default::default()
+        },
+        WorkerAlias {
+            wallet: "0xAB".into(),
+            worker: "MacBook-Pro.local*".into(),
+            alias: "mac".into(),
+            group: "office".into(),
+            ..Default::default()
+        },
+    ];
+    let aliases = Aliases::new(&config).unwrap();
+
+    let mut w = worker("0xab", "MacBook-Pro.local.eth1.0", "10.0.1.7");
+    aliases.label(&mut w);
+    assert_eq!(
+        (w.alias.as_str(), w.location.as_str(), w.group.as_str()),
+        ("mac", "room1", "customer-a")
+    );
+
+    let mut w = worker("0xcd", "Default", "10.0.2.7");
+    aliases.label(&mut w);
+    assert_eq!(w.group, "");
+
+    // 管理接口的规则优先
+    aliases
+        .set(WorkerAlias {
+            wallet: "0xcd".into(),
+            worker: "Default".into(),
+            alias: "rig-07".into(),
+            group: "room2".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    aliases.label(&mut w);
+    assert_eq!((w.alias.as_str(), w.group.as_str()), ("rig-07", "room2"));
+    assert_eq!(aliases.list().0.len(), 1);
+
+    // 只给匹配条件时删除
+    aliases
+        .set(WorkerAlias {
+            wallet: "0xCD".into(),
+            worker: "Default".into(),
+            ..Default::default()
+        })
+        .unwrap();
+    assert!(aliases.list().0.is_empty());
+    assert!(aliases.set(WorkerAlias::default()).is_err());
+
+    config.worker_aliases[0].ip = "10.0.1.0/40".into();
+    assert!(aliases.reload(&config).is_err());
+    assert_eq!(aliases.list().1.len(), 2);
+}
+
+#[test]
+fn test_groups() {
+    let mut workers = vec![
+        worker("0xab", "rig1", "10.0.1.1"),
+        worker("0xab", "rig2", "10.0.1.2"),
+        worker("0xab", "rig3", "10.0.2.1"),
+    ];
+    workers[0].group = "room1".into();
+    workers[1].group = "room1".into();
+    workers[1].online = false;
+    for w in &mut workers {
+        w.hash = 100;
+        w.share_index = 2;
+    }
+
+    let groups = groups(&workers);
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].group, "");
+    assert_eq!(groups[1].group, "room1");
+    assert_eq!(groups[1].workers, 2);
+    assert_eq!(groups[1].online, 1);
+    assert_eq!(groups[1].hash, 100);
+    assert_eq!(groups[1].share_index, 4);
+}
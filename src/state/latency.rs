@@ -0,0 +1,106 @@
+use std::{collections::VecDeque, fmt, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+// 每项耗时保留的最近样本数
+pub const SAMPLES: usize = 200;
+
+// 最近的耗时样本(毫秒)。超出 SAMPLES 时丢弃最早的
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Latency {
+    samples: VecDeque<u64>,
+}
+
+impl Latency {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, d: Duration) {
+        self.push(d.as_millis() as u64);
+    }
+
+    fn push(&mut self, ms: u64) {
+        if self.samples.len() == SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(ms);
+    }
+
+    // 汇总另一个会话的样本
+    pub fn merge(&mut self, other: &Latency) {
+        for &ms in &other.samples {
+            self.push(ms);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    // 没有样本时返回 None
+    pub fn percentiles(&self) -> Option<Percentiles> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<u64> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        // 最近秩法
+        let at = |p: usize| sorted[(sorted.len() * p).div_ceil(100).max(1) - 1];
+        Some(Percentiles {
+            samples: sorted.len() as u64,
+            p50: at(50),
+            p90: at(90),
+            p99: at(99),
+        })
+    }
+}
+
+// 耗时分位数(毫秒)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub samples: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+}
+
+impl fmt::Display for Percentiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.p50, self.p90, self.p99)
+    }
+}
+
+// 表格中显示 p50/p90/p99。没有样本时为空
+pub fn brief(p: &Option<Percentiles>) -> String {
+    p.map(|p| p.to_string()).unwrap_or_default()
+}
+
+#[test]
+fn test_percentiles() {
+    let mut l = Latency::new();
+    assert_eq!(l.percentiles(), None);
+
+    for ms in 1..=100 {
+        l.record(Duration::from_millis(ms));
+    }
+    let p = l.percentiles().unwrap();
+    assert_eq!((p.samples, p.p50, p.p90, p.p99), (100, 50, 90, 99));
+
+    l = Latency::new();
+    l.record(Duration::from_millis(7));
+    assert_eq!(brief(&l.percentiles()), "7/7/7");
+
+    // 只保留最近的样本
+    let mut other = Latency::new();
+    for _ in 0..SAMPLES {
+        other.record(Duration::from_millis(500));
+    }
+    l.merge(&other);
+    assert_eq!(l.len(), SAMPLES);
+    assert_eq!(l.percentiles().unwrap().p50, 500);
+}
@@ -1,6 +1,15 @@
+pub mod alias;
+pub mod latency;
+pub mod registry;
+
 use std::time::Instant;
 
-use log::info;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use self::latency::{Latency, Percentiles};
+use crate::{client::error::SessionError, protocol::ethash::ShareError, util::logger::event};
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Worker {
     pub worker: String,
@@ -14,6 +23,38 @@ pub struct Worker {
     pub share_index: u64,
     pub accept_index: u64,
     pub invalid_index: u64,
+    // 本地验证不通过的份额。未提交到矿池
+    pub local_reject_index: u64,
+    // 重复提交的份额。未提交到矿池
+    pub duplicate_index: u64,
+    // 提交时任务已属于旧区块的份额。含本地丢弃的
+    pub stale_index: u64,
+    // 落后太多区块、本地丢弃的份额
+    pub stale_drop_index: u64,
+    // 最近一个任务的份额难度。未知时为 0
+    pub share_diff: u64,
+    // 会话结束的原因。会话进行中为 None
+    pub disconnect: Option<SessionError>,
+    // 上报状态的会话 id。汇总后的统计为 0
+    pub session: u64,
+    // 累计会话数
+    pub sessions: u64,
+    // 在线会话数。大于 1 时多台矿机使用了同一个矿机名
+    pub online_sessions: u64,
+    // 最近一小时矿机断开的次数。不含代理主动断开
+    pub recent_disconnects: u64,
+    // 矿机的 IP
+    pub ip: String,
+    // 由矿机别名规则设置。未匹配时为空
+    pub alias: String,
+    pub location: String,
+    pub group: String,
+    // 矿池任务到达至写给矿机的耗时
+    pub job_delay: Latency,
+    // 提交份额至矿池返回结果的耗时
+    pub submit_rtt: Latency,
+    // 任务发给矿机至矿机提交第一个份额的耗时
+    pub first_share: Latency,
 }
 
 impl Worker {
@@ -29,7 +70,24 @@ impl Worker {
             share_index: 0,
             accept_index: 0,
             invalid_index: 0,
+            local_reject_index: 0,
+            duplicate_index: 0,
+            stale_index: 0,
+            stale_drop_index: 0,
+            share_diff: 0,
             rpc_id: 0,
+            disconnect: None,
+            session: 0,
+            sessions: 0,
+            online_sessions: 0,
+            recent_disconnects: 0,
+            ip: String::new(),
+            alias: String::new(),
+            location: String::new(),
+            group: String::new(),
+            job_delay: Latency::new(),
+            submit_rtt: Latency::new(),
+            first_share: Latency::new(),
         }
     }
 
@@ -45,7 +103,24 @@ impl Worker {
             share_index: 0,
             accept_index: 0,
             invalid_index: 0,
+            local_reject_index: 0,
+            duplicate_index: 0,
+            stale_index: 0,
+            stale_drop_index: 0,
+            share_diff: 0,
             rpc_id: 0,
+            disconnect: None,
+            session: 0,
+            sessions: 0,
+            online_sessions: 0,
+            recent_disconnects: 0,
+            ip: String::new(),
+            alias: String::new(),
+            location: String::new(),
+            group: String::new(),
+            job_delay: Latency::new(),
+            submit_rtt: Latency::new(),
+            first_share: Latency::new(),
         }
     }
 
@@ -56,10 +131,19 @@ impl Worker {
         self.worker_wallet = worker_wallet;
     }
 
+    // 登录成功。份额计数跟随会话，跨会话的累计由 Registry 汇总
     pub fn logind(&mut self) {
-        info!("👍  Worker {} 登录成功", self.worker);
+        info!(target: event::LOGIN, "👍  Worker {} 登录成功", self.worker);
         self.online = true;
-        self.clear_state();
+    }
+
+    // 跨会话识别矿机的键。钱包地址不区分大小写
+    pub fn identity(&self) -> String {
+        format!(
+            "{}.{}",
+            self.worker_wallet.to_ascii_lowercase(),
+            self.worker_name
+        )
     }
 
     // 下线
@@ -91,13 +175,14 @@ impl Worker {
         self.last_subwork_time = Instant::now();
 
         self.share_index += 1;
-        info!("✅ Worker {} Share #{}", self.worker, self.share_index);
+        info!(target: event::SHARE, "✅ Worker {} Share #{}", self.worker, self.share_index);
     }
 
     // 接受份额
     pub fn share_accept(&mut self) {
         self.accept_index += 1;
         info!(
+            target: event::ACCEPT,
             "👍 Worker {} Share Accept #{}",
             self.worker, self.share_index
         );
@@ -106,7 +191,67 @@ impl Worker {
     // 拒绝的份额
     pub fn share_reject(&mut self) {
         self.invalid_index += 1;
-        info!("😭 Worker {} Reject! {}", self.worker, self.accept_index);
+        info!(target: event::REJECT, "😭 Worker {} Reject! {}", self.worker, self.accept_index);
+    }
+
+    // 本地验证不通过的份额。哈希错误多为显卡故障
+    pub fn share_local_reject(&mut self, reason: ShareError) {
+        self.local_reject_index += 1;
+        warn!(
+            target: event::REJECT,
+            "😭 Worker {} 本地拒绝 [{}] {} #{}",
+            self.worker,
+            reason.as_str(),
+            reason,
+            self.local_reject_index
+        );
+        if reason == ShareError::MixMismatch && self.local_reject_index.is_multiple_of(10) {
+            warn!(
+                "⚠️ 矿机 {} 已有 {} 个本地验证不通过的份额，可能存在故障显卡",
+                self.worker, self.local_reject_index
+            );
+        }
+    }
+
+    // 重复提交的份额。矿机反复提交同一份额会被矿池判为作弊
+    pub fn share_duplicate(&mut self) {
+        self.duplicate_index += 1;
+        warn!(
+            target: event::REJECT,
+            "😭 Worker {} 重复份额 #{}",
+            self.worker,
+            self.duplicate_index
+        );
+    }
+
+    // 过期份额。dropped 为本地丢弃，不提交到矿池
+    pub fn share_stale(&mut self, blocks: u64, dropped: bool) {
+        self.stale_index += 1;
+        if dropped {
+            self.stale_drop_index += 1;
+            warn!(
+                target: event::REJECT,
+                "😭 Worker {} 丢弃过期份额 落后 {} 个区块",
+                self.worker,
+                blocks
+            );
+        } else {
+            info!(
+                target: event::SHARE,
+                "⏰ Worker {} 过期份额 落后 {} 个区块",
+                self.worker,
+                blocks
+            );
+        }
+    }
+
+    // 过期份额占提交份额的比例
+    pub fn stale_rate(&self) -> f64 {
+        let total = self.share_index + self.stale_drop_index;
+        if total == 0 {
+            return 0.0;
+        }
+        self.stale_index as f64 / total as f64
     }
 
     pub fn submit_hashrate<T>(&mut self, rpc: &T) -> bool
@@ -116,6 +261,102 @@ impl Worker {
         self.hash = rpc.get_submit_hashrate();
         true
     }
+
+    // 已提交但矿池尚未返回结果的份额数
+    pub fn pending_shares(&self) -> u64 {
+        self.share_index
+            .saturating_sub(self.accept_index + self.invalid_index)
+    }
+}
+
+// 矿机状态快照。停机时写入文件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerSnapshot {
+    pub worker: String,
+    pub worker_name: String,
+    pub worker_wallet: String,
+    pub online: bool,
+    pub hash: u64,
+    pub share_index: u64,
+    pub accept_index: u64,
+    pub invalid_index: u64,
+    #[serde(default)]
+    pub local_reject_index: u64,
+    #[serde(default)]
+    pub duplicate_index: u64,
+    #[serde(default)]
+    pub stale_index: u64,
+    #[serde(default)]
+    pub stale_drop_index: u64,
+    pub online_secs: u64,
+    pub last_submit_secs: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disconnect: Option<SessionError>,
+    #[serde(default)]
+    pub sessions: u64,
+    #[serde(default)]
+    pub online_sessions: u64,
+    #[serde(default)]
+    pub recent_disconnects: u64,
+    // 最近一小时异常断开的时间(Unix 秒)。重启后继续统计断开频率
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disconnect_times: Vec<u64>,
+    // 最近一次上报的时间(Unix 秒)。离线超过 worker_expire_days 的矿机在重启后也会被移除
+    #[serde(default)]
+    pub last_seen: u64,
+    #[serde(default)]
+    pub ip: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub alias: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub location: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub group: String,
+    // 重连次数。累计会话数减一
+    #[serde(default)]
+    pub reconnects: u64,
+    // 以下为最近样本的耗时分位数。重启后重新统计
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_delay: Option<Percentiles>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub submit_rtt: Option<Percentiles>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_share: Option<Percentiles>,
+}
+
+impl From<&Worker> for WorkerSnapshot {
+    fn from(w: &Worker) -> Self {
+        Self {
+            worker: w.worker.clone(),
+            worker_name: w.worker_name.clone(),
+            worker_wallet: w.worker_wallet.clone(),
+            online: w.online,
+            hash: w.hash,
+            share_index: w.share_index,
+            accept_index: w.accept_index,
+            invalid_index: w.invalid_index,
+            local_reject_index: w.local_reject_index,
+            duplicate_index: w.duplicate_index,
+            stale_index: w.stale_index,
+            stale_drop_index: w.stale_drop_index,
+            online_secs: w.login_time.elapsed().as_secs(),
+            last_submit_secs: w.last_subwork_time.elapsed().as_secs(),
+            disconnect: w.disconnect,
+            sessions: w.sessions,
+            online_sessions: w.online_sessions,
+            recent_disconnects: w.recent_disconnects,
+            disconnect_times: Vec::new(),
+            last_seen: 0,
+            ip: w.ip.clone(),
+            alias: w.alias.clone(),
+            location: w.location.clone(),
+            group: w.group.clone(),
+            reconnects: w.sessions.saturating_sub(1),
+            job_delay: w.job_delay.percentiles(),
+            submit_rtt: w.submit_rtt.percentiles(),
+            first_share: w.first_share.percentiles(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -268,3 +509,14 @@ fn test_share_reject() {
     assert_eq!(w.accept_index, 0);
     assert_eq!(w.invalid_index, 1);
 }
+
+#[test]
+fn test_pending_shares() {
+    let mut w = Worker::default();
+    w.share_index_add();
+    w.share_index_add();
+    w.share_accept();
+    assert_eq!(w.pending_shares(), 1);
+    w.share_reject();
+    assert_eq!(w.pending_shares(), 0);
+}
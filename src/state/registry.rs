@@ -0,0 +1,469 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use log::warn;
+
+use super::{Worker, WorkerSnapshot};
+
+// 统计断开频率的时间窗口
+pub const DISCONNECT_WINDOW: u64 = 3600;
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn ago(secs: u64) -> Instant {
+    let now = Instant::now();
+    now.checked_sub(Duration::from_secs(secs)).unwrap_or(now)
+}
+
+// 一台矿机跨会话的记录
+#[derive(Debug, Clone)]
+struct Record {
+    // 已结束会话的累计统计及最近一次的矿机信息
+    done: Worker,
+    // 进行中的会话。会话 id -> 最近一次上报
+    live: BTreeMap<u64, Worker>,
+    sessions: u64,
+    // 最近的断开时间(Unix 秒)
+    disconnects: VecDeque<u64>,
+    // 最近一次上报的时间(Unix 秒)
+    seen: u64,
+}
+
+impl Record {
+    fn new(w: &Worker) -> Self {
+        Self {
+            done: Worker::new(
+                w.worker.clone(),
+                w.worker_name.clone(),
+                w.worker_wallet.clone(),
+                false,
+            ),
+            live: BTreeMap::new(),
+            sessions: 0,
+            disconnects: VecDeque::new(),
+            seen: unix_now(),
+        }
+    }
+
+    // 停机时保存的统计
+    fn restore(s: WorkerSnapshot, now: u64) -> Self {
+        let mut w = Worker::new(s.worker, s.worker_name, s.worker_wallet, false);
+        w.hash = s.hash;
+        w.share_index = s.share_index;
+        w.accept_index = s.accept_index;
+        w.invalid_index = s.invalid_index;
+        w.local_reject_index = s.local_reject_index;
+        w.duplicate_index = s.duplicate_index;
+        w.stale_index = s.stale_index;
+        w.stale_drop_index = s.stale_drop_index;
+        w.login_time = ago(s.online_secs);
+        w.last_subwork_time = ago(s.last_submit_secs);
+        w.disconnect = s.disconnect;
+        w.ip = s.ip;
+
+        let mut record = Record::new(&w);
+        record.done = w;
+        record.sessions = s.sessions;
+        record.disconnects = s.disconnect_times.into_iter().collect();
+        // 旧版本的统计文件没有 last_seen，按重启时间计算
+        if s.last_seen > 0 {
+            record.seen = s.last_seen;
+        }
+        record.prune(now);
+        record
+    }
+
+    // 扣除 base 中已经计入的部分
+    fn subtract(&mut self, base: &WorkerSnapshot) {
+        let done = &mut self.done;
+        done.share_index = done.share_index.saturating_sub(base.share_index);
+        done.accept_index = done.accept_index.saturating_sub(base.accept_index);
+        done.invalid_index = done.invalid_index.saturating_sub(base.invalid_index);
+        done.local_reject_index = done
+            .local_reject_index
+            .saturating_sub(base.local_reject_index);
+        done.duplicate_index = done.duplicate_index.saturating_sub(base.duplicate_index);
+        done.stale_index = done.stale_index.saturating_sub(base.stale_index);
+        done.stale_drop_index = done.stale_drop_index.saturating_sub(base.stale_drop_index);
+        self.sessions = self.sessions.saturating_sub(base.sessions);
+        self.disconnects
+            .retain(|t| !base.disconnect_times.contains(t));
+    }
+
+    fn prune(&mut self, now: u64) {
+        while let Some(&t) = self.disconnects.front() {
+            if t + DISCONNECT_WINDOW > now {
+                break;
+            }
+            self.disconnects.pop_front();
+        }
+    }
+
+    // 汇总已结束和进行中的会话
+    fn merged(&self) -> Worker {
+        let mut w = self.done.clone();
+        w.session = 0;
+        w.online = !self.live.is_empty();
+        if w.online {
+            w.hash = 0;
+            w.disconnect = None;
+        }
+        for (i, s) in self.live.values().enumerate() {
+            add_counters(&mut w, s);
+            w.hash += s.hash;
+            w.share_diff = s.share_diff;
+            w.ip = s.ip.clone();
+            if i == 0 || s.login_time < w.login_time {
+                w.login_time = s.login_time;
+            }
+            if s.last_subwork_time > w.last_subwork_time {
+                w.last_subwork_time = s.last_subwork_time;
+            }
+        }
+        w.sessions = self.sessions;
+        w.online_sessions = self.live.len() as u64;
+        let since = unix_now().saturating_sub(DISCONNECT_WINDOW);
+        w.recent_disconnects = self.disconnects.iter().filter(|&&t| t > since).count() as u64;
+        w
+    }
+}
+
+fn add_counters(total: &mut Worker, w: &Worker) {
+    total.share_index += w.share_index;
+    total.accept_index += w.accept_index;
+    total.invalid_index += w.invalid_index;
+    total.local_reject_index += w.local_reject_index;
+    total.duplicate_index += w.duplicate_index;
+    total.stale_index += w.stale_index;
+    total.stale_drop_index += w.stale_drop_index;
+    total.job_delay.merge(&w.job_delay);
+    total.submit_rtt.merge(&w.submit_rtt);
+    total.first_share.merge(&w.first_share);
+}
+
+// 矿机统计。按 Worker::identity 汇总各会话上报的状态，矿机重连后统计继续累计
+#[derive(Debug, Default)]
+pub struct Registry {
+    workers: HashMap<String, Record>,
+    // 启动时读取的统计。合并平滑升级时旧进程交接的统计时扣除这部分
+    baseline: HashMap<String, WorkerSnapshot>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 读取停机时保存的矿机统计。文件不存在时返回空的统计
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+        let snapshots: Vec<WorkerSnapshot> = serde_json::from_slice(&std::fs::read(path)?)?;
+        Ok(Self::restore(snapshots))
+    }
+
+    pub fn restore(snapshots: Vec<WorkerSnapshot>) -> Self {
+        let mut registry = Self::new();
+        let now = unix_now();
+        for s in snapshots {
+            let record = Record::restore(s.clone(), now);
+            let identity = record.done.identity();
+            registry.baseline.insert(identity.clone(), s);
+            registry.workers.insert(identity, record);
+        }
+        registry
+    }
+
+    // 合并平滑升级时旧进程停机前交接的统计。旧进程的统计包含本进程启动时读取的部分，
+    // 只累计之后的增量
+    pub fn merge(&mut self, snapshots: Vec<WorkerSnapshot>) {
+        let now = unix_now();
+        for s in snapshots {
+            let mut old = Record::restore(s, now);
+            let identity = old.done.identity();
+            if let Some(base) = self.baseline.get(&identity) {
+                old.subtract(base);
+            }
+            let record = match self.workers.get_mut(&identity) {
+                Some(record) => record,
+                None => {
+                    self.workers.insert(identity, old);
+                    continue;
+                }
+            };
+
+            add_counters(&mut record.done, &old.done);
+            record.sessions += old.sessions;
+            let mut disconnects: Vec<u64> = record.disconnects.drain(..).collect();
+            disconnects.extend(old.disconnects);
+            disconnects.sort_unstable();
+            record.disconnects = disconnects.into_iter().collect();
+            record.prune(now);
+            // 旧进程的会话结束得更晚时使用它的最后状态
+            if old.seen > record.seen && record.live.is_empty() {
+                record.seen = old.seen;
+                record.done.hash = old.done.hash;
+                record.done.ip = old.done.ip;
+                record.done.disconnect = old.done.disconnect;
+                record.done.last_subwork_time = old.done.last_subwork_time;
+            }
+        }
+        self.baseline.clear();
+    }
+
+    // 会话上报的状态。带断开原因的是会话的最后一次上报
+    pub fn update(&mut self, w: Worker) {
+        if w.worker.is_empty() {
+            return;
+        }
+        let record = self
+            .workers
+            .entry(w.identity())
+            .or_insert_with(|| Record::new(&w));
+        record.seen = unix_now();
+        if !record.live.contains_key(&w.session) {
+            record.sessions += 1;
+            if !record.live.is_empty() && w.disconnect.is_none() {
+                warn!(
+                    "⚠️ 矿机 {} 同时有 {} 个连接 可能多台矿机使用了同一个矿机名",
+                    w.worker,
+                    record.live.len() + 1
+                );
+            }
+        }
+
+        let reason = match w.disconnect {
+            Some(reason) => reason,
+            None => {
+                record.live.insert(w.session, w);
+                return;
+            }
+        };
+        record.live.remove(&w.session);
+        let now = unix_now();
+        if !reason.by_proxy() {
+            record.disconnects.push_back(now);
+        }
+        record.prune(now);
+
+        let done = &mut record.done;
+        add_counters(done, &w);
+        done.worker = w.worker;
+        done.worker_name = w.worker_name;
+        done.worker_wallet = w.worker_wallet;
+        done.hash = w.hash;
+        done.share_diff = w.share_diff;
+        done.ip = w.ip;
+        done.login_time = w.login_time;
+        if w.last_subwork_time > done.last_subwork_time {
+            done.last_subwork_time = w.last_subwork_time;
+        }
+        done.disconnect = Some(reason);
+    }
+
+    pub fn get(&self, identity: &str) -> Option<Worker> {
+        self.workers.get(identity).map(Record::merged)
+    }
+
+    pub fn workers(&self) -> Vec<Worker> {
+        self.workers.values().map(Record::merged).collect()
+    }
+
+    // 停机时保存。进行中的会话按已结束汇总
+    pub fn snapshots(&self) -> Vec<WorkerSnapshot> {
+        self.workers
+            .values()
+            .map(|record| {
+                let mut s = WorkerSnapshot::from(&record.merged());
+                s.disconnect_times = record.disconnects.iter().copied().collect();
+                s.last_seen = record.seen;
+                s
+            })
+            .collect()
+    }
+
+    // 移除离线超过 max_age 秒的矿机。矿机名为 IP 或频繁更换名字时统计不会无限增长。
+    // max_age 为 0 时不移除。返回移除的矿机数
+    pub fn expire(&mut self, max_age: u64) -> usize {
+        if max_age == 0 {
+            return 0;
+        }
+        let before = self.workers.len();
+        let now = unix_now();
+        self.workers
+            .retain(|_, record| !record.live.is_empty() || record.seen + max_age > now);
+        before - self.workers.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+}
+
+#[cfg(test)]
+fn report(
+    session: u64,
+    wallet: &str,
+    shares: u64,
+    disconnect: Option<super::SessionError>,
+) -> Worker {
+    let mut w = Worker::new(
+        format!("{}.rig1", wallet),
+        "rig1".into(),
+        wallet.into(),
+        disconnect.is_none(),
+    );
+    w.session = session;
+    w.share_index = shares;
+    w.accept_index = shares;
+    w.hash = 100;
+    w.disconnect = disconnect;
+    w
+}
+
+#[test]
+fn test_registry_reconnect() {
+    use super::SessionError;
+
+    let mut registry = Registry::new();
+    registry.update(report(1, "0xAB", 3, None));
+    registry.update(report(1, "0xAB", 5, Some(SessionError::MinerTimeout)));
+    // 重连后钱包地址大小写不同也是同一台矿机
+    registry.update(report(2, "0xab", 2, None));
+    assert_eq!(registry.len(), 1);
+
+    let w = registry.get("0xab.rig1").unwrap();
+    assert!(w.online);
+    assert_eq!(w.share_index, 7);
+    assert_eq!(w.accept_index, 7);
+    assert_eq!(w.hash, 100);
+    assert_eq!(w.sessions, 2);
+    assert_eq!(w.online_sessions, 1);
+    assert_eq!(w.recent_disconnects, 1);
+    assert_eq!(w.disconnect, None);
+
+    // 同名矿机同时在线。耗时样本合并各会话
+    let mut r = report(3, "0xab", 1, None);
+    r.submit_rtt.record(Duration::from_millis(40));
+    registry.update(r);
+    let w = registry.get("0xab.rig1").unwrap();
+    assert_eq!(w.online_sessions, 2);
+    assert_eq!(w.submit_rtt.percentiles().unwrap().p50, 40);
+    assert_eq!(w.hash, 200);
+    assert_eq!(w.share_index, 8);
+
+    // 代理主动断开不计入断开频率
+    registry.update(report(2, "0xab", 2, Some(SessionError::Drained)));
+    let mut r = report(3, "0xab", 1, Some(SessionError::Kicked));
+    r.submit_rtt.record(Duration::from_millis(40));
+    registry.update(r);
+    let w = registry.get("0xab.rig1").unwrap();
+    assert!(!w.online);
+    assert_eq!(w.share_index, 8);
+    assert_eq!(w.sessions, 3);
+    assert_eq!(w.recent_disconnects, 1);
+    assert_eq!(w.disconnect, Some(SessionError::Kicked));
+    assert_eq!(w.submit_rtt.len(), 1);
+    assert_eq!(WorkerSnapshot::from(&w).reconnects, 2);
+
+    // 登录前的上报不计入
+    registry.update(Worker::default());
+    assert_eq!(registry.len(), 1);
+}
+
+#[test]
+fn test_registry_restore() {
+    use super::SessionError;
+
+    let mut registry = Registry::new();
+    registry.update(report(1, "0xab", 4, Some(SessionError::MinerClosed)));
+    registry.update(report(2, "0xab", 1, None));
+    let snapshots = registry.snapshots();
+    assert_eq!(snapshots[0].disconnect_times.len(), 1);
+
+    let json = serde_json::to_vec(&snapshots).unwrap();
+    let mut registry = Registry::restore(serde_json::from_slice(&json).unwrap());
+    let w = registry.get("0xab.rig1").unwrap();
+    assert!(!w.online);
+    assert_eq!(w.share_index, 5);
+    assert_eq!(w.sessions, 2);
+    assert_eq!(w.recent_disconnects, 1);
+
+    registry.update(report(1, "0xab", 2, None));
+    let w = registry.get("0xab.rig1").unwrap();
+    assert_eq!(w.share_index, 7);
+    assert_eq!(w.sessions, 3);
+
+    // 超出时间窗口的断开不再计入
+    let mut snapshots = registry.snapshots();
+    snapshots[0].disconnect_times = vec![unix_now() - DISCONNECT_WINDOW - 1];
+    let registry = Registry::restore(snapshots);
+    assert_eq!(registry.get("0xab.rig1").unwrap().recent_disconnects, 0);
+}
+
+#[test]
+fn test_registry_expire() {
+    use super::SessionError;
+
+    let mut registry = Registry::new();
+    registry.update(report(1, "0xab", 1, Some(SessionError::MinerClosed)));
+    registry.update(report(2, "0xcd", 1, None));
+    assert_eq!(registry.expire(3600), 0);
+
+    // 离线超过期限的矿机被移除，在线的保留
+    let mut snapshots = registry.snapshots();
+    for s in snapshots.iter_mut() {
+        s.last_seen = unix_now() - 7200;
+    }
+    let mut registry = Registry::restore(snapshots);
+    registry.update(report(3, "0xcd", 1, None));
+    assert_eq!(registry.expire(0), 0);
+    assert_eq!(registry.expire(3600), 1);
+    assert!(registry.get("0xab.rig1").is_none());
+    assert!(registry.get("0xcd.rig1").is_some());
+}
+
+#[test]
+fn test_registry_upgrade_handoff() {
+    use super::SessionError;
+
+    // 旧进程启动新进程前保存统计
+    let mut old = Registry::new();
+    old.update(report(1, "0xab", 4, Some(SessionError::MinerClosed)));
+    old.update(report(2, "0xab", 1, None));
+    let mut new = Registry::restore(old.snapshots());
+
+    // 之后旧进程的会话继续提交，新进程接受重连的矿机
+    old.update(report(2, "0xab", 3, Some(SessionError::Drained)));
+    new.update(report(1, "0xab", 2, Some(SessionError::MinerClosed)));
+    new.update(report(1, "0xcd", 6, None));
+
+    // 旧进程停机时交接，启动前读取的部分不重复累计
+    new.merge(old.snapshots());
+    let w = new.get("0xab.rig1").unwrap();
+    assert_eq!(w.share_index, 4 + 3 + 2);
+    assert_eq!(w.sessions, 3);
+    assert_eq!(w.recent_disconnects, 2);
+    assert_eq!(new.get("0xcd.rig1").unwrap().share_index, 6);
+
+    // 只有旧进程见过的矿机直接加入
+    let mut old = Registry::new();
+    old.update(report(1, "0xef", 5, Some(SessionError::MinerClosed)));
+    new.merge(old.snapshots());
+    assert_eq!(new.get("0xef.rig1").unwrap().share_index, 5);
+    assert_eq!(new.len(), 3);
+}
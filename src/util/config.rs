@@ -3,16 +3,32 @@ use std::env;
 use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
 
-use super::get_develop_fee;
+use super::{get_develop_fee, rotate::Retention};
+use crate::{
+    client::{auth::AuthToken, naming::NameRule},
+    state::alias::WorkerAlias,
+};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     pub name: String,
     pub log_level: u32,
     pub log_path: String,
-    pub ssl_port: u32,
-    pub tcp_port: u32,
-    pub encrypt_port: u32,
+    #[serde(default)]
+    pub log_format: String,
+    #[serde(default = "default_log_max_size")]
+    pub log_max_size: u64,
+    #[serde(default = "default_log_max_days")]
+    pub log_max_days: u64,
+    #[serde(default = "default_log_max_files")]
+    pub log_max_files: usize,
+    #[serde(default = "default_true")]
+    pub log_compress: bool,
+    #[serde(default = "default_true")]
+    pub log_events: bool,
+    pub ssl_port: u16,
+    pub tcp_port: u16,
+    pub encrypt_port: u16,
     pub pool_ssl_address: Vec<String>,
     pub pool_tcp_address: Vec<String>,
     pub share_tcp_address: Vec<String>,
@@ -25,6 +41,164 @@ pub struct Settings {
     pub p12_pass: String,
     pub key: String,
     pub iv: String,
+    #[serde(default)]
+    pub tcp_allow: Vec<String>,
+    #[serde(default)]
+    pub tcp_deny: Vec<String>,
+    #[serde(default)]
+    pub ssl_allow: Vec<String>,
+    #[serde(default)]
+    pub ssl_deny: Vec<String>,
+    #[serde(default)]
+    pub encrypt_allow: Vec<String>,
+    #[serde(default)]
+    pub encrypt_deny: Vec<String>,
+    #[serde(default)]
+    pub ban_secs: u64,
+    #[serde(default = "default_ban_threshold")]
+    pub ban_threshold: u32,
+    #[serde(default)]
+    pub auth_password: String,
+    #[serde(default)]
+    pub auth_tokens: Vec<AuthToken>,
+    #[serde(default)]
+    pub auth_wallets: Vec<String>,
+    #[serde(default)]
+    pub worker_aliases: Vec<WorkerAlias>,
+    #[serde(default)]
+    pub worker_name_rules: Vec<NameRule>,
+    #[serde(default)]
+    pub tcp_proxy_protocol: bool,
+    #[serde(default)]
+    pub ssl_proxy_protocol: bool,
+    #[serde(default)]
+    pub encrypt_proxy_protocol: bool,
+    #[serde(default = "default_proxy_protocol_from")]
+    pub proxy_protocol_from: Vec<String>,
+    #[serde(default)]
+    pub pool_proxy_protocol: u32,
+    #[serde(default)]
+    pub pool_upstream_proxy: String,
+    #[serde(default)]
+    pub share_upstream_proxy: String,
+    #[serde(default)]
+    pub develop_upstream_proxy: String,
+    #[serde(default)]
+    pub tcp_bind: Vec<String>,
+    #[serde(default)]
+    pub ssl_bind: Vec<String>,
+    #[serde(default)]
+    pub encrypt_bind: Vec<String>,
+    #[serde(default)]
+    pub source_address: String,
+    #[serde(default = "default_drain_timeout")]
+    pub drain_timeout: u64,
+    #[serde(default)]
+    pub state_file: String,
+    #[serde(default = "default_worker_expire_days")]
+    pub worker_expire_days: u64,
+    #[serde(default = "default_upgrade_timeout")]
+    pub upgrade_timeout: u64,
+    #[serde(default)]
+    pub pid_file: String,
+    #[serde(default)]
+    pub record_path: String,
+    #[serde(default)]
+    pub record_allow: Vec<String>,
+    #[serde(default)]
+    pub record_redact: bool,
+    #[serde(default)]
+    pub verify_shares: bool,
+    #[serde(default = "default_verify_epochs")]
+    pub verify_epochs: usize,
+    #[serde(default)]
+    pub stale_drop_blocks: u64,
+    #[serde(default)]
+    pub alert_webhook: String,
+    #[serde(default)]
+    pub alert_command: String,
+    #[serde(default)]
+    pub alert_file: String,
+    #[serde(default = "default_alert_interval_secs")]
+    pub alert_interval_secs: u64,
+    #[serde(default = "default_alert_debounce")]
+    pub alert_debounce: u32,
+    #[serde(default = "default_alert_offline_secs")]
+    pub alert_offline_secs: u64,
+    #[serde(default = "default_alert_hashrate_percent")]
+    pub alert_hashrate_percent: u64,
+    #[serde(default = "default_alert_reject_percent")]
+    pub alert_reject_percent: u64,
+    #[serde(default = "default_alert_window_secs")]
+    pub alert_window_secs: u64,
+    #[serde(default = "default_true")]
+    pub alert_pool: bool,
+    #[serde(default)]
+    pub admin_socket: String,
+}
+
+fn default_ban_threshold() -> u32 {
+    3
+}
+
+fn default_proxy_protocol_from() -> Vec<String> {
+    vec!["127.0.0.1".into(), "::1".into()]
+}
+
+fn default_drain_timeout() -> u64 {
+    30
+}
+
+fn default_upgrade_timeout() -> u64 {
+    3600
+}
+
+fn default_worker_expire_days() -> u64 {
+    7
+}
+
+fn default_verify_epochs() -> usize {
+    2
+}
+
+fn default_log_max_size() -> u64 {
+    100
+}
+
+fn default_log_max_days() -> u64 {
+    7
+}
+
+fn default_log_max_files() -> usize {
+    20
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_alert_interval_secs() -> u64 {
+    60
+}
+
+fn default_alert_debounce() -> u32 {
+    3
+}
+
+fn default_alert_offline_secs() -> u64 {
+    600
+}
+
+fn default_alert_hashrate_percent() -> u64 {
+    50
+}
+
+fn default_alert_reject_percent() -> u64 {
+    10
+}
+
+fn default_alert_window_secs() -> u64 {
+    1800
 }
 
 impl Default for Settings {
@@ -32,6 +206,12 @@ impl Default for Settings {
         Self {
             log_level: 6,
             log_path: "".into(),
+            log_format: String::new(),
+            log_max_size: default_log_max_size(),
+            log_max_days: default_log_max_days(),
+            log_max_files: default_log_max_files(),
+            log_compress: true,
+            log_events: true,
             pool_ssl_address: Vec::new(),
             pool_tcp_address: Vec::new(),
             share_tcp_address: Vec::new(),
@@ -48,6 +228,53 @@ impl Default for Settings {
             share_alg: 0,
             key: "0000000000000000000000".into(),
             iv: "123456".into(),
+            tcp_allow: Vec::new(),
+            tcp_deny: Vec::new(),
+            ssl_allow: Vec::new(),
+            ssl_deny: Vec::new(),
+            encrypt_allow: Vec::new(),
+            encrypt_deny: Vec::new(),
+            ban_secs: 0,
+            ban_threshold: default_ban_threshold(),
+            auth_password: "".into(),
+            auth_tokens: Vec::new(),
+            auth_wallets: Vec::new(),
+            worker_aliases: Vec::new(),
+            worker_name_rules: Vec::new(),
+            tcp_proxy_protocol: false,
+            ssl_proxy_protocol: false,
+            encrypt_proxy_protocol: false,
+            proxy_protocol_from: default_proxy_protocol_from(),
+            pool_proxy_protocol: 0,
+            pool_upstream_proxy: String::new(),
+            share_upstream_proxy: String::new(),
+            develop_upstream_proxy: String::new(),
+            tcp_bind: Vec::new(),
+            ssl_bind: Vec::new(),
+            encrypt_bind: Vec::new(),
+            source_address: String::new(),
+            drain_timeout: default_drain_timeout(),
+            state_file: String::new(),
+            worker_expire_days: default_worker_expire_days(),
+            upgrade_timeout: default_upgrade_timeout(),
+            pid_file: String::new(),
+            record_path: String::new(),
+            record_allow: Vec::new(),
+            record_redact: false,
+            verify_shares: false,
+            verify_epochs: default_verify_epochs(),
+            stale_drop_blocks: 0,
+            alert_webhook: String::new(),
+            alert_command: String::new(),
+            alert_file: String::new(),
+            alert_interval_secs: default_alert_interval_secs(),
+            alert_debounce: default_alert_debounce(),
+            alert_offline_secs: default_alert_offline_secs(),
+            alert_hashrate_percent: default_alert_hashrate_percent(),
+            alert_reject_percent: default_alert_reject_percent(),
+            alert_window_secs: default_alert_window_secs(),
+            alert_pool: true,
+            admin_socket: String::new(),
         }
     }
 }
@@ -97,6 +324,26 @@ impl Settings {
             Err(_) => {}
         }
 
+        for key in [
+            "tcp_allow",
+            "tcp_deny",
+            "ssl_allow",
+            "ssl_deny",
+            "encrypt_allow",
+            "encrypt_deny",
+            "auth_wallets",
+            "tcp_bind",
+            "ssl_bind",
+            "encrypt_bind",
+            "record_allow",
+            "proxy_protocol_from",
+        ] {
+            if let Ok(list) = env::var(format!("PROXY_{}", key.to_uppercase())) {
+                let arr: Vec<&str> = list.split(',').collect();
+                s.set(key, arr)?;
+            }
+        }
+
         // // You may also programmatically change settings
         // s.set("database.url", "postgres://")?;
 
@@ -115,4 +362,34 @@ impl Settings {
 
         develop_fee + share_fee as f64
     }
+
+    // log_format 为 json 时输出 JSON 日志
+    pub fn log_json(&self) -> bool {
+        self.log_format.eq_ignore_ascii_case("json")
+    }
+
+    // 日志文件保留策略。log_max_size 单位为 MB
+    pub fn log_retention(&self) -> Retention {
+        Retention {
+            max_size: self.log_max_size * 1024 * 1024,
+            max_age: std::time::Duration::from_secs(self.log_max_days * 86400),
+            max_files: self.log_max_files,
+            compress: self.log_compress,
+        }
+    }
+
+    // 矿机状态文件。未配置时写入日志目录
+    pub fn state_file(&self) -> std::path::PathBuf {
+        if !self.state_file.is_empty() {
+            return self.state_file.clone().into();
+        }
+        std::path::Path::new(&self.log_path).join("workers.json")
+    }
+
+    // 平滑升级时旧进程停机前交接给新进程的矿机统计
+    pub fn handoff_file(&self) -> std::path::PathBuf {
+        let mut path = self.state_file().into_os_string();
+        path.push(".upgrade");
+        path.into()
+    }
 }
@@ -1,32 +1,212 @@
-pub fn init(app_name: &str, path: String, log_level: u32) -> anyhow::Result<()> {
-    let lavel = match log_level {
+use std::{
+    cell::RefCell,
+    future::Future,
+    net::SocketAddr,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+use serde_json::{json, Map, Value};
+
+use super::rotate::{Retention, RotatingFile};
+
+// 事件日志的 target。JSON 格式下输出为 event 字段
+pub mod event {
+    pub const LOGIN: &str = "event::login";
+    pub const SHARE: &str = "event::share";
+    pub const ACCEPT: &str = "event::accept";
+    pub const REJECT: &str = "event::reject";
+    pub const DISCONNECT: &str = "event::disconnect";
+    // 抽水汇总
+    pub const FEE: &str = "event::fee";
+}
+
+// 矿机会话的日志上下文。会话任务内的每行 JSON 日志都带上这些字段
+#[derive(Debug, Default, Clone)]
+struct SessionContext {
+    id: u64,
+    peer: String,
+    worker: String,
+    wallet: String,
+    pool: String,
+    reason: String,
+}
+
+tokio::task_local! {
+    static SESSION: RefCell<SessionContext>;
+}
+
+static SESSION_ID: AtomicU64 = AtomicU64::new(0);
+
+// 在会话上下文中运行 fut。每次调用分配新的会话 id
+pub fn session<F: Future>(peer: SocketAddr, fut: F) -> impl Future<Output = F::Output> {
+    let ctx = SessionContext {
+        id: SESSION_ID.fetch_add(1, Ordering::Relaxed) + 1,
+        peer: peer.to_string(),
+        ..Default::default()
+    };
+    SESSION.scope(RefCell::new(ctx), fut)
+}
+
+fn update(f: impl FnOnce(&mut SessionContext)) {
+    let _ = SESSION.try_with(|ctx| f(&mut ctx.borrow_mut()));
+}
+
+// 解析 PROXY protocol 后的真实地址
+pub fn set_peer(peer: &SocketAddr) {
+    update(|ctx| ctx.peer = peer.to_string());
+}
+
+pub fn set_worker(worker: &str, wallet: &str) {
+    update(|ctx| {
+        ctx.worker = worker.to_string();
+        ctx.wallet = wallet.to_string();
+    });
+}
+
+pub fn set_pool(pool: &str) {
+    update(|ctx| ctx.pool = pool.to_string());
+}
+
+// 会话断开原因。随断开事件输出
+pub fn set_reason(reason: &str) {
+    update(|ctx| ctx.reason = reason.to_string());
+}
+
+// 日志等级。可通过管理接口在运行时修改，事件日志不受影响
+pub struct Level(AtomicUsize);
+
+impl Level {
+    pub const fn new(level: log::LevelFilter) -> Self {
+        Self(AtomicUsize::new(level as usize))
+    }
+
+    pub fn get(&self) -> log::LevelFilter {
+        match self.0.load(Ordering::Relaxed) {
+            0 => log::LevelFilter::Off,
+            1 => log::LevelFilter::Error,
+            2 => log::LevelFilter::Warn,
+            3 => log::LevelFilter::Info,
+            4 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        }
+    }
+
+    pub fn set(&self, level: log::LevelFilter) {
+        self.0.store(level as usize, Ordering::Relaxed);
+    }
+}
+
+// 当前日志等级
+pub static LEVEL: Level = Level::new(log::LevelFilter::Info);
+
+pub fn level() -> log::LevelFilter {
+    LEVEL.get()
+}
+
+pub fn set_level(level: log::LevelFilter) {
+    LEVEL.set(level);
+}
+
+fn level_filter(log_level: u32) -> log::LevelFilter {
+    match log_level {
         4 => log::LevelFilter::Off,
         3 => log::LevelFilter::Error,
         2 => log::LevelFilter::Warn,
         1 => log::LevelFilter::Info,
         0 => log::LevelFilter::Debug,
         _ => log::LevelFilter::Info,
-    };
+    }
+}
+
+fn format_text(out: fern::FormatCallback, message: &std::fmt::Arguments, record: &log::Record) {
+    out.finish(format_args!(
+        "[{}] [{}:{}] [{}] {}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        record.file().unwrap_or_default(),
+        record.line().unwrap_or_default(),
+        record.level(),
+        message
+    ))
+}
+
+fn format_json(out: fern::FormatCallback, message: &std::fmt::Arguments, record: &log::Record) {
+    out.finish(format_args!("{}", json_line(message, record)))
+}
+
+// 一行 JSON 日志。空字段不输出
+fn json_line(message: &std::fmt::Arguments, record: &log::Record) -> Value {
+    let mut line = Map::new();
+    line.insert(
+        "ts".into(),
+        json!(chrono::Local::now()
+            .format("%Y-%m-%dT%H:%M:%S%.3f%:z")
+            .to_string()),
+    );
+    line.insert("level".into(), json!(record.level().as_str()));
+    if let Some(event) = record.target().strip_prefix("event::") {
+        line.insert("event".into(), json!(event));
+    } else {
+        line.insert("target".into(), json!(record.target()));
+    }
+    line.insert("msg".into(), json!(message.to_string()));
+    if let (Some(file), Some(no)) = (record.file(), record.line()) {
+        line.insert("file".into(), json!(format!("{}:{}", file, no)));
+    }
+
+    let _ = SESSION.try_with(|ctx| {
+        let ctx = ctx.borrow();
+        line.insert("session".into(), json!(ctx.id));
+        let fields = [
+            ("peer", &ctx.peer),
+            ("worker", &ctx.worker),
+            ("wallet", &ctx.wallet),
+            ("pool", &ctx.pool),
+        ];
+        for (key, value) in fields {
+            if !value.is_empty() {
+                line.insert(key.into(), json!(value));
+            }
+        }
+        if record.target() == event::DISCONNECT && !ctx.reason.is_empty() {
+            line.insert("reason".into(), json!(ctx.reason));
+        }
+    });
+    Value::Object(line)
+}
+
+type Format = fn(fern::FormatCallback, &std::fmt::Arguments, &log::Record);
+
+// json 为 true 时每行输出一个 JSON 对象。
+// path 不为空时同时写入按保留策略滚动的日志文件，events 为 true 时事件日志另外写入 事件.events.log
+pub fn init(
+    app_name: &str,
+    path: String,
+    log_level: u32,
+    json: bool,
+    retention: Retention,
+    events: bool,
+) -> anyhow::Result<()> {
+    set_level(level_filter(log_level));
+    let format: Format = if json { format_json } else { format_text };
     if path != "" {
-        let log = fern::DateBased::new(path, format!("{}.log.%Y-%m-%d.%H", app_name))
-            .utc_time()
-            .local_time();
-        let (lavel, logger) = fern::Dispatch::new()
-            .format(move |out, message, record| {
-                out.finish(format_args!(
-                    "[{}] [{}:{}] [{}] {}",
-                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                    record.file().unwrap(),
-                    record.line().unwrap(),
-                    record.level(),
-                    message
-                ))
-            })
-            .level(lavel)
-            .level_for("reqwest", log::LevelFilter::Off)
-            .chain(std::io::stdout())
-            .chain(log)
-            .into_log();
+        let log = RotatingFile::open(&path, &format!("{}.log", app_name), retention.clone())?;
+        let mut dispatch = fern::Dispatch::new().format(format).chain(
+            fern::Dispatch::new()
+                .filter(|md| md.level() <= level())
+                .level_for("reqwest", log::LevelFilter::Off)
+                .chain(std::io::stdout())
+                .chain(Box::new(log) as Box<dyn std::io::Write + Send>),
+        );
+        // 事件日志不受日志等级限制
+        if events {
+            let log = RotatingFile::open(&path, &format!("{}.events.log", app_name), retention)?;
+            dispatch = dispatch.chain(
+                fern::Dispatch::new()
+                    .filter(|md| md.target().starts_with("event::"))
+                    .chain(Box::new(log) as Box<dyn std::io::Write + Send>),
+            );
+        }
+        let (lavel, logger) = dispatch.into_log();
 
         let logger = sentry_log::SentryLogger::with_dest(logger).filter(|md| match md.level() {
             log::Level::Error => sentry_log::LogFilter::Event,
@@ -38,17 +218,8 @@ pub fn init(app_name: &str, path: String, log_level: u32) -> anyhow::Result<()>
         log::set_max_level(lavel);
     } else {
         let (lavel, logger) = fern::Dispatch::new()
-            .format(move |out, message, record| {
-                out.finish(format_args!(
-                    "[{}] [{}:{}] [{}] {}",
-                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                    record.file().unwrap(),
-                    record.line().unwrap(),
-                    record.level(),
-                    message
-                ))
-            })
-            .level(lavel)
+            .format(format)
+            .filter(|md| md.level() <= level())
             .level_for("reqwest", log::LevelFilter::Off)
             .chain(std::io::stdout())
             .into_log();
@@ -67,26 +238,10 @@ pub fn init(app_name: &str, path: String, log_level: u32) -> anyhow::Result<()>
 }
 
 pub fn init_client(log_level: u32) -> anyhow::Result<()> {
-    let lavel = match log_level {
-        4 => log::LevelFilter::Off,
-        3 => log::LevelFilter::Error,
-        2 => log::LevelFilter::Warn,
-        1 => log::LevelFilter::Info,
-        0 => log::LevelFilter::Debug,
-        _ => log::LevelFilter::Info,
-    };
+    let lavel = level_filter(log_level);
 
     let (lavel, logger) = fern::Dispatch::new()
-        .format(move |out, message, record| {
-            out.finish(format_args!(
-                "[{}] [{}:{}] [{}] {}",
-                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                record.file().unwrap(),
-                record.line().unwrap(),
-                record.level(),
-                message
-            ))
-        })
+        .format(format_text)
         .level(lavel)
         .level_for("reqwest", log::LevelFilter::Off)
         .chain(std::io::stdout())
@@ -103,3 +258,48 @@ pub fn init_client(log_level: u32) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_json_line() {
+    let record = log::Record::builder()
+        .level(log::Level::Info)
+        .target(event::DISCONNECT)
+        .build();
+    let line = json_line(&format_args!("断开 {}", 1), &record);
+    assert_eq!(line["event"], "disconnect");
+    assert_eq!(line["msg"], "断开 1");
+    assert!(line.get("session").is_none());
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let line = rt.block_on(session("1.2.3.4:5678".parse().unwrap(), async {
+        set_worker("rig1", "0x00");
+        set_pool("5.6.7.8:4444");
+        set_reason("pool_closed");
+        json_line(&format_args!("断开"), &record)
+    }));
+    assert!(line["session"].as_u64().unwrap() > 0);
+    assert_eq!(line["peer"], "1.2.3.4:5678");
+    assert_eq!(line["worker"], "rig1");
+    assert_eq!(line["wallet"], "0x00");
+    assert_eq!(line["pool"], "5.6.7.8:4444");
+    assert_eq!(line["reason"], "pool_closed");
+
+    let record = log::Record::builder()
+        .level(log::Level::Warn)
+        .target("proxy::client")
+        .build();
+    let line = json_line(&format_args!("x"), &record);
+    assert_eq!(line["target"], "proxy::client");
+    assert!(line.get("event").is_none());
+}
+
+#[test]
+fn test_level() {
+    use log::LevelFilter::*;
+    // 不修改全局日志等级，避免影响并行的测试
+    let l = Level::new(Info);
+    for filter in [Off, Error, Warn, Info, Debug, Trace] {
+        l.set(filter);
+        assert_eq!(l.get(), filter);
+    }
+}
